@@ -7,6 +7,10 @@ use clap::{Parser, Subcommand};
 #[command(version = "2.0.0")]
 #[command(about = "Game Archive Manager v2.0 - Version control for game saves like Git", long_about = None)]
 pub struct Cli {
+    /// Wait up to SECS seconds for another gam process to release the repository lock
+    #[arg(long, global = true, value_name = "SECS")]
+    pub wait: Option<u64>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -42,7 +46,7 @@ pub enum Commands {
     #[command(name = "activity")]
     Activity(ActivityArgs),
 
-    /// Compare snapshots
+    /// Compare snapshots, or the working directory with a snapshot
     #[command(name = "diff")]
     Diff(DiffArgs),
 
@@ -50,6 +54,34 @@ pub enum Commands {
     #[command(name = "gc")]
     Gc(GcArgs),
 
+    /// Consolidate loose objects into pack files
+    #[command(name = "pack")]
+    Pack(PackArgs),
+
+    /// Expand pack files back into loose objects
+    #[command(name = "unpack")]
+    Unpack(UnpackArgs),
+
+    /// Content index maintenance
+    #[command(name = "index")]
+    Index(IndexArgs),
+
+    /// Upgrade the repository to the current storage format
+    #[command(name = "upgrade")]
+    Upgrade(UpgradeArgs),
+
+    /// Rewrite the repository with a different content hash algorithm
+    #[command(name = "migrate-hash")]
+    MigrateHash(MigrateHashArgs),
+
+    /// Manage the encryption key of an encrypted repository
+    #[command(name = "key")]
+    Key(KeyArgs),
+
+    /// Show or relocate the object storage
+    #[command(name = "storage")]
+    Storage(StorageArgs),
+
     /// Ignore rules management
     #[command(name = "ignore")]
     Ignore(IgnoreArgs),
@@ -61,6 +93,10 @@ pub enum Commands {
     /// Diagnose issues
     #[command(name = "doctor")]
     Doctor(DoctorArgs),
+
+    /// Verify integrity of all objects, snapshots and references
+    #[command(name = "fsck")]
+    Fsck(FsckArgs),
 }
 
 /// init command arguments
@@ -73,6 +109,18 @@ pub struct InitArgs {
     /// Force reinitialize
     #[arg(long)]
     pub force: bool,
+
+    /// Encrypt snapshots and file contents (passphrase from GAM_PASSPHRASE or prompt)
+    #[arg(long)]
+    pub encrypt: bool,
+
+    /// Protect the encryption key with this key file instead of a passphrase (created if missing)
+    #[arg(long, value_name = "PATH", requires = "encrypt")]
+    pub key_file: Option<String>,
+
+    /// Keep objects and snapshots in this directory (e.g. on another disk) instead of .gam/objects
+    #[arg(long, value_name = "DIR")]
+    pub storage: Option<String>,
 }
 
 /// snapshot subcommand arguments
@@ -120,6 +168,10 @@ pub struct SaveArgs {
     /// Save to specified timeline
     #[arg(short, long)]
     pub timeline: Option<String>,
+
+    /// Re-hash every file instead of trusting the stat cache
+    #[arg(long)]
+    pub rehash: bool,
 }
 
 /// list command arguments
@@ -252,6 +304,10 @@ pub struct RestoreArgs {
     /// Force restore (skip confirmation)
     #[arg(long)]
     pub force: bool,
+
+    /// Re-hash the restored files after restoring and compare them with the snapshot
+    #[arg(long)]
+    pub verify: bool,
 }
 
 /// history command arguments
@@ -268,6 +324,21 @@ pub struct StatusArgs {
     /// Show detailed information
     #[arg(short, long)]
     pub verbose: bool,
+
+    /// Re-hash every file instead of trusting the stat cache
+    #[arg(long)]
+    pub rehash: bool,
+
+    /// Also pair deleted and added text files that are at least PERCENT similar as renames
+    #[arg(
+        short = 'M',
+        long,
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        default_missing_value = "50",
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    pub find_renames: Option<u8>,
 }
 
 /// activity command arguments
@@ -281,11 +352,34 @@ pub struct ActivityArgs {
 /// diff command arguments
 #[derive(Parser, Debug)]
 pub struct DiffArgs {
-    /// First snapshot ID
-    pub id1: String,
+    /// Snapshot ID to compare from (defaults to HEAD)
+    pub id1: Option<String>,
+
+    /// Snapshot ID to compare to (defaults to the working directory)
+    pub id2: Option<String>,
 
-    /// Second snapshot ID
-    pub id2: String,
+    /// Show line diffs of text files and key-level diffs of JSON/TOML/INI/XML files
+    #[arg(long)]
+    pub content: bool,
+
+    /// Show changed byte ranges with a side-by-side hex/ASCII dump (text files too unless --content)
+    #[arg(long)]
+    pub binary: bool,
+
+    /// Maximum number of listed ranges and hex dump lines per file in --binary mode
+    #[arg(long, value_name = "N", default_value_t = 64, requires = "binary")]
+    pub max_lines: usize,
+
+    /// Also pair deleted and added text files that are at least PERCENT similar as renames
+    #[arg(
+        short = 'M',
+        long,
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        default_missing_value = "50",
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    pub find_renames: Option<u8>,
 }
 
 /// gc command arguments
@@ -298,6 +392,103 @@ pub struct GcArgs {
     /// Preview mode (do not actually execute)
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Pack remaining loose objects after collection
+    #[arg(long)]
+    pub pack: bool,
+}
+
+/// pack command arguments
+#[derive(Parser, Debug)]
+pub struct PackArgs {}
+
+/// unpack command arguments
+#[derive(Parser, Debug)]
+pub struct UnpackArgs {}
+
+/// upgrade command arguments
+#[derive(Parser, Debug)]
+pub struct UpgradeArgs {
+    /// Show the pending migration steps without changing the repository
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// migrate-hash command arguments
+#[derive(Parser, Debug)]
+pub struct MigrateHashArgs {
+    /// Target hash algorithm
+    #[arg(value_parser = ["sha256", "blake3"])]
+    pub algorithm: String,
+}
+
+/// key subcommand arguments
+#[derive(Parser, Debug)]
+pub struct KeyArgs {
+    #[command(subcommand)]
+    pub command: KeyCommands,
+}
+
+/// key subcommands
+#[derive(Subcommand, Debug)]
+pub enum KeyCommands {
+    /// Change the passphrase or key file protecting the repository key
+    #[command(name = "change")]
+    Change(KeyChangeArgs),
+}
+
+/// key change command arguments
+#[derive(Parser, Debug)]
+pub struct KeyChangeArgs {
+    /// Protect the key with this key file instead of a new passphrase (created if missing)
+    #[arg(long, value_name = "PATH")]
+    pub key_file: Option<String>,
+}
+
+/// storage subcommand arguments
+#[derive(Parser, Debug)]
+pub struct StorageArgs {
+    #[command(subcommand)]
+    pub command: StorageCommands,
+}
+
+/// storage subcommands
+#[derive(Subcommand, Debug)]
+pub enum StorageCommands {
+    /// Show where objects and snapshots are stored
+    #[command(name = "show")]
+    Show,
+
+    /// Move all objects and snapshots to another directory
+    #[command(name = "move")]
+    Move(StorageMoveArgs),
+}
+
+/// storage move command arguments
+#[derive(Parser, Debug)]
+pub struct StorageMoveArgs {
+    /// Target directory (must be empty or not exist)
+    #[arg(required_unless_present = "local", conflicts_with = "local")]
+    pub path: Option<String>,
+
+    /// Move objects back into .gam/objects
+    #[arg(long)]
+    pub local: bool,
+}
+
+/// index subcommand arguments
+#[derive(Parser, Debug)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub command: IndexCommands,
+}
+
+/// index subcommands
+#[derive(Subcommand, Debug)]
+pub enum IndexCommands {
+    /// Recompute reference counts from all snapshot manifests
+    #[command(name = "rebuild")]
+    Rebuild,
 }
 
 /// ignore subcommand arguments
@@ -368,6 +559,14 @@ pub struct DoctorArgs {
     pub fix: bool,
 }
 
+/// fsck command arguments
+#[derive(Parser, Debug)]
+pub struct FsckArgs {
+    /// Only compare object sizes with the index instead of re-hashing
+    #[arg(long)]
+    pub quick: bool,
+}
+
 /// config command arguments
 #[derive(Parser, Debug)]
 pub struct ConfigArgs {
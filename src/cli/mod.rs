@@ -6,8 +6,9 @@ pub mod args;
 
 pub use args::{
     ActivityArgs, AddIgnoreArgs, CheckIgnoreArgs, Cli, Commands, CreateArgs, DeleteArgs,
-    DeleteTimelineArgs, DiffArgs, DoctorArgs, GcArgs, HistoryArgs, IgnoreArgs, IgnoreCommands,
-    IgnoreInitArgs, InfoArgs, InitArgs, ListArgs, RemoveIgnoreArgs, RenameArgs, RestoreArgs,
-    SaveArgs, SnapshotArgs, SnapshotCommands, StatusArgs, SwitchArgs, TimelineArgs,
-    TimelineCommands,
+    DeleteTimelineArgs, DiffArgs, DoctorArgs, FsckArgs, GcArgs, HistoryArgs, IgnoreArgs, IgnoreCommands,
+    IgnoreInitArgs, IndexArgs, IndexCommands, InfoArgs, InitArgs, KeyArgs, KeyChangeArgs, KeyCommands, ListArgs, MigrateHashArgs, PackArgs,
+    RemoveIgnoreArgs, RenameArgs, RestoreArgs, SaveArgs, SnapshotArgs, SnapshotCommands,
+    StatusArgs, StorageArgs, StorageCommands, StorageMoveArgs, SwitchArgs, TimelineArgs, TimelineCommands, UnpackArgs,
+    UpgradeArgs,
 };
@@ -33,6 +33,18 @@ pub enum ActivityAction {
     IgnoreRemove,
     /// GC 操作
     Gc,
+    /// 打包对象
+    Pack,
+    /// 解包对象
+    Unpack,
+    /// 升级仓库格式
+    Upgrade,
+    /// 迁移哈希算法
+    MigrateHash,
+    /// 更换仓库密钥的凭据
+    KeyChange,
+    /// 迁移对象存储位置
+    StorageMove,
     /// 未知操作
     Unknown(String),
 }
@@ -51,6 +63,12 @@ impl std::fmt::Display for ActivityAction {
             ActivityAction::IgnoreAdd => write!(f, "ignore add"),
             ActivityAction::IgnoreRemove => write!(f, "ignore remove"),
             ActivityAction::Gc => write!(f, "gc"),
+            ActivityAction::Pack => write!(f, "pack"),
+            ActivityAction::Unpack => write!(f, "unpack"),
+            ActivityAction::Upgrade => write!(f, "upgrade"),
+            ActivityAction::MigrateHash => write!(f, "migrate-hash"),
+            ActivityAction::KeyChange => write!(f, "key change"),
+            ActivityAction::StorageMove => write!(f, "storage move"),
             ActivityAction::Unknown(name) => write!(f, "{}", name),
         }
     }
@@ -173,6 +191,12 @@ fn parse_action(s: &str) -> ActivityAction {
         "ignore_add" => ActivityAction::IgnoreAdd,
         "ignore_remove" => ActivityAction::IgnoreRemove,
         "gc" => ActivityAction::Gc,
+        "pack" => ActivityAction::Pack,
+        "unpack" => ActivityAction::Unpack,
+        "upgrade" => ActivityAction::Upgrade,
+        "migrate-hash" => ActivityAction::MigrateHash,
+        "key change" => ActivityAction::KeyChange,
+        "storage move" => ActivityAction::StorageMove,
         _ => ActivityAction::Unknown(s.to_string()),
     }
 }
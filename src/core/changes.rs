@@ -0,0 +1,324 @@
+//! 变更比较模块
+//!
+//! 比较两组文件条目（两个快照，或工作目录与快照），得到新增、修改和删除的文件，
+//! 并识别重命名和复制。`status` 和 `diff` 都使用这里的结果
+
+use crate::core::types::FileEntry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+/// 参与相似度比较的文件大小上限
+const SIMILARITY_MAX_SIZE: u64 = 1024 * 1024;
+
+/// 相似度比较最多尝试的文件对数
+const SIMILARITY_MAX_PAIRS: usize = 10_000;
+
+/// 相似度比较时每一侧最多读取的文件数
+const SIMILARITY_MAX_FILES: usize = 1_000;
+
+/// 相似度比较时每一侧最多读取的总字节数
+const SIMILARITY_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// 重命名（或移动）的文件
+#[derive(Debug, Clone, Copy)]
+pub struct Rename<'a> {
+    /// 原路径的条目
+    pub old: &'a FileEntry,
+    /// 新路径的条目
+    pub new: &'a FileEntry,
+    /// 内容相似度（百分比，内容完全相同时为 100）
+    pub similarity: u8,
+}
+
+/// 两组文件条目之间的变更（各列表按路径排序）
+#[derive(Debug, Default)]
+pub struct ChangeSet<'a> {
+    /// 只存在于新条目中的文件
+    pub added: Vec<&'a FileEntry>,
+    /// 内容或类型不同的文件（旧条目，新条目）
+    pub modified: Vec<(&'a FileEntry, &'a FileEntry)>,
+    /// 只存在于旧条目中的文件
+    pub deleted: Vec<&'a FileEntry>,
+    /// 重命名的文件（从新增和删除中配对得到）
+    pub renamed: Vec<Rename<'a>>,
+    /// 复制得到的新文件（来源条目，新条目）
+    ///
+    /// 来源是旧条目中内容相同的任一文件，它在新条目中可能已被修改、重命名或删除
+    pub copied: Vec<(&'a FileEntry, &'a FileEntry)>,
+    /// 未变化的文件数
+    pub unchanged: usize,
+}
+
+impl<'a> ChangeSet<'a> {
+    /// 比较旧条目和新条目，并识别内容完全相同的重命名和复制
+    ///
+    /// 哈希、大小或条目类型不同的文件视为修改；只有修改时间或权限不同的文件视为未变化
+    pub fn compare(old: &'a [FileEntry], new: &'a [FileEntry]) -> Self {
+        let old_files: BTreeMap<&PathBuf, &FileEntry> = old.iter().map(|f| (&f.path, f)).collect();
+        let new_files: BTreeMap<&PathBuf, &FileEntry> = new.iter().map(|f| (&f.path, f)).collect();
+
+        let mut changes = ChangeSet::default();
+        for (path, new_file) in &new_files {
+            match old_files.get(path) {
+                Some(old_file) if Self::same_content(old_file, new_file) => changes.unchanged += 1,
+                Some(old_file) => changes.modified.push((old_file, new_file)),
+                None => changes.added.push(new_file),
+            }
+        }
+        changes.deleted = old_files
+            .iter()
+            .filter(|(path, _)| !new_files.contains_key(*path))
+            .map(|(_, file)| *file)
+            .collect();
+
+        changes.detect_exact(&old_files);
+        changes
+    }
+
+    /// 两个条目的内容是否相同
+    fn same_content(a: &FileEntry, b: &FileEntry) -> bool {
+        a.kind == b.kind && a.hash == b.hash && a.size == b.size
+    }
+
+    /// 是否参与重命名识别（非空的普通文件）
+    fn trackable(file: &FileEntry) -> bool {
+        file.kind.is_file() && file.size > 0
+    }
+
+    /// 按哈希识别重命名和复制
+    ///
+    /// 新增文件优先与哈希相同的已删除文件配对为重命名；没有可配对的已删除文件时，
+    /// 如果旧条目中有相同内容的文件，则视为从该文件复制
+    fn detect_exact(&mut self, old_files: &BTreeMap<&PathBuf, &'a FileEntry>) {
+        let mut deleted_by_hash: HashMap<&str, Vec<&'a FileEntry>> = HashMap::new();
+        for file in self.deleted.iter().rev().filter(|f| Self::trackable(f)) {
+            deleted_by_hash.entry(file.hash.as_str()).or_default().push(file);
+        }
+        let mut old_by_hash: HashMap<&str, &'a FileEntry> = HashMap::new();
+        for file in old_files.values().filter(|f| Self::trackable(f)) {
+            old_by_hash.entry(file.hash.as_str()).or_insert(file);
+        }
+
+        let mut added = Vec::new();
+        for file in std::mem::take(&mut self.added) {
+            if !Self::trackable(file) {
+                added.push(file);
+                continue;
+            }
+            if let Some(old) = deleted_by_hash
+                .get_mut(file.hash.as_str())
+                .and_then(|candidates| candidates.pop())
+            {
+                self.renamed.push(Rename {
+                    old,
+                    new: file,
+                    similarity: 100,
+                });
+            } else if let Some(old) = old_by_hash.get(file.hash.as_str()) {
+                self.copied.push((old, file));
+            } else {
+                added.push(file);
+            }
+        }
+        self.added = added;
+
+        let renamed: HashSet<&PathBuf> = self.renamed.iter().map(|r| &r.old.path).collect();
+        self.deleted.retain(|f| !renamed.contains(&f.path));
+    }
+
+    /// 在剩余的新增和删除文件中按内容相似度识别重命名
+    ///
+    /// 只比较不超过 1 MiB 的文本文件。相似度为两边共有的行数占总行数的比例，
+    /// 不低于 `threshold`（百分比）的文件对按相似度从高到低配对。
+    /// `load_old` 和 `load_new` 读取旧条目和新条目的内容，无法读取时返回 `None`。
+    ///
+    /// 候选文件过多（文件对数、单侧文件数或单侧总大小超过上限）时不做比较，返回 `false`
+    pub fn detect_similar<F, G>(&mut self, threshold: u8, mut load_old: F, mut load_new: G) -> bool
+    where
+        F: FnMut(&FileEntry) -> Option<Vec<u8>>,
+        G: FnMut(&FileEntry) -> Option<Vec<u8>>,
+    {
+        let candidate = |f: &&FileEntry| Self::trackable(f) && f.size <= SIMILARITY_MAX_SIZE;
+        let deleted: Vec<&'a FileEntry> = self.deleted.iter().copied().filter(candidate).collect();
+        let added: Vec<&'a FileEntry> = self.added.iter().copied().filter(candidate).collect();
+        if deleted.is_empty() || added.is_empty() {
+            return true;
+        }
+        let too_many = |files: &[&FileEntry]| {
+            files.len() > SIMILARITY_MAX_FILES
+                || files.iter().map(|f| f.size).sum::<u64>() > SIMILARITY_MAX_BYTES
+        };
+        if deleted.len() * added.len() > SIMILARITY_MAX_PAIRS || too_many(&deleted) || too_many(&added) {
+            return false;
+        }
+
+        let old_lines: Vec<Option<HashMap<Vec<u8>, usize>>> =
+            deleted.iter().map(|f| load_old(f).and_then(|d| Self::line_counts(&d))).collect();
+        let new_lines: Vec<Option<HashMap<Vec<u8>, usize>>> =
+            added.iter().map(|f| load_new(f).and_then(|d| Self::line_counts(&d))).collect();
+
+        let mut pairs = Vec::new();
+        for (i, old) in old_lines.iter().enumerate() {
+            for (j, new) in new_lines.iter().enumerate() {
+                if let (Some(old), Some(new)) = (old, new) {
+                    let score = Self::similarity(old, new);
+                    if score >= threshold {
+                        pairs.push((score, i, j));
+                    }
+                }
+            }
+        }
+        pairs.sort_by_key(|&(score, i, j)| (std::cmp::Reverse(score), i, j));
+
+        let mut used_old = vec![false; deleted.len()];
+        let mut used_new = vec![false; added.len()];
+        for (score, i, j) in pairs {
+            if used_old[i] || used_new[j] {
+                continue;
+            }
+            used_old[i] = true;
+            used_new[j] = true;
+            self.renamed.push(Rename {
+                old: deleted[i],
+                new: added[j],
+                similarity: score,
+            });
+        }
+
+        let renamed_old: HashSet<&PathBuf> = self.renamed.iter().map(|r| &r.old.path).collect();
+        let renamed_new: HashSet<&PathBuf> = self.renamed.iter().map(|r| &r.new.path).collect();
+        self.deleted.retain(|f| !renamed_old.contains(&f.path));
+        self.added.retain(|f| !renamed_new.contains(&f.path));
+        self.renamed.sort_by(|a, b| a.new.path.cmp(&b.new.path));
+        true
+    }
+
+    /// 统计每一行出现的次数，不是文本时返回 `None`
+    fn line_counts(data: &[u8]) -> Option<HashMap<Vec<u8>, usize>> {
+        if data.contains(&0) || std::str::from_utf8(data).is_err() {
+            return None;
+        }
+        let data = data.strip_suffix(b"\n").unwrap_or(data);
+        let mut counts = HashMap::new();
+        for line in data.split(|b| *b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            *counts.entry(line.to_vec()).or_insert(0) += 1;
+        }
+        Some(counts)
+    }
+
+    /// 共有行数占两边总行数的百分比
+    fn similarity(a: &HashMap<Vec<u8>, usize>, b: &HashMap<Vec<u8>, usize>) -> u8 {
+        let total: usize = a.values().sum::<usize>() + b.values().sum::<usize>();
+        let common: usize = a
+            .iter()
+            .map(|(line, count)| (*count).min(b.get(line).copied().unwrap_or(0)))
+            .sum();
+        if total == 0 {
+            return 0;
+        }
+        (common * 2 * 100 / total) as u8
+    }
+
+    /// 变更的文件数
+    pub fn len(&self) -> usize {
+        self.added.len() + self.modified.len() + self.deleted.len() + self.renamed.len() + self.copied.len()
+    }
+
+    /// 是否没有任何变更
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, hash: &str) -> FileEntry {
+        FileEntry::new(PathBuf::from(path), hash.to_string(), hash.len() as u64)
+    }
+
+    fn paths(files: &[&FileEntry]) -> Vec<String> {
+        files.iter().map(|f| f.path.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = vec![entry("a.sav", "aa"), entry("b.sav", "bb"), entry("c.sav", "cc")];
+        let mut touched = entry("a.sav", "aa");
+        touched.mtime = Some(1);
+        let new = vec![
+            entry("d.sav", "dd"),
+            touched,
+            entry("c.sav", "c2"),
+            FileEntry::dir(PathBuf::from("empty")),
+        ];
+
+        let changes = ChangeSet::compare(&old, &new);
+        assert_eq!(paths(&changes.added), vec!["d.sav", "empty"]);
+        assert_eq!(paths(&changes.deleted), vec!["b.sav"]);
+        assert_eq!(changes.modified.len(), 1);
+        assert_eq!(changes.modified[0].1.hash, "c2");
+        assert_eq!(changes.unchanged, 1);
+        assert_eq!(changes.len(), 4);
+        assert!(ChangeSet::compare(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_exact_renames_and_copies() {
+        let old = vec![entry("slot1/save.dat", "aa"), entry("keep.dat", "kk"), entry("gone.dat", "gg")];
+        let new = vec![
+            entry("slot2/save.dat", "aa"),
+            entry("keep.dat", "kk"),
+            entry("backup/keep.dat", "kk"),
+            entry("new.dat", "nn"),
+        ];
+
+        let changes = ChangeSet::compare(&old, &new);
+        assert_eq!(changes.renamed.len(), 1);
+        assert_eq!(changes.renamed[0].old.path, PathBuf::from("slot1/save.dat"));
+        assert_eq!(changes.renamed[0].new.path, PathBuf::from("slot2/save.dat"));
+        assert_eq!(changes.copied.len(), 1);
+        assert_eq!(changes.copied[0].0.path, PathBuf::from("keep.dat"));
+        assert_eq!(paths(&changes.added), vec!["new.dat"]);
+        assert_eq!(paths(&changes.deleted), vec!["gone.dat"]);
+        assert_eq!(changes.len(), 4);
+    }
+
+    #[test]
+    fn test_similar_renames() {
+        let old = vec![entry("old.txt", "o1"), entry("other.txt", "o2")];
+        let new = vec![entry("new.txt", "n1"), entry("unrelated.txt", "n2")];
+        let content = |f: &FileEntry| -> Option<Vec<u8>> {
+            let text: &[u8] = match f.path.to_str().unwrap() {
+                "old.txt" => b"a\nb\nc\nd\n",
+                "new.txt" => b"a\nb\nc\nx\n",
+                "other.txt" => b"1\n2\n",
+                _ => b"\x00binary",
+            };
+            Some(text.to_vec())
+        };
+
+        let mut changes = ChangeSet::compare(&old, &new);
+        assert!(changes.detect_similar(50, content, content));
+        assert_eq!(changes.renamed.len(), 1);
+        assert_eq!(changes.renamed[0].old.path, PathBuf::from("old.txt"));
+        assert_eq!(changes.renamed[0].similarity, 75);
+        assert_eq!(paths(&changes.added), vec!["unrelated.txt"]);
+        assert_eq!(paths(&changes.deleted), vec!["other.txt"]);
+    }
+
+    #[test]
+    fn test_similar_renames_skipped_when_too_many() {
+        // 文件对数未超过上限，但删除的文件数超过单侧上限
+        let old: Vec<FileEntry> = (0..=SIMILARITY_MAX_FILES)
+            .map(|i| entry(&format!("old{}.txt", i), &format!("o{}", i)))
+            .collect();
+        let new = vec![entry("new.txt", "n1")];
+
+        let mut changes = ChangeSet::compare(&old, &new);
+        assert!(!changes.detect_similar(50, |_| panic!("nothing is loaded"), |_| panic!("nothing is loaded")));
+        assert!(changes.renamed.is_empty());
+    }
+}
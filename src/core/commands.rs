@@ -3,14 +3,43 @@
 //! 实现所有核心命令的实际逻辑
 
 use crate::core::activity::{ActivityAction, ActivityEngine};
+use crate::core::changes::ChangeSet;
+use crate::core::crypto::{Credential, KeyStore, RepoKey};
 use crate::core::error::GamResult;
+use crate::core::format::{self, RepoFormat};
 use crate::core::ignore::IgnoreEngine;
-use crate::core::store::{ContentStore, SnapshotStore, TimelineManager};
+use crate::core::journal::{Journal, JournalEntry, JournalStage};
+use crate::core::lock::RepoLock;
+use crate::core::stat_cache::{FileStat, StatCache};
+use crate::core::store::location::{MARKER_FILE, MOVE_MARKER_FILE};
+use crate::core::store::content_store::ObjectCheck;
+use crate::core::store::{
+    ContentStore, SnapshotStore, StorageLocation, TimelineManager,
+};
 use crate::core::tag::TagStore;
-use crate::core::types::{FileEntry, Snapshot};
-use crate::ui::{print_error, print_info, print_success, Formatter};
-use crate::utils::{FileUtils, HashUtils};
-use std::path::PathBuf;
+use crate::core::textconv::TextConv;
+use crate::core::types::{
+    CompressionCodec, EntryKind, FileEntry, HashAlgorithm, Snapshot, StorageStrategy,
+};
+use crate::ui::{print_error, print_info, print_success, print_warning, Formatter};
+use crate::utils::{
+    DocumentFormat, FileUtils, HashUtils, HexDiff, KeyChange, StructuredDiff, TextDiff,
+};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 命令对仓库的访问方式，与命令持有的锁对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// 只读（共享锁）
+    Read,
+    /// 修改仓库（排他锁）
+    Write,
+}
 
 /// 仓库上下文
 pub struct Repository {
@@ -19,14 +48,50 @@ pub struct Repository {
     snapshot_store: SnapshotStore,
     content_store: ContentStore,
     timeline_manager: TimelineManager,
+    format: RepoFormat,
+    /// 对象存储位置
+    storage: StorageLocation,
+    /// 并行任务使用的线程池，大小由 `core.jobs` 决定
+    pool: rayon::ThreadPool,
 }
 
 impl Repository {
     /// 创建新的仓库实例
-    pub fn new(gam_dir: PathBuf, game_path: PathBuf) -> GamResult<Self> {
-        let snapshot_store = SnapshotStore::new(&gam_dir);
-        let content_store = ContentStore::new(gam_dir.join("objects").join("content"))?;
+    ///
+    /// 仓库使用了本程序不支持的格式时，以 [`Access::Write`] 打开直接失败，只读打开给出警告
+    pub fn new(gam_dir: PathBuf, game_path: PathBuf, access: Access) -> GamResult<Self> {
+        let format = load_format(&gam_dir)?;
+        match access {
+            Access::Write => format.check_writable()?,
+            Access::Read => {
+                if let Some(reason) = format.unsupported() {
+                    print_warning(&format!(
+                        "仓库使用了本程序不支持的格式 ({})，结果可能不完整",
+                        reason
+                    ));
+                }
+            }
+        }
+
+        let hash_algorithm = get_hash_algorithm(&gam_dir)?;
+        let key = load_key(&gam_dir)?;
+        let storage = open_storage(&gam_dir)?;
+        let mut snapshot_store = SnapshotStore::new(&storage)?;
+        snapshot_store.set_hash_algorithm(hash_algorithm);
+        snapshot_store.set_key(key.clone());
+        let mut content_store = ContentStore::new(&storage)?;
+        content_store.set_hash_algorithm(hash_algorithm);
+        content_store.set_key(key);
+        content_store.set_codec(get_compression_codec(&gam_dir)?);
+        content_store.set_chunk_threshold(get_chunk_threshold(&gam_dir)?);
+        content_store.set_delta_max_depth(get_delta_max_depth(&gam_dir)?);
         let timeline_manager = TimelineManager::new(&gam_dir);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(get_jobs(&gam_dir)?)
+            .build()
+            .map_err(|e| {
+                crate::core::error::GamError::Unknown(format!("failed to start worker threads: {}", e))
+            })?;
 
         Ok(Repository {
             gam_dir,
@@ -34,6 +99,9 @@ impl Repository {
             snapshot_store,
             content_store,
             timeline_manager,
+            format,
+            storage,
+            pool,
         })
     }
 
@@ -50,8 +118,35 @@ impl Repository {
     }
 }
 
+/// 按配置打开对象存储位置并检查其可用性
+///
+/// 配置了 `storage.path` 时使用该目录，否则使用 `.gam/objects`
+fn open_storage(gam_dir: &Path) -> GamResult<StorageLocation> {
+    let storage =
+        match read_config_value(gam_dir, "storage", "path")?.and_then(|v| v.as_str().map(PathBuf::from)) {
+            Some(path) => {
+                let id = read_config_value(gam_dir, "storage", "id")?
+                    .and_then(|v| v.as_str().map(String::from))
+                    .unwrap_or_default();
+                StorageLocation::directory(path, id)
+            }
+            None => StorageLocation::local(gam_dir),
+        };
+    storage.check()?;
+    Ok(storage)
+}
+
 /// 处理 init 命令
-pub fn handle_init(path: Option<String>, force: bool) -> GamResult<()> {
+///
+/// `encrypt` 时生成仓库主密钥，由 `key_file`（不存在时自动生成）或口令保护；
+/// `storage` 指定时对象保存在该目录而不是 `.gam/objects`
+pub fn handle_init(
+    path: Option<String>,
+    force: bool,
+    encrypt: bool,
+    key_file: Option<String>,
+    storage: Option<String>,
+) -> GamResult<()> {
     // First determine game_path from argument
     let game_path = if let Some(p) = path {
         PathBuf::from(p)
@@ -72,41 +167,123 @@ pub fn handle_init(path: Option<String>, force: bool) -> GamResult<()> {
     // gam_dir should be INSIDE game_path
     let gam_dir = game_path.join(".gam");
 
-    // 创建 .gam 目录结构
+    // 创建 .gam 目录结构（重新初始化时不能与其他 gam 进程并发）
     std::fs::create_dir_all(&gam_dir)?;
+    let _lock = RepoLock::exclusive(&gam_dir)?;
     std::fs::create_dir_all(&gam_dir.join("refs").join("timelines"))?;
-    std::fs::create_dir_all(&gam_dir.join("objects").join("snapshot"))?;
-    std::fs::create_dir_all(&gam_dir.join("objects").join("content"))?;
+
+    // 重新初始化时保留已有的对象存储位置；已有快照的仓库需要使用 gam storage move 迁移
+    let mut storage_config = match read_config_value(&gam_dir, "storage", "path")? {
+        Some(path) => Some((
+            path.as_str().unwrap_or_default().to_string(),
+            read_config_value(&gam_dir, "storage", "id")?
+                .and_then(|v| v.as_str().map(String::from))
+                .unwrap_or_default(),
+        )),
+        None => None,
+    };
+    let location = match &storage {
+        Some(path) => {
+            if !SnapshotStore::new(&open_storage(&gam_dir)?)?.ids()?.is_empty() {
+                return Err(crate::core::error::GamError::StorageUnavailable(
+                    "repository already has snapshots. Use 'gam storage move' to relocate them"
+                        .to_string(),
+                ));
+            }
+            let location = StorageLocation::create_directory(Path::new(path))?;
+            storage_config = Some((
+                location.objects_dir().to_string_lossy().to_string(),
+                location.repo_id().unwrap_or_default().to_string(),
+            ));
+            location
+        }
+        None => open_storage(&gam_dir)?,
+    };
+    location.init()?;
+
+    // 重新初始化已加密的仓库时保留密钥；已有快照的仓库不能再启用加密
+    let mut key_file_path = read_config_value(&gam_dir, "encryption", "key_file")?
+        .and_then(|v| v.as_str().map(PathBuf::from));
+    if encrypt {
+        if KeyStore::exists(&gam_dir) {
+            return Err(crate::core::error::GamError::Encryption(
+                "repository is already encrypted. Use 'gam key change' to change its credentials"
+                    .to_string(),
+            ));
+        }
+        if !SnapshotStore::new(&location)?.ids()?.is_empty() {
+            return Err(crate::core::error::GamError::Encryption(
+                "cannot enable encryption for a repository that already has snapshots".to_string(),
+            ));
+        }
+
+        let credential = match &key_file {
+            Some(path) => {
+                let (credential, path) = open_or_create_key_file(Path::new(path))?;
+                key_file_path = Some(path);
+                credential
+            }
+            None => read_new_passphrase("GAM_PASSPHRASE")?,
+        };
+        KeyStore::wrap(&RepoKey::generate(), &credential)?.save(&gam_dir)?;
+    }
+    let encrypted = KeyStore::exists(&gam_dir);
 
     // 创建配置
-    let config_content = format!(
+    let mut config_content = format!(
         r#"[core]
 game_path = "{}"
 default_timeline = "main"
 use_gamignore = true
+# 内容寻址使用的哈希算法 (sha256, blake3)，已有快照后请使用 gam migrate-hash 修改
+hash_algorithm = "sha256"
+# 保存和恢复时的并行任务数，0 表示使用全部 CPU 核心
+jobs = 0
 
 [storage]
 strategy = "deduplication"
+# strategy = "compression" 时新对象使用的压缩算法 (zstd, gzip)
+compression = "zstd"
 "#,
         game_path.to_string_lossy().replace('\\', "/")
     );
+    if let Some((path, id)) = &storage_config {
+        config_content.push_str(&format!(
+            "# 对象存储目录（为空时使用 .gam/objects），请使用 gam storage move 修改\npath = \"{}\"\nid = \"{}\"\n",
+            path.replace('\\', "/"),
+            id
+        ));
+    }
+    if let Some(path) = key_file_path.filter(|_| encrypted) {
+        config_content.push_str(&format!(
+            "\n[encryption]\nkey_file = \"{}\"\n",
+            path.to_string_lossy().replace('\\', "/")
+        ));
+    }
 
-    std::fs::write(gam_dir.join("config"), config_content)?;
+    FileUtils::atomic_write(&gam_dir.join("config"), config_content.as_bytes())?;
+    let mut repo_format = RepoFormat::current();
+    if encrypted {
+        repo_format.require(format::FEATURE_ENCRYPTION);
+    }
+    repo_format.save(&gam_dir)?;
 
     // 创建 HEAD
-    std::fs::write(gam_dir.join("HEAD"), "ref: refs/timelines/main\n")?;
+    FileUtils::atomic_write(&gam_dir.join("HEAD"), b"ref: refs/timelines/main\n")?;
 
     // 创建默认时间线
-    std::fs::write(gam_dir.join("refs").join("timelines").join("main"), "")?;
+    FileUtils::atomic_write(&gam_dir.join("refs").join("timelines").join("main"), b"")?;
 
     // 记录活动
     let engine = ActivityEngine::new(&gam_dir);
     engine.log(ActivityAction::Init, None, None, None)?;
 
     print_success(&format!(
-        "初始化完成！\n  游戏存档目录: {}\n  GAM 仓库: {}",
+        "初始化完成！\n  游戏存档目录: {}\n  GAM 仓库: {}\n  对象存储: {}{}",
         game_path.to_string_lossy(),
-        gam_dir.to_string_lossy()
+        gam_dir.to_string_lossy(),
+        location.describe(),
+        if encrypted { "\n  加密: 已启用" } else { "" }
     ));
 
     Ok(())
@@ -117,8 +294,10 @@ pub fn handle_snapshot_save(
     gam_dir: &PathBuf,
     message: Option<String>,
     timeline: Option<String>,
+    rehash: bool,
 ) -> GamResult<()> {
-    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 确定时间线
     let timeline_name = match timeline {
@@ -157,23 +336,74 @@ pub fn handle_snapshot_save(
         return Ok(());
     }
 
-    // 存储文件内容
-    let mut stored_files = Vec::new();
-    let mut total_size = 0u64;
+    // 上一次保存中途中断时必须先恢复，避免覆盖预写日志
+    let journal = Journal::new(gam_dir);
+    if let Some(pending) = journal.pending()? {
+        return Err(crate::core::error::GamError::IncompleteOperation(
+            pending.operation.to_string(),
+        ));
+    }
+
+    let parent = match &parent_snapshot {
+        Some(id) => Some(repo.snapshot_store.get(id)?),
+        None => None,
+    };
 
-    for file in &files {
-        // 计算文件哈希并存储
-        let hash = HashUtils::hash_file(file)?;
-        repo.content_store.store_with_hash(file, &hash)?;
+    // 手动修改了哈希算法时，新旧快照的哈希无法比较，也无法去重
+    let hash_algorithm = repo.content_store.hash_algorithm();
+    if let Some(parent) = &parent
+        && parent.hash_algorithm != hash_algorithm
+    {
+        return Err(crate::core::error::GamError::InvalidConfig(format!(
+            "core.hash_algorithm is {} but existing snapshots use {}. Run 'gam migrate-hash {}' instead of editing the config",
+            hash_algorithm, parent.hash_algorithm, hash_algorithm
+        )));
+    }
 
-        let size = std::fs::metadata(file)?.len();
-        let relative_path = FileUtils::relative_to(file, &repo.game_path)
-            .unwrap_or_else(|| PathBuf::from(file.file_name().unwrap()));
+    // 计算文件哈希，状态未变化的文件使用缓存的哈希
+    let mut stat_cache = StatCache::load(gam_dir, hash_algorithm);
+    let mut stored_files = repo.pool.install(|| {
+        hash_game_files(&files, &repo.game_path, &repo.content_store, &mut stat_cache, rehash)
+    })?;
+    let total_size: u64 = stored_files.iter().map(|f| f.size).sum();
+
+    // 与父快照内容完全相同时不创建新快照
+    if let Some(parent) = &parent
+        && Snapshot::same_manifest(&parent.files, &stored_files)
+    {
+        stat_cache.save(gam_dir)?;
+        print_info(&format!(
+            "存档与当前快照 {} 完全相同，无需保存",
+            Formatter::short_hash(&parent.id)
+        ));
+        return Ok(());
+    }
 
-        stored_files.push(FileEntry::new(relative_path, hash, size));
-        total_size += size;
+    // 父快照中的文件，用作增量存储的基础
+    let parent_files: HashMap<PathBuf, FileEntry> = parent
+        .map(|p| p.files.into_iter().map(|f| (f.path.clone(), f)).collect())
+        .unwrap_or_default();
+
+    // 不支持 BLAKE3 的程序无法校验新写入的对象
+    if hash_algorithm == HashAlgorithm::Blake3
+        && repo.format.version >= format::CURRENT_VERSION
+        && repo.format.require(format::FEATURE_BLAKE3)
+    {
+        repo.format.save(gam_dir)?;
     }
 
+    // 写入预写日志，之后的步骤中断时可由 doctor --fix 回滚或完成
+    let mut journal_entry =
+        JournalEntry::snapshot_save(&timeline_name, parent_snapshot.as_deref());
+    journal.record(&journal_entry)?;
+    let mut guard = JournalGuard::new(gam_dir, &journal_entry);
+
+    // 并行存储文件内容，索引在全部完成后写入一次
+    let content_store = &mut repo.content_store;
+    repo.pool.install(|| {
+        content_store.store_entries(&files, &mut stored_files, &parent_files)
+    })?;
+
     // 创建快照
     let snapshot = repo.snapshot_store.create(
         &stored_files,
@@ -181,12 +411,19 @@ pub fn handle_snapshot_save(
         parent_snapshot.as_deref(),
         &snapshot_name,
         None,
-        &repo.game_path,
+        repo.content_store.codec(),
     )?;
 
+    journal_entry.stage = JournalStage::Committing;
+    journal_entry.snapshot_id = Some(snapshot.id.clone());
+    journal.record(&journal_entry)?;
+    guard.disarm();
+
     // 更新时间线 HEAD
     repo.timeline_manager
         .update_head(&timeline_name, &snapshot.id)?;
+    journal.finish()?;
+    stat_cache.save(gam_dir)?;
 
     // 记录活动
     let engine = ActivityEngine::new(gam_dir);
@@ -209,10 +446,104 @@ pub fn handle_snapshot_save(
     Ok(())
 }
 
-/// 扫描游戏存档目录，获取所有文件
-fn scan_game_directory(game_path: &PathBuf, gam_dir: &PathBuf) -> GamResult<Vec<PathBuf>> {
-    let mut files = Vec::new();
+/// 快照保存进入提交阶段前的预写日志守卫
+///
+/// 未解除时被释放（写入对象或快照文件出错提前返回）说明没有任何修改被提交，
+/// 回滚已增加的引用计数并清除日志，避免之后的保存都要求先运行 doctor --fix。
+/// panic 时保留日志，与进程崩溃一样交给 doctor --fix 处理
+struct JournalGuard<'a> {
+    gam_dir: &'a PathBuf,
+    entry: JournalEntry,
+    armed: bool,
+}
+
+impl<'a> JournalGuard<'a> {
+    fn new(gam_dir: &'a PathBuf, entry: &JournalEntry) -> Self {
+        JournalGuard {
+            gam_dir,
+            entry: entry.clone(),
+            armed: true,
+        }
+    }
+
+    /// 已进入提交阶段，之后的失败保留日志
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for JournalGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed
+            && !std::thread::panicking()
+            && let Err(e) = recover_journal(self.gam_dir, &self.entry)
+        {
+            print_warning(&format!(
+                "回滚未完成的快照保存失败，请运行 'gam doctor --fix': {}",
+                e
+            ));
+        }
+    }
+}
+
+/// 计算文件的哈希和大小，生成尚未存储的文件条目
+///
+/// 大小、修改时间和 inode 与 `cache` 中记录一致的文件直接使用缓存的哈希，`rehash` 时全部重新计算。
+/// 完成后 `cache` 只包含本次扫描到的文件
+fn hash_game_files(
+    files: &[PathBuf],
+    game_path: &Path,
+    content_store: &ContentStore,
+    cache: &mut StatCache,
+    rehash: bool,
+) -> GamResult<Vec<FileEntry>> {
+    let algorithm = cache.algorithm();
+    let hashed: Vec<(FileEntry, Option<(String, FileStat)>)> = files
+        .par_iter()
+        .map(|file| {
+            let metadata = std::fs::symlink_metadata(file)?;
+            let stat = FileStat::from_metadata(&metadata);
+            let relative_path = FileUtils::relative_to(file, game_path)
+                .unwrap_or_else(|| PathBuf::from(file.file_name().unwrap()));
+
+            let (mut entry, cached) = if metadata.is_dir() {
+                (FileEntry::dir(relative_path), None)
+            } else if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(file)?;
+                let hash = content_store.hash_symlink_with(&target, algorithm);
+                (FileEntry::symlink(relative_path, target, hash), None)
+            } else {
+                let key = relative_path.to_string_lossy().replace('\\', "/");
+                let hash = match cache.lookup(&key, &stat) {
+                    Some(hash) if !rehash => hash.to_string(),
+                    _ => content_store.hash_file_with(file, algorithm)?,
+                };
+                let entry = FileEntry::new(relative_path, hash, stat.size);
+                (entry, Some((key, stat.clone())))
+            };
+
+            entry.mtime = Some(stat.mtime);
+            if entry.kind != EntryKind::Symlink {
+                entry.mode = FileUtils::unix_mode(&metadata);
+            }
+            Ok((entry, cached))
+        })
+        .collect::<GamResult<_>>()?;
 
+    let mut fresh = StatCache::new(algorithm);
+    let mut entries = Vec::with_capacity(hashed.len());
+    for (entry, cached) in hashed {
+        if let Some((key, stat)) = cached {
+            fresh.insert(key, stat, entry.hash.clone());
+        }
+        entries.push(entry);
+    }
+    *cache = fresh;
+    Ok(entries)
+}
+
+/// 扫描游戏存档目录，获取所有文件、符号链接和空目录
+fn scan_game_directory(game_path: &PathBuf, gam_dir: &PathBuf) -> GamResult<Vec<PathBuf>> {
     // 检查是否启用 .gamignore
     let use_gamignore = check_use_gamignore(gam_dir)?;
 
@@ -223,29 +554,12 @@ fn scan_game_directory(game_path: &PathBuf, gam_dir: &PathBuf) -> GamResult<Vec<
         IgnoreEngine::new(Vec::new())
     };
 
-    // 扫描目录
-    for entry in std::fs::read_dir(game_path)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_file() {
-            let relative_path = FileUtils::relative_to(&path, game_path)
-                .unwrap_or_else(|| PathBuf::from(path.file_name().unwrap()));
-
-            if !ignore_engine.is_ignored(&relative_path, false) {
-                files.push(path);
-            }
-        } else if path.is_dir() && !is_hidden_dir(&path) {
-            // 递归扫描子目录
-            let sub_files = scan_directory_recursive(&path, game_path, &ignore_engine)?;
-            files.extend(sub_files);
-        }
-    }
-
-    Ok(files)
+    scan_directory_recursive(game_path, game_path, &ignore_engine)
 }
 
 /// 递归扫描目录
+///
+/// 符号链接不会被跟随，而是作为链接本身记录；完全为空的目录作为空目录记录
 fn scan_directory_recursive(
     dir: &PathBuf,
     base_path: &PathBuf,
@@ -256,19 +570,24 @@ fn scan_directory_recursive(
     for entry in std::fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
+        let file_type = entry.file_type()?;
 
         let relative_path = FileUtils::relative_to(&path, base_path)
             .unwrap_or_else(|| PathBuf::from(path.file_name().unwrap()));
 
-        if path.is_file() {
-            if !ignore_engine.is_ignored(&relative_path, false) {
-                files.push(path);
-            }
-        } else if path.is_dir() && !is_hidden_dir(&path) {
-            if !ignore_engine.is_ignored(&relative_path, true) {
+        if file_type.is_dir() {
+            if !is_hidden_dir(&path) && !ignore_engine.is_ignored(&relative_path, true) {
                 let sub_files = scan_directory_recursive(&path, base_path, ignore_engine)?;
-                files.extend(sub_files);
+                if sub_files.is_empty() && FileUtils::is_dir_empty(&path) {
+                    files.push(path);
+                } else {
+                    files.extend(sub_files);
+                }
             }
+        } else if (file_type.is_file() || file_type.is_symlink())
+            && !ignore_engine.is_ignored(&relative_path, false)
+        {
+            files.push(path);
         }
     }
 
@@ -317,6 +636,319 @@ fn load_ignore_engine(gam_dir: &PathBuf) -> GamResult<IgnoreEngine> {
     Ok(IgnoreEngine::new(patterns))
 }
 
+/// 读取配置项
+fn read_config_value(gam_dir: &Path, section: &str, key: &str) -> GamResult<Option<toml::Value>> {
+    Ok(read_config_section(gam_dir, section)?.and_then(|s| s.get(key).cloned()))
+}
+
+/// 读取配置文件中的整个段
+fn read_config_section(gam_dir: &Path, section: &str) -> GamResult<Option<toml::Value>> {
+    let config_path = gam_dir.join("config");
+    if !config_path.exists() {
+        return Ok(None);
+    }
+
+    let config_content = std::fs::read_to_string(&config_path)?;
+    let config: toml::Value = config_content
+        .parse()
+        .map_err(|e: toml::de::Error| crate::core::error::GamError::TomlParse(e.to_string()))?;
+
+    Ok(config.get(section).cloned())
+}
+
+/// 获取内容寻址使用的哈希算法
+fn get_hash_algorithm(gam_dir: &Path) -> GamResult<HashAlgorithm> {
+    match read_config_value(gam_dir, "core", "hash_algorithm")? {
+        Some(value) => value.try_into().map_err(|e: toml::de::Error| {
+            crate::core::error::GamError::InvalidConfig(format!("core.hash_algorithm: {}", e))
+        }),
+        None => Ok(HashAlgorithm::default()),
+    }
+}
+
+/// 获取新对象使用的压缩算法
+///
+/// 仅在 `storage.strategy = "compression"` 时生效，算法由 `storage.compression` 指定（默认 zstd）
+fn get_compression_codec(gam_dir: &Path) -> GamResult<CompressionCodec> {
+    let strategy: StorageStrategy = match read_config_value(gam_dir, "storage", "strategy")? {
+        Some(value) => value.try_into().map_err(|e: toml::de::Error| {
+            crate::core::error::GamError::InvalidConfig(format!("storage.strategy: {}", e))
+        })?,
+        None => StorageStrategy::default(),
+    };
+
+    if strategy != StorageStrategy::Compression {
+        return Ok(CompressionCodec::None);
+    }
+
+    match read_config_value(gam_dir, "storage", "compression")? {
+        Some(value) => value.try_into().map_err(|e: toml::de::Error| {
+            crate::core::error::GamError::InvalidConfig(format!("storage.compression: {}", e))
+        }),
+        None => Ok(CompressionCodec::Zstd),
+    }
+}
+
+/// 读取仓库格式描述
+///
+/// 没有 `.gam/format` 的旧仓库按配置文件中的 `core.format_version` 推断（未记录时为 1）
+fn load_format(gam_dir: &Path) -> GamResult<RepoFormat> {
+    if let Some(format) = RepoFormat::read(gam_dir)? {
+        return Ok(format);
+    }
+
+    match read_config_value(gam_dir, "core", "format_version")? {
+        Some(toml::Value::Integer(v)) if v > 0 => Ok(RepoFormat::legacy(v as u32)),
+        Some(other) => Err(crate::core::error::GamError::InvalidConfig(format!(
+            "core.format_version: {}",
+            other
+        ))),
+        None => Ok(RepoFormat::legacy(1)),
+    }
+}
+
+/// 在配置文件中写入 `section.key = value`（`value` 为 TOML 字面量），保留其他内容和注释
+fn set_config_value(gam_dir: &Path, section: &str, key: &str, value: &str) -> GamResult<()> {
+    let config_path = gam_dir.join("config");
+    let content = std::fs::read_to_string(&config_path)?;
+    let new_line = format!("{} = {}", key, value);
+    let header = format!("[{}]", section);
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_section = false;
+    let mut written = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            // 离开目标段时仍未找到则插入到段末尾（段间空行之前）
+            if in_section && !written {
+                let end = lines.len() - lines.iter().rev().take_while(|l| l.trim().is_empty()).count();
+                lines.insert(end, new_line.clone());
+                written = true;
+            }
+            in_section = trimmed == header;
+        }
+        if in_section
+            && !written
+            && trimmed.split('=').next().map(str::trim) == Some(key)
+            && trimmed.contains('=')
+        {
+            lines.push(new_line.clone());
+            written = true;
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if !written {
+        if !in_section {
+            lines.push(header);
+        }
+        lines.push(new_line);
+    }
+
+    let mut new_content = lines.join("\n");
+    new_content.push('\n');
+    FileUtils::atomic_write(&config_path, new_content.as_bytes())?;
+    Ok(())
+}
+
+/// 从配置文件中删除 `section.key`，保留其他内容和注释
+fn remove_config_value(gam_dir: &Path, section: &str, key: &str) -> GamResult<()> {
+    let config_path = gam_dir.join("config");
+    let content = std::fs::read_to_string(&config_path)?;
+    let header = format!("[{}]", section);
+
+    let mut in_section = false;
+    let lines: Vec<&str> = content
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                in_section = trimmed == header;
+            }
+            !(in_section
+                && trimmed.contains('=')
+                && trimmed.split('=').next().map(str::trim) == Some(key))
+        })
+        .collect();
+
+    let mut new_content = lines.join("\n");
+    new_content.push('\n');
+    FileUtils::atomic_write(&config_path, new_content.as_bytes())?;
+    Ok(())
+}
+
+/// 获取并行任务数
+///
+/// `core.jobs` 未设置或为 0 时使用 CPU 核心数
+fn get_jobs(gam_dir: &Path) -> GamResult<usize> {
+    match read_config_value(gam_dir, "core", "jobs")? {
+        Some(toml::Value::Integer(n)) if n > 0 => Ok(n as usize),
+        Some(toml::Value::Integer(0)) | None => Ok(std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)),
+        Some(other) => Err(crate::core::error::GamError::InvalidConfig(format!(
+            "core.jobs: {}",
+            other
+        ))),
+    }
+}
+
+/// 默认分块阈值（16 MiB）
+const DEFAULT_CHUNK_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// 获取分块存储阈值
+///
+/// `storage.chunking = true` 时，不小于 `storage.chunk_threshold` 字节的文件按内容分块存储
+fn get_chunk_threshold(gam_dir: &Path) -> GamResult<Option<u64>> {
+    let enabled = read_config_value(gam_dir, "storage", "chunking")?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    match read_config_value(gam_dir, "storage", "chunk_threshold")? {
+        Some(value) => value
+            .as_integer()
+            .filter(|t| *t >= 0)
+            .map(|t| Some(t as u64))
+            .ok_or_else(|| {
+                crate::core::error::GamError::InvalidConfig(
+                    "storage.chunk_threshold must be a non-negative integer".to_string(),
+                )
+            }),
+        None => Ok(Some(DEFAULT_CHUNK_THRESHOLD)),
+    }
+}
+
+/// 默认增量链最大深度
+const DEFAULT_DELTA_MAX_DEPTH: usize = 8;
+
+/// 获取增量链最大深度
+///
+/// `storage.delta = true` 时，同一路径的新版本以相对父快照对象的增量存储，
+/// 链深度不超过 `storage.delta_max_depth`
+fn get_delta_max_depth(gam_dir: &Path) -> GamResult<Option<usize>> {
+    let enabled = read_config_value(gam_dir, "storage", "delta")?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    match read_config_value(gam_dir, "storage", "delta_max_depth")? {
+        Some(value) => value
+            .as_integer()
+            .filter(|d| *d > 0)
+            .map(|d| Some(d as usize))
+            .ok_or_else(|| {
+                crate::core::error::GamError::InvalidConfig(
+                    "storage.delta_max_depth must be a positive integer".to_string(),
+                )
+            }),
+        None => Ok(Some(DEFAULT_DELTA_MAX_DEPTH)),
+    }
+}
+
+/// 本进程已解锁的仓库密钥，同一命令多次打开仓库时不再重复询问口令
+static UNLOCKED_KEY: Lazy<Mutex<Option<(PathBuf, RepoKey)>>> = Lazy::new(|| Mutex::new(None));
+
+/// 加载仓库密钥（未加密的仓库返回空）
+fn load_key(gam_dir: &Path) -> GamResult<Option<RepoKey>> {
+    if !KeyStore::exists(gam_dir) {
+        return Ok(None);
+    }
+
+    let mut unlocked = UNLOCKED_KEY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((dir, key)) = unlocked.as_ref()
+        && dir == gam_dir
+    {
+        return Ok(Some(key.clone()));
+    }
+
+    let store = KeyStore::read(gam_dir)?;
+    let key = store.unwrap(&read_credential(gam_dir, &store)?)?;
+    *unlocked = Some((gam_dir.to_path_buf(), key.clone()));
+    Ok(Some(key))
+}
+
+/// 获取解锁仓库的凭据
+///
+/// 口令依次从环境变量 `GAM_PASSPHRASE` 和终端输入获取；
+/// 密钥文件依次从环境变量 `GAM_KEY_FILE` 和配置 `encryption.key_file` 获取
+fn read_credential(gam_dir: &Path, store: &KeyStore) -> GamResult<Credential> {
+    if store.uses_passphrase() {
+        if let Ok(passphrase) = std::env::var("GAM_PASSPHRASE") {
+            return Ok(Credential::passphrase(passphrase));
+        }
+        return Ok(Credential::passphrase(prompt_passphrase("请输入仓库口令: ")?));
+    }
+
+    let path = match std::env::var_os("GAM_KEY_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => read_config_value(gam_dir, "encryption", "key_file")?
+            .and_then(|v| v.as_str().map(PathBuf::from))
+            .ok_or_else(|| {
+                crate::core::error::GamError::Encryption(
+                    "repository is protected by a key file. Set GAM_KEY_FILE or encryption.key_file"
+                        .to_string(),
+                )
+            })?,
+    };
+    Credential::read_key_file(&path)
+}
+
+/// 获取新口令：优先使用环境变量 `env`，否则在终端输入两次确认
+fn read_new_passphrase(env: &str) -> GamResult<Credential> {
+    let passphrase = match std::env::var(env) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let first = prompt_passphrase("请输入新口令: ")?;
+            let second = prompt_passphrase("请再次输入新口令: ")?;
+            if first != second {
+                return Err(crate::core::error::GamError::Encryption(
+                    "passphrases do not match".to_string(),
+                ));
+            }
+            first
+        }
+    };
+    if passphrase.is_empty() {
+        return Err(crate::core::error::GamError::Encryption(
+            "passphrase must not be empty".to_string(),
+        ));
+    }
+    Ok(Credential::passphrase(passphrase))
+}
+
+/// 在终端输入口令（不回显）
+fn prompt_passphrase(prompt: &str) -> GamResult<String> {
+    rpassword::prompt_password(prompt).map_err(|e| {
+        crate::core::error::GamError::Encryption(format!(
+            "cannot read passphrase from terminal ({}). Set GAM_PASSPHRASE instead",
+            e
+        ))
+    })
+}
+
+/// 打开密钥文件，不存在时生成新的随机密钥文件，返回凭据和密钥文件的绝对路径
+fn open_or_create_key_file(path: &Path) -> GamResult<(Credential, PathBuf)> {
+    let credential = if path.exists() {
+        Credential::read_key_file(path)?
+    } else {
+        let credential = Credential::create_key_file(path)?;
+        print_warning(&format!(
+            "已生成密钥文件 {}，丢失该文件将无法读取仓库，请妥善备份",
+            path.display()
+        ));
+        credential
+    };
+    Ok((credential, std::fs::canonicalize(path)?))
+}
+
 /// 获取游戏路径
 fn get_game_path(gam_dir: &PathBuf) -> GamResult<PathBuf> {
     let config_path = gam_dir.join("config");
@@ -353,7 +985,8 @@ pub fn handle_snapshot_list(
     all: bool,
     timeline: Option<String>,
 ) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
     let snapshot_store = &repo.snapshot_store;
 
     // 先确定要显示的时间线名称
@@ -363,11 +996,11 @@ pub fn handle_snapshot_list(
     };
 
     let snapshots = if let Some(tl) = &timeline {
-        snapshot_store.list_by_timeline(tl)?
+        snapshot_store.summaries_on_timeline(tl)?
     } else if all {
-        snapshot_store.list_all()?
+        snapshot_store.summaries()?
     } else {
-        snapshot_store.list_by_timeline(&timeline_name)?
+        snapshot_store.summaries_on_timeline(&timeline_name)?
     };
 
     if snapshots.is_empty() {
@@ -383,7 +1016,8 @@ pub fn handle_snapshot_list(
 
 /// 处理 snapshot info 命令
 pub fn handle_snapshot_info(gam_dir: &PathBuf, id: &str) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
     let snapshot_store = &repo.snapshot_store;
 
     // 尝试按前缀查找
@@ -403,7 +1037,8 @@ pub fn handle_snapshot_info(gam_dir: &PathBuf, id: &str) -> GamResult<()> {
 
 /// 处理 snapshot tag 命令 - 为快照添加标签
 pub fn handle_snapshot_tag(gam_dir: &PathBuf, id: &str, tag_name: &str) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 验证标签名称
     if tag_name.is_empty() || tag_name.contains('/') || tag_name.contains('\\') {
@@ -445,7 +1080,8 @@ pub fn handle_snapshot_tag(gam_dir: &PathBuf, id: &str, tag_name: &str) -> GamRe
 
 /// 处理 snapshot delete 命令
 pub fn handle_snapshot_delete(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()> {
-    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
     let snapshot_store = &mut repo.snapshot_store;
 
     // 查找快照
@@ -492,8 +1128,9 @@ pub fn handle_snapshot_delete(gam_dir: &PathBuf, id: &str, force: bool) -> GamRe
                 }
             }
 
-            // 删除快照文件
+            // 删除快照文件，并释放其对内容对象的引用
             snapshot_store.delete(&snap.id)?;
+            repo.content_store.release(&snap.files)?;
 
             // 记录活动
             let engine = ActivityEngine::new(gam_dir);
@@ -524,7 +1161,8 @@ pub fn handle_timeline_create(
     name: &str,
     from: Option<String>,
 ) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 检查时间线是否已存在
     if repo.timeline_manager.exists(name) {
@@ -574,7 +1212,8 @@ pub fn handle_timeline_create(
 
 /// 处理 timeline list 命令
 pub fn handle_timeline_list(gam_dir: &PathBuf) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
 
     let timelines = repo.timeline_manager.list()?;
     let current = repo.current_timeline()?;
@@ -592,7 +1231,8 @@ pub fn handle_timeline_list(gam_dir: &PathBuf) -> GamResult<()> {
 
 /// 处理 timeline switch 命令
 pub fn handle_timeline_switch(gam_dir: &PathBuf, target: &str) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 检查是否是时间线名称
     if repo.timeline_manager.exists(target) {
@@ -620,7 +1260,7 @@ pub fn handle_timeline_switch(gam_dir: &PathBuf, target: &str) -> GamResult<()>
         Some(snap) => {
             // 分离 HEAD 状态
             let head_file = gam_dir.join("HEAD");
-            std::fs::write(head_file, &snap.id)?;
+            FileUtils::atomic_write(&head_file, snap.id.as_bytes())?;
             print_success(&format!(
                 "HEAD 现在指向快照 {} ({})",
                 Formatter::short_hash(&snap.id),
@@ -636,10 +1276,15 @@ pub fn handle_timeline_switch(gam_dir: &PathBuf, target: &str) -> GamResult<()>
 
 /// 处理 timeline rename 命令
 pub fn handle_timeline_rename(gam_dir: &PathBuf, old_name: &str, new_name: &str) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 验证新名称
-    if new_name.is_empty() || new_name.contains('/') || new_name.contains('\\') {
+    if new_name.is_empty()
+        || new_name.contains('/')
+        || new_name.contains('\\')
+        || FileUtils::is_temp_file(Path::new(new_name))
+    {
         return Err(crate::core::error::GamError::InvalidTimelineName(
             new_name.to_string(),
         ));
@@ -681,7 +1326,8 @@ pub fn handle_timeline_rename(gam_dir: &PathBuf, old_name: &str, new_name: &str)
 
 /// 处理 timeline current 命令
 pub fn handle_timeline_current(gam_dir: &PathBuf) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
 
     match repo.current_timeline()? {
         Some(name) => {
@@ -697,7 +1343,8 @@ pub fn handle_timeline_current(gam_dir: &PathBuf) -> GamResult<()> {
 
 /// 处理 timeline delete 命令
 pub fn handle_timeline_delete(gam_dir: &PathBuf, name: &str, force: bool) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     if !repo.timeline_manager.exists(name) {
         return Err(crate::core::error::GamError::TimelineNotFound(
@@ -727,8 +1374,9 @@ pub fn handle_timeline_delete(gam_dir: &PathBuf, name: &str, force: bool) -> Gam
 }
 
 /// 处理 restore 命令
-pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool, verify: bool) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
     let snapshot_store = &repo.snapshot_store;
 
     // 查找快照
@@ -736,6 +1384,13 @@ pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()>
 
     match snapshot {
         Some(snap) => {
+            // 文件清单被改动过的快照不能信任
+            if snap.verify_content_hash() == Some(false) {
+                return Err(crate::core::error::GamError::ManifestMismatch(
+                    Formatter::short_hash(&snap.id),
+                ));
+            }
+
             // 确认操作
             if !force {
                 println!("此操作将覆盖当前存档。");
@@ -757,27 +1412,22 @@ pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()>
                 }
             }
 
-            // 恢复文件
-            let mut restored_count = 0usize;
-            for file_entry in &snap.files {
-                let source_path = repo.content_store.get(&file_entry.hash)?;
-
-                let target_path = repo.game_path.join(&file_entry.path);
+            // 并行恢复文件
+            repo.pool.install(|| {
+                snap.files.par_iter().try_for_each(|file_entry| {
+                    let target_path = repo.game_path.join(&file_entry.path);
 
-                // 确保父目录存在
-                if let Some(parent) = target_path.parent() {
-                    std::fs::create_dir_all(parent)?;
-                }
+                    create_restore_parents(&repo.game_path, &target_path)?;
 
-                // 复制文件
-                std::fs::copy(&source_path, &target_path)?;
-                restored_count += 1;
-            }
+                    restore_entry(&repo.content_store, file_entry, &target_path)
+                })
+            })?;
+            let restored_count = snap.files.len();
 
             // 更新 HEAD（如果是分离状态）
             let head_content = std::fs::read_to_string(gam_dir.join("HEAD"))?;
             if !head_content.starts_with("ref:") {
-                std::fs::write(gam_dir.join("HEAD"), &snap.id)?;
+                FileUtils::atomic_write(&gam_dir.join("HEAD"), snap.id.as_bytes())?;
             }
 
             // 记录活动
@@ -796,6 +1446,11 @@ pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()>
                 restored_count
             ));
 
+            // 恢复完成后重新计算恢复的条目的内容哈希
+            if verify {
+                verify_restored(&repo, &snap)?;
+            }
+
             Ok(())
         }
         None => Err(crate::core::error::GamError::SnapshotNotFound(
@@ -804,16 +1459,146 @@ pub fn handle_restore(gam_dir: &PathBuf, id: &str, force: bool) -> GamResult<()>
     }
 }
 
+/// 恢复单个条目及其权限和修改时间
+fn restore_entry(content_store: &ContentStore, entry: &FileEntry, target: &Path) -> GamResult<()> {
+    // 目标位置原有的文件或链接先删除：只读文件无法覆盖，符号链接会把内容写到链接目标
+    if let Ok(existing) = std::fs::symlink_metadata(target)
+        && !existing.is_dir()
+    {
+        std::fs::remove_file(target)?;
+    }
+
+    match entry.kind {
+        EntryKind::File => {
+            // 写出文件（压缩对象在此解压，分块对象在此拼接）
+            let mut reader = content_store.open_entry(entry)?;
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(target)?);
+            let written = std::io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            if let Some(expected) = reader.size_hint().filter(|&size| size != written) {
+                // 对象内容与记录的大小不符，说明对象已损坏
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: expected {} bytes, restored {}",
+                        entry.path.display(),
+                        expected,
+                        written
+                    ),
+                )
+                .into());
+            }
+        }
+        EntryKind::Dir => std::fs::create_dir_all(target)?,
+        EntryKind::Symlink => {
+            let link_target = entry.symlink_target.as_deref().ok_or_else(|| {
+                crate::core::error::GamError::RestoreConflict(format!(
+                    "symlink {} has no target",
+                    entry.path.display()
+                ))
+            })?;
+            FileUtils::create_symlink(link_target, target)?;
+        }
+    }
+
+    // 旧快照没有记录元数据时保持恢复后的默认值。
+    // 先设置时间再设置权限：设置时间需要打开文件，没有所有者读权限的模式（如 0o200）会导致打开失败
+    if let Some(mtime) = entry.mtime {
+        FileUtils::set_mtime(target, mtime)?;
+    }
+    if let Some(mode) = entry.mode {
+        FileUtils::set_unix_mode(target, mode)?;
+    }
+    Ok(())
+}
+
+/// 创建恢复目标在存档目录中缺失的父目录
+///
+/// 已有的父路径必须是真实目录：跟随其中的符号链接会把文件写到存档目录之外
+fn create_restore_parents(game_path: &Path, target: &Path) -> GamResult<()> {
+    let Some(relative) = target.parent().and_then(|p| p.strip_prefix(game_path).ok()) else {
+        return Ok(());
+    };
+
+    let mut current = game_path.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(crate::core::error::GamError::RestoreConflict(format!(
+                    "{} is not a directory",
+                    current.display()
+                )));
+            }
+            // 其他线程可能同时创建同一目录，create_dir_all 对已存在的目录不报错
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => std::fs::create_dir_all(&current)?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// 重新计算恢复的条目的内容根哈希，确认与快照完全一致
+///
+/// 恢复不会删除快照之外的文件，因此只校验快照中的条目，存档目录中多余的文件不影响结果
+fn verify_restored(repo: &Repository, snap: &Snapshot) -> GamResult<()> {
+    let files: Vec<PathBuf> = snap
+        .files
+        .iter()
+        .map(|entry| repo.game_path.join(&entry.path))
+        .filter(|path| std::fs::symlink_metadata(path).is_ok())
+        .collect();
+    let mut cache = StatCache::new(snap.hash_algorithm);
+    let actual = repo.pool.install(|| {
+        hash_game_files(&files, &repo.game_path, &repo.content_store, &mut cache, true)
+    })?;
+    if Snapshot::compute_content_hash(&actual) == Snapshot::compute_content_hash(&snap.files) {
+        print_success(&format!(
+            "校验通过: 恢复的文件与快照内容哈希一致 ({})",
+            Formatter::short_hash(&Snapshot::compute_content_hash(&actual))
+        ));
+        return Ok(());
+    }
+
+    // 列出不一致的文件
+    let expected: HashMap<&PathBuf, &FileEntry> = snap.files.iter().map(|f| (&f.path, f)).collect();
+    let mut lines = Vec::new();
+    for entry in &actual {
+        if let Some(e) = expected.get(&entry.path)
+            && (e.hash != entry.hash || e.size != entry.size)
+        {
+            lines.push(format!("  不同 {}", entry.path.display()));
+        }
+    }
+    let present: HashSet<&PathBuf> = actual.iter().map(|f| &f.path).collect();
+    for entry in &snap.files {
+        if !present.contains(&entry.path) {
+            lines.push(format!("  缺失 {}", entry.path.display()));
+        }
+    }
+    lines.sort();
+
+    print_error("校验失败: 恢复的文件与快照内容不一致");
+    for line in &lines {
+        println!("{}", line);
+    }
+    Err(crate::core::error::GamError::VerificationFailed(
+        Formatter::short_hash(&snap.id),
+    ))
+}
+
 /// 处理 history 命令
 pub fn handle_history(gam_dir: &PathBuf, all: bool) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
     let snapshot_store = &repo.snapshot_store;
 
     let snapshots = if all {
-        snapshot_store.list_all()?
+        snapshot_store.summaries()?
     } else {
         let current_timeline = repo.get_timeline_name()?;
-        snapshot_store.list_by_timeline(&current_timeline)?
+        snapshot_store.summaries_on_timeline(&current_timeline)?
     };
 
     if snapshots.is_empty() {
@@ -835,182 +1620,491 @@ pub fn handle_history(gam_dir: &PathBuf, all: bool) -> GamResult<()> {
     Ok(())
 }
 
-/// 处理 diff 命令 - 比较两个快照
-pub fn handle_diff(gam_dir: &PathBuf, id1: &str, id2: &str) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
-    let snapshot_store = &repo.snapshot_store;
-
-    // 查找两个快照
-    let snapshot1 = snapshot_store.get_by_prefix(id1)?;
-    let snapshot2 = snapshot_store.get_by_prefix(id2)?;
-
-    let snap1 = match snapshot1 {
-        Some(s) => s,
-        None => {
-            return Err(crate::core::error::GamError::SnapshotNotFound(
-                id1.to_string(),
-            ))
-        }
+/// 处理 diff 命令
+///
+/// - 两个 ID：比较两个快照
+/// - 一个 ID：比较工作目录与该快照
+/// - 不指定：比较工作目录与 HEAD 快照
+///
+/// `content` 时还会输出修改过的文本文件的逐行差异，以及 JSON、TOML、INI、XML 文件的键级差异；
+/// `binary` 为 `Some(行数上限)` 时输出变化的字节区间和十六进制对照；
+/// `find_renames` 时再按相似度识别重命名的文本文件（内容相同的重命名和复制总会识别）
+pub fn handle_diff(
+    gam_dir: &PathBuf,
+    id1: Option<&str>,
+    id2: Option<&str>,
+    content: bool,
+    binary: Option<usize>,
+    find_renames: Option<u8>,
+) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
+
+    let Some(DiffSides {
+        old: snap1,
+        new: snap2,
+        working,
+    }) = diff_sides(&repo, id1, id2)?
+    else {
+        print_info("尚无快照，请先使用 gam snapshot save 保存");
+        return Ok(());
     };
 
-    let snap2 = match snapshot2 {
-        Some(s) => s,
+    match &snap2 {
+        Some(snap2) => {
+            println!("比较快照");
+            println!(
+                "  快照1: {} ({})",
+                Formatter::short_hash(&snap1.id),
+                snap1.name
+            );
+            println!(
+                "  快照2: {} ({})",
+                Formatter::short_hash(&snap2.id),
+                snap2.name
+            );
+        }
         None => {
-            return Err(crate::core::error::GamError::SnapshotNotFound(
-                id2.to_string(),
-            ))
+            println!("比较工作目录");
+            println!(
+                "  快照:     {} ({})",
+                Formatter::short_hash(&snap1.id),
+                snap1.name
+            );
+            println!("  工作目录: {}", repo.game_path.display());
         }
-    };
+    }
+    println!();
+
+    let new_files = snap2.as_ref().map_or(&working, |s| &s.files);
+    let mut changes = ChangeSet::compare(&snap1.files, new_files);
+    if let Some(threshold) = find_renames {
+        detect_similar_renames(&repo, &mut changes, threshold, snap2.is_none());
+    }
+    print_diff_report(&changes);
+    if content || binary.is_some() {
+        let textconv = TextConv::from_config(read_config_section(gam_dir, "textconv")?.as_ref())?;
+        print_content_diff(&repo, &changes, snap2.is_none(), content, binary, &textconv)?;
+    }
+
+    // 计算大小变化
+    let old_total: u64 = snap1.files.iter().map(|f| f.size).sum();
+    let new_total: u64 = new_files.iter().map(|f| f.size).sum();
+    let size_change = new_total as i64 - old_total as i64;
 
-    // 比较快照
-    println!("比较快照");
-    println!(
-        "  快照1: {} ({})",
-        Formatter::short_hash(&snap1.id),
-        snap1.name
-    );
     println!(
-        "  快照2: {} ({})",
-        Formatter::short_hash(&snap2.id),
-        snap2.name
+        "总大小变化: {} → {} ({}{})",
+        Formatter::format_size(old_total),
+        Formatter::format_size(new_total),
+        if size_change >= 0 { "+" } else { "-" },
+        Formatter::format_size(size_change.unsigned_abs())
     );
-    println!();
 
-    // 构建文件哈希映射
-    let files1: std::collections::HashMap<String, &FileEntry> = snap1
-        .files
-        .iter()
-        .map(|f| (f.path.to_string_lossy().to_string(), f))
-        .collect();
+    Ok(())
+}
 
-    let files2: std::collections::HashMap<String, &FileEntry> = snap2
-        .files
+/// 差异比较的两侧
+struct DiffSides {
+    /// 旧快照
+    old: Snapshot,
+    /// 新快照（为空表示与工作目录比较）
+    new: Option<Snapshot>,
+    /// 工作目录中的文件条目（与新快照比较时为空）
+    working: Vec<FileEntry>,
+}
+
+/// 确定差异比较的两侧，未指定新快照时与工作目录比较
+///
+/// 未指定旧快照时使用 HEAD，尚无 HEAD 时返回空
+fn diff_sides(
+    repo: &Repository,
+    id1: Option<&str>,
+    id2: Option<&str>,
+) -> GamResult<Option<DiffSides>> {
+    let snap1 = match id1 {
+        Some(id) => find_snapshot(repo, id)?,
+        None => match head_snapshot(repo)? {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        },
+    };
+
+    let (new, working) = match id2 {
+        Some(id) => (Some(find_snapshot(repo, id)?), Vec::new()),
+        None => (None, scan_working_tree(repo, Some(&snap1), false)?),
+    };
+    Ok(Some(DiffSides {
+        old: snap1,
+        new,
+        working,
+    }))
+}
+
+/// 超过该大小的文件不比较内容
+const CONTENT_DIFF_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// 输出修改过的文件（包括内容有变化的重命名文件）的内容差异
+///
+/// `text` 时文本文件输出统一差异，可识别的格式再输出键级差异，配置了文本转换命令的文件先转换再比较；
+/// `binary` 为 `Some(行数上限)` 时其余文件输出变化的字节区间和十六进制对照，否则只输出大小和哈希。
+/// `working` 时新版本从工作目录读取
+fn print_content_diff(
+    repo: &Repository,
+    changes: &ChangeSet,
+    working: bool,
+    text: bool,
+    binary: Option<usize>,
+    textconv: &TextConv,
+) -> GamResult<()> {
+    let modified: Vec<(&FileEntry, &FileEntry)> = changes
+        .modified
         .iter()
-        .map(|f| (f.path.to_string_lossy().to_string(), f))
+        .copied()
+        .chain(
+            changes
+                .renamed
+                .iter()
+                .filter(|r| r.old.hash != r.new.hash)
+                .map(|r| (r.old, r.new)),
+        )
+        .filter(|(old, new)| old.kind.is_file() && new.kind.is_file())
         .collect();
+    if modified.is_empty() {
+        return Ok(());
+    }
 
-    // 找出添加、删除、修改的文件
-    let mut added = Vec::new();
-    let mut deleted = Vec::new();
-    let mut modified = Vec::new();
-    let mut unchanged = Vec::new();
-
-    // 检查添加和修改的文件
-    for (path, file2) in &files2 {
-        match files1.get(path) {
-            Some(file1) => {
-                if file1.hash != file2.hash {
-                    modified.push((path.clone(), file1.size, file2.size));
-                } else {
-                    unchanged.push(path.clone());
+    println!("内容差异:");
+    println!();
+    for (old, new) in modified {
+        let old_path = old.path.to_string_lossy().replace('\\', "/");
+        let path = new.path.to_string_lossy().replace('\\', "/");
+        let too_large = old.size.max(new.size) > CONTENT_DIFF_LIMIT;
+
+        // 过大的文件跳过转换，与普通文本比较使用同样的大小上限
+        if text
+            && !too_large
+            && let Some(command) = textconv.driver_for(&new.path)
+        {
+            match (
+                convert_entry_content(repo, command, old, false),
+                convert_entry_content(repo, command, new, working),
+            ) {
+                (Ok(old_text), Ok(new_text)) => {
+                    println!("{}: 使用 textconv \"{}\" 转换后比较", path, command);
+                    print_text_diff(
+                        &old_path,
+                        &path,
+                        &String::from_utf8_lossy(&old_text),
+                        &String::from_utf8_lossy(&new_text),
+                    );
+                    println!();
+                    continue;
                 }
+                (Err(e), _) | (_, Err(e)) => print_warning(&format!("{}: {}", path, e)),
             }
-            None => {
-                added.push((path.clone(), file2.size));
+        }
+
+        if text && !too_large {
+            let old_data = read_entry_content(repo, old, false)?;
+            let new_data = read_entry_content(repo, new, working)?;
+            if let (Some(old_text), Some(new_text)) =
+                (TextDiff::as_text(&old_data), TextDiff::as_text(&new_data))
+            {
+                print_text_diff(&old_path, &path, old_text, new_text);
+                println!();
+                continue;
             }
         }
+
+        if let Some(max_rows) = binary {
+            let diff = HexDiff::compare(
+                open_entry_content(repo, old, false)?,
+                open_entry_content(repo, new, working)?,
+                max_rows,
+            )?;
+            println!("--- a/{}", old_path);
+            println!("+++ b/{}", path);
+            print!("{}", diff.render(max_rows));
+        } else if too_large {
+            println!(
+                "{}: 文件过大 ({} → {})，跳过内容比较",
+                path,
+                Formatter::format_size(old.size),
+                Formatter::format_size(new.size)
+            );
+        } else {
+            println!(
+                "二进制文件 {} 不同 ({} → {}, {} → {})",
+                path,
+                Formatter::format_size(old.size),
+                Formatter::format_size(new.size),
+                Formatter::short_hash(&old.hash),
+                Formatter::short_hash(&new.hash)
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// 输出文本文件的统一差异，可识别的格式再输出键级差异
+fn print_text_diff(old_path: &str, path: &str, old_text: &str, new_text: &str) {
+    let diff = TextDiff::unified(
+        old_text,
+        new_text,
+        &format!("a/{}", old_path),
+        &format!("b/{}", path),
+        3,
+    );
+    if diff.is_empty() {
+        println!("{}: 只有换行符不同", path);
+    } else {
+        print!("{}", diff);
     }
 
-    // 检查删除的文件
-    for (path, _) in &files1 {
-        if !files2.contains_key(path) {
-            deleted.push(path.clone());
+    if let Some(format) = DocumentFormat::from_path(Path::new(path))
+        && let Some(key_changes) = StructuredDiff::diff(format, old_text, new_text)
+        && !key_changes.is_empty()
+    {
+        println!("结构差异 ({}):", path);
+        for change in key_changes {
+            match change {
+                KeyChange::Added(key, value) => println!("  + {}: {}", key, value),
+                KeyChange::Removed(key, value) => println!("  - {}: {}", key, value),
+                KeyChange::Changed(key, old, new) => println!("  {}: {} → {}", key, old, new),
+            }
         }
     }
+}
+
+/// 打开文件条目的内容，`working` 时从工作目录读取，否则从内容存储读取
+fn open_entry_content<'a>(
+    repo: &'a Repository,
+    entry: &'a FileEntry,
+    working: bool,
+) -> GamResult<Box<dyn Read + 'a>> {
+    if working {
+        return Ok(Box::new(std::fs::File::open(repo.game_path.join(&entry.path))?));
+    }
+    Ok(Box::new(repo.content_store.open_entry(entry)?))
+}
 
-    // 输出结果
+/// 使用文本转换命令转换文件条目的内容
+///
+/// 工作目录中的文件直接传给命令，内容存储中的对象先写入仓库目录下的临时文件
+fn convert_entry_content(
+    repo: &Repository,
+    command: &str,
+    entry: &FileEntry,
+    working: bool,
+) -> GamResult<Vec<u8>> {
+    if working {
+        return TextConv::convert_file(command, &repo.game_path.join(&entry.path));
+    }
+    TextConv::convert_reader(command, &mut repo.content_store.open_entry(entry)?, &entry.path)
+}
+
+/// 在剩余的新增和删除文件中按内容相似度识别重命名，`working` 时新文件从工作目录读取
+fn detect_similar_renames(repo: &Repository, changes: &mut ChangeSet, threshold: u8, working: bool) {
+    let detected = changes.detect_similar(
+        threshold,
+        |entry| read_entry_content(repo, entry, false).ok(),
+        |entry| read_entry_content(repo, entry, working).ok(),
+    );
+    if !detected {
+        print_info("新增和删除的文件过多，已跳过按相似度识别重命名");
+    }
+}
+
+/// 读取文件条目的全部内容
+fn read_entry_content(repo: &Repository, entry: &FileEntry, working: bool) -> GamResult<Vec<u8>> {
+    let mut data = Vec::new();
+    open_entry_content(repo, entry, working)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// 按 ID 前缀查找快照，找不到时返回 `SnapshotNotFound`
+fn find_snapshot(repo: &Repository, id: &str) -> GamResult<Snapshot> {
+    repo.snapshot_store
+        .get_by_prefix(id)?
+        .ok_or_else(|| crate::core::error::GamError::SnapshotNotFound(id.to_string()))
+}
+
+/// 输出新增、删除、修改、重命名和复制的文件列表
+fn print_diff_report(changes: &ChangeSet) {
     println!("文件变更统计:");
-    println!("  新增: {} 个", added.len());
-    println!("  删除: {} 个", deleted.len());
-    println!("  修改: {} 个", modified.len());
-    println!("  未变: {} 个", unchanged.len());
+    println!("  新增: {} 个", changes.added.len());
+    println!("  删除: {} 个", changes.deleted.len());
+    println!("  修改: {} 个", changes.modified.len());
+    println!("  重命名: {} 个", changes.renamed.len());
+    println!("  复制: {} 个", changes.copied.len());
+    println!("  未变: {} 个", changes.unchanged);
     println!();
 
     // 显示新增的文件
-    if !added.is_empty() {
+    if !changes.added.is_empty() {
         println!("新增文件:");
-        for (path, size) in &added {
-            println!("  + {} ({})", path, Formatter::format_size(*size));
+        for file in &changes.added {
+            println!(
+                "  + {} ({})",
+                file.path.to_string_lossy(),
+                Formatter::format_size(file.size)
+            );
         }
         println!();
     }
 
     // 显示删除的文件
-    if !deleted.is_empty() {
+    if !changes.deleted.is_empty() {
         println!("删除文件:");
-        for path in &deleted {
-            println!("  - {}", path);
+        for file in &changes.deleted {
+            println!("  - {}", file.path.to_string_lossy());
         }
         println!();
     }
 
     // 显示修改的文件
-    if !modified.is_empty() {
+    if !changes.modified.is_empty() {
         println!("修改文件:");
-        for (path, old_size, new_size) in &modified {
-            let change = if *new_size > *old_size {
-                format!("+{}", Formatter::format_size(new_size - old_size))
-            } else if *new_size < *old_size {
-                format!("-{}", Formatter::format_size(old_size - new_size))
+        for (old, new) in &changes.modified {
+            let change = if new.size > old.size {
+                format!("+{}", Formatter::format_size(new.size - old.size))
+            } else if new.size < old.size {
+                format!("-{}", Formatter::format_size(old.size - new.size))
             } else {
                 "无变化".to_string()
             };
             println!(
                 "  ~ {} ({} → {}) [{}]",
-                path,
-                Formatter::format_size(*old_size),
-                Formatter::format_size(*new_size),
+                new.path.to_string_lossy(),
+                Formatter::format_size(old.size),
+                Formatter::format_size(new.size),
                 change
             );
         }
         println!();
     }
 
-    // 计算大小变化
-    let old_total: u64 = snap1.files.iter().map(|f| f.size).sum();
-    let new_total: u64 = snap2.files.iter().map(|f| f.size).sum();
-    let size_change = new_total as i64 - old_total as i64;
-
-    println!(
-        "总大小变化: {} → {} ({}{})",
-        Formatter::format_size(old_total),
-        Formatter::format_size(new_total),
-        if size_change >= 0 { "+" } else { "" },
-        Formatter::format_size(size_change.abs() as u64)
-    );
+    // 显示重命名的文件
+    if !changes.renamed.is_empty() {
+        println!("重命名文件:");
+        for rename in &changes.renamed {
+            let similarity = if rename.similarity < 100 {
+                format!(" [相似度 {}%]", rename.similarity)
+            } else {
+                String::new()
+            };
+            println!(
+                "  > {} → {}{}",
+                rename.old.path.to_string_lossy(),
+                rename.new.path.to_string_lossy(),
+                similarity
+            );
+        }
+        println!();
+    }
 
-    Ok(())
+    // 显示复制的文件
+    if !changes.copied.is_empty() {
+        println!("复制文件:");
+        for (source, file) in &changes.copied {
+            println!(
+                "  + {} (复制自 {})",
+                file.path.to_string_lossy(),
+                source.path.to_string_lossy()
+            );
+        }
+        println!();
+    }
 }
 
-/// 处理 status 命令
-pub fn handle_status(gam_dir: &PathBuf) -> GamResult<()> {
-    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+/// 处理 status 命令 - 比较工作目录与 HEAD 快照
+///
+/// `verbose` 时列出每个变更文件的大小和哈希，`find_renames` 时再按相似度识别重命名的文本文件
+pub fn handle_status(
+    gam_dir: &PathBuf,
+    verbose: bool,
+    rehash: bool,
+    find_renames: Option<u8>,
+) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
     let snapshot_store = &repo.snapshot_store;
 
     // 获取当前时间线
     let current_timeline = repo.get_timeline_name()?;
 
     // 获取快照数量
-    let snapshots = snapshot_store.list_by_timeline(&current_timeline)?;
+    let snapshots = snapshot_store.summaries_on_timeline(&current_timeline)?;
     let snapshot_count = snapshots.len();
 
-    // 计算大小
-    let game_size = FileUtils::get_dir_size(&repo.game_path)?;
-    let store_size = repo.content_store.deduplication_savings();
+    // 与 HEAD 快照比较
+    let head = head_snapshot(&repo)?;
+    let current = scan_working_tree(&repo, head.as_ref(), rehash)?;
+    let mut changes = ChangeSet::compare(head.as_ref().map_or(&[][..], |h| &h.files), &current);
+    if let Some(threshold) = find_renames {
+        detect_similar_renames(&repo, &mut changes, threshold, true);
+    }
+
+    // 计算大小（只统计纳入版本控制的文件）
+    let game_size = current.iter().filter(|f| f.kind.is_file()).map(|f| f.size).sum();
+    let compression = repo.content_store.index().compression_totals();
 
     let output = Formatter::format_status(
         &current_timeline,
         snapshot_count as u32,
         game_size,
-        store_size,
+        repo.content_store.deduplication_savings(),
+        repo.content_store.codec(),
+        compression,
+        changes.len(),
     );
     println!("{}", output);
+    print!("{}", Formatter::format_changes(head.as_ref(), &changes, verbose));
 
     Ok(())
 }
 
+/// 获取 HEAD 指向的快照（当前时间线的最新快照或分离 HEAD 指向的快照）
+fn head_snapshot(repo: &Repository) -> GamResult<Option<Snapshot>> {
+    let head_id = match repo.current_timeline()? {
+        Some(name) => repo
+            .timeline_manager
+            .get(&name)?
+            .map(|t| t.head_snapshot)
+            .unwrap_or_default(),
+        None => std::fs::read_to_string(repo.gam_dir.join("HEAD"))?
+            .trim()
+            .to_string(),
+    };
+    if head_id.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(repo.snapshot_store.get(&head_id)?))
+}
+
+/// 扫描工作目录（应用忽略规则）并计算文件哈希，用于与快照 `base` 比较
+///
+/// 哈希使用 `base` 记录的算法；状态未变化的文件使用上次保存时缓存的哈希，只有改动过的文件需要重新读取
+fn scan_working_tree(
+    repo: &Repository,
+    base: Option<&Snapshot>,
+    rehash: bool,
+) -> GamResult<Vec<FileEntry>> {
+    let algorithm = base
+        .map(|s| s.hash_algorithm)
+        .unwrap_or_else(|| repo.content_store.hash_algorithm());
+
+    let files = scan_game_directory(&repo.game_path, &repo.gam_dir)?;
+    let mut cache = StatCache::load(&repo.gam_dir, algorithm);
+    repo.pool.install(|| {
+        hash_game_files(&files, &repo.game_path, &repo.content_store, &mut cache, rehash)
+    })
+}
+
 /// 处理 activity 命令
 pub fn handle_activity(gam_dir: &PathBuf, limit: u32) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
     let engine = ActivityEngine::new(gam_dir);
 
     let entries = engine.get_entries(limit as usize)?;
@@ -1037,6 +2131,11 @@ pub fn handle_config(
     value: Option<String>,
     list: bool,
 ) -> GamResult<()> {
+    let _lock = if value.is_some() {
+        RepoLock::exclusive(gam_dir)?
+    } else {
+        RepoLock::shared(gam_dir)?
+    };
     let config_path = gam_dir.join("config");
 
     if !config_path.exists() {
@@ -1132,7 +2231,7 @@ pub fn handle_config(
 
             if found {
                 let new_content = new_lines.join("\n");
-                std::fs::write(&config_path, new_content)?;
+                FileUtils::atomic_write(&config_path, new_content.as_bytes())?;
                 print_success(&format!("已设置 {} = {}", k, v));
             } else {
                 // 如果没找到，追加新配置
@@ -1141,7 +2240,7 @@ pub fn handle_config(
                     new_content.push('\n');
                 }
                 new_content.push_str(&format!("[{}]\n{} = {}\n", section, key_name, v));
-                std::fs::write(&config_path, new_content)?;
+                FileUtils::atomic_write(&config_path, new_content.as_bytes())?;
                 print_success(&format!("已添加 {} = {}", k, v));
             }
         }
@@ -1162,70 +2261,53 @@ pub fn handle_config(
 }
 
 /// 处理 gc 命令
-pub fn handle_gc(gam_dir: &PathBuf, aggressive: bool, dry_run: bool) -> GamResult<()> {
-    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?)?;
+pub fn handle_gc(gam_dir: &PathBuf, aggressive: bool, dry_run: bool, pack: bool) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
 
     // 收集所有被引用的内容哈希
     let mut referenced_hashes = std::collections::HashSet::new();
 
-    // 1. 收集所有快照中的文件哈希
+    // 1. 按快照清单统计引用次数，并修正索引中的引用计数
     let all_snapshots = repo.snapshot_store.list_all()?;
-    for snapshot in &all_snapshots {
-        for file in &snapshot.files {
-            referenced_hashes.insert(file.hash.clone());
-        }
-    }
-
-    // 计算将被清理的空间
-    let mut orphaned_content_size = 0u64;
-    let mut orphaned_content_count = 0u64;
-
-    // 遍历内容存储中的所有文件
-    for prefix_dir in std::fs::read_dir(&repo.content_store.root)? {
-        let prefix_dir = prefix_dir?;
-        if prefix_dir.file_type()?.is_dir() {
-            let prefix_name = prefix_dir.file_name().to_string_lossy().to_string();
-            for file in std::fs::read_dir(prefix_dir.path())? {
-                let file = file?;
-                if file.file_type()?.is_file() {
-                    let file_name = file.file_name().to_string_lossy().to_string();
-                    // 尝试构建完整哈希
-                    if file_name.len() >= 64 {
-                        let full_hash = format!("{}{}", prefix_name, file_name);
-
-                        if !referenced_hashes.contains(&full_hash) {
-                            // 未引用的内容
-                            let size = std::fs::metadata(file.path())?.len();
-                            orphaned_content_size += size;
-                            orphaned_content_count += 1;
-
-                            if dry_run {
-                                // 预览模式：只计算大小
-                            } else {
-                                // 实际删除
-                                std::fs::remove_file(file.path())?;
-                                // 从索引中移除
-                                repo.content_store.index.entries.remove(&full_hash);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let counts = ContentStore::count_references(&all_snapshots);
+    let corrected = if dry_run {
+        repo.content_store.index().refcount_mismatches(&counts).len()
+    } else {
+        repo.content_store.rebuild_refcounts(&counts)?.len()
+    };
+    referenced_hashes.extend(
+        counts
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(hash, _)| hash.clone()),
+    );
 
-    // 清理空的哈希前缀目录
-    if aggressive && !dry_run {
-        for prefix_dir in std::fs::read_dir(&repo.content_store.root)? {
-            let prefix_dir = prefix_dir?;
-            if prefix_dir.file_type()?.is_dir() {
-                if std::fs::read_dir(prefix_dir.path())?.next().is_none() {
-                    std::fs::remove_dir(prefix_dir.path())?;
-                }
-            }
+    // 2. 增量对象依赖的基础对象同样需要保留
+    let direct_hashes: Vec<String> = referenced_hashes.iter().cloned().collect();
+    for hash in &direct_hashes {
+        if repo.content_store.exists(hash) {
+            referenced_hashes.extend(repo.content_store.delta_chain(hash)?);
         }
     }
 
+    // 未被引用的内容对象（松散或已打包）
+    let orphaned_content: HashSet<String> = repo
+        .content_store
+        .all_hashes()?
+        .into_iter()
+        .filter(|hash| HashUtils::is_digest(hash) && !referenced_hashes.contains(hash))
+        .collect();
+    let orphaned_content_count = orphaned_content.len() as u64;
+    let orphaned_content_size = if dry_run {
+        orphaned_content
+            .iter()
+            .map(|hash| repo.content_store.disk_size(hash))
+            .sum()
+    } else {
+        repo.content_store.remove_objects(&orphaned_content)?
+    };
+
     // 保存更新后的索引
     if !dry_run {
         repo.content_store.save_index()?;
@@ -1241,18 +2323,11 @@ pub fn handle_gc(gam_dir: &PathBuf, aggressive: bool, dry_run: bool) -> GamResul
     }
 
     let orphaned_snapshot_size = if aggressive && !dry_run {
-        let mut total = 0u64;
+        // 删除快照文件（包中的快照一次性移除），并释放其对内容对象的引用
+        let ids: Vec<String> = orphaned_snapshots.iter().map(|s| s.id.clone()).collect();
+        let total = repo.snapshot_store.delete_many(&ids)?;
         for snapshot in &orphaned_snapshots {
-            // 删除快照文件
-            let snapshot_path = repo
-                .snapshot_store
-                .snapshot_dir
-                .join(&snapshot.id[..2])
-                .join(&snapshot.id);
-            if snapshot_path.exists() {
-                total += std::fs::metadata(&snapshot_path)?.len();
-                std::fs::remove_file(&snapshot_path)?;
-            }
+            repo.content_store.release(&snapshot.files)?;
         }
         total
     } else {
@@ -1260,6 +2335,14 @@ pub fn handle_gc(gam_dir: &PathBuf, aggressive: bool, dry_run: bool) -> GamResul
         orphaned_snapshots.iter().map(|s| s.size).sum()
     };
 
+    if corrected > 0 {
+        print_info(&format!(
+            "{} {} 个对象的引用计数",
+            if dry_run { "将修正" } else { "已修正" },
+            corrected
+        ));
+    }
+
     let total_freed = if dry_run {
         orphaned_content_size + orphaned_snapshot_size
     } else {
@@ -1301,13 +2384,914 @@ pub fn handle_gc(gam_dir: &PathBuf, aggressive: bool, dry_run: bool) -> GamResul
         )?;
     }
 
+    // 回收后打包剩余的松散对象
+    if !dry_run {
+        let auto_pack = get_gc_auto_pack(gam_dir)?;
+        let loose = repo.content_store.loose_count()? + repo.snapshot_store.loose_count()?;
+        if loose > 0 && (pack || (auto_pack > 0 && loose >= auto_pack)) {
+            pack_repository(gam_dir, &mut repo)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 获取 GC 自动打包阈值（松散对象数），0 表示禁用
+fn get_gc_auto_pack(gam_dir: &Path) -> GamResult<usize> {
+    match read_config_value(gam_dir, "gc", "auto_pack")? {
+        Some(value) => value
+            .as_integer()
+            .filter(|n| *n >= 0)
+            .map(|n| n as usize)
+            .ok_or_else(|| {
+                crate::core::error::GamError::InvalidConfig(
+                    "gc.auto_pack must be a non-negative integer".to_string(),
+                )
+            }),
+        None => Ok(0),
+    }
+}
+
+/// 打包内容对象和快照，并输出结果
+fn pack_repository(gam_dir: &Path, repo: &mut Repository) -> GamResult<()> {
+    let (content_count, content_bytes) = repo.content_store.pack()?;
+    let (snapshot_count, snapshot_bytes) = repo.snapshot_store.pack()?;
+
+    if content_count == 0 && snapshot_count == 0 {
+        print_info("没有需要打包的松散对象");
+        return Ok(());
+    }
+
+    print_success(&format!(
+        "打包完成:\n  内容对象: {} 个 ({})\n  快照: {} 个 ({})",
+        content_count,
+        Formatter::format_size(content_bytes),
+        snapshot_count,
+        Formatter::format_size(snapshot_bytes)
+    ));
+
+    let engine = ActivityEngine::new(&gam_dir.to_path_buf());
+    engine.log(
+        ActivityAction::Pack,
+        None,
+        Some(&format!("{} 个内容, {} 个快照", content_count, snapshot_count)),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// 处理 pack 命令 - 将松散对象合并为包文件
+pub fn handle_pack(gam_dir: &PathBuf) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+    pack_repository(gam_dir, &mut repo)
+}
+
+/// 处理 unpack 命令 - 将包文件还原为松散对象
+pub fn handle_unpack(gam_dir: &PathBuf) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+
+    let content_count = repo.content_store.unpack()?;
+    let snapshot_count = repo.snapshot_store.unpack()?;
+
+    if content_count == 0 && snapshot_count == 0 {
+        print_info("没有包文件需要解包");
+        return Ok(());
+    }
+
+    print_success(&format!(
+        "解包完成:\n  内容对象: {} 个\n  快照: {} 个",
+        content_count, snapshot_count
+    ));
+
+    let engine = ActivityEngine::new(gam_dir);
+    engine.log(
+        ActivityAction::Unpack,
+        None,
+        Some(&format!("{} 个内容, {} 个快照", content_count, snapshot_count)),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// 处理 index rebuild 命令 - 根据所有快照清单重新计算引用计数
+pub fn handle_index_rebuild(gam_dir: &PathBuf) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+
+    let snapshots = repo.snapshot_store.list_all()?;
+    let counts = ContentStore::count_references(&snapshots);
+    let mismatches = repo.content_store.rebuild_refcounts(&counts)?;
+
+    let missing = mismatches
+        .iter()
+        .filter(|m| m.recorded.is_none() && !repo.content_store.exists(&m.hash))
+        .count();
+
+    print_success(&format!(
+        "索引重建完成:\n  扫描了 {} 个快照，{} 个被引用的对象\n  修正了 {} 个引用计数",
+        snapshots.len(),
+        counts.len(),
+        mismatches.len() - missing
+    ));
+    if missing > 0 {
+        print_error(&format!(
+            "{} 个被引用的对象在存储中缺失，相关快照无法完整恢复",
+            missing
+        ));
+    }
+
     Ok(())
 }
 
+/// 处理 fsck 命令 - 深度校验对象、快照和引用
+///
+/// 发现问题时返回错误，进程以非零状态退出，便于脚本判断
+pub fn handle_fsck(gam_dir: &PathBuf, quick: bool) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Read)?;
+    let mut problems = 0usize;
+
+    println!(
+        "Game Archive Manager - 完整性检查{}",
+        if quick { " (快速模式)" } else { "" }
+    );
+    println!("================================\n");
+
+    // 1. 内容对象
+    let hashes = repo.content_store.all_hashes()?;
+    let mut corrupt: HashMap<String, String> = HashMap::new();
+    let mut unindexed = 0usize;
+    for hash in &hashes {
+        match repo.content_store.verify(hash, quick) {
+            ObjectCheck::Ok => {}
+            ObjectCheck::Corrupt(reason) => {
+                corrupt.insert(hash.clone(), reason);
+            }
+            ObjectCheck::Unindexed => unindexed += 1,
+        }
+    }
+    if corrupt.is_empty() {
+        println!("✓ {} 个内容对象完好", hashes.len() - unindexed);
+    } else {
+        println!("✗ 损坏的内容对象 ({} 个):", corrupt.len());
+        let mut sorted: Vec<_> = corrupt.iter().collect();
+        sorted.sort();
+        for (hash, reason) in sorted {
+            println!("    {}  {}", Formatter::short_hash(hash), reason);
+        }
+        problems += corrupt.len();
+    }
+    if unindexed > 0 {
+        println!("! {} 个对象不在索引中，快速模式无法校验", unindexed);
+    }
+
+    // 2. 快照清单
+    let ids = repo.snapshot_store.ids()?;
+    let mut snapshots = Vec::new();
+    for id in &ids {
+        match repo.snapshot_store.get(id) {
+            Ok(snapshot) if snapshot.id == *id => snapshots.push(snapshot),
+            Ok(snapshot) => {
+                println!(
+                    "✗ 快照 {} 的内容 ID 为 {}，与文件名不符",
+                    Formatter::short_hash(id),
+                    Formatter::short_hash(&snapshot.id)
+                );
+                problems += 1;
+            }
+            Err(e) => {
+                println!("✗ 快照 {} 无法解析: {}", Formatter::short_hash(id), e);
+                problems += 1;
+            }
+        }
+    }
+    println!("✓ 解析了 {} / {} 个快照", snapshots.len(), ids.len());
+    let canonical_ids = repo.format.has(format::FEATURE_CANONICAL_IDS);
+    for snapshot in &snapshots {
+        if snapshot.verify_content_hash() == Some(false) {
+            println!(
+                "✗ 快照 {} 的文件清单与内容哈希不符，可能已被篡改",
+                Formatter::short_hash(&snapshot.id)
+            );
+            problems += 1;
+        } else if snapshot.hash_algorithm != repo.content_store.hash_algorithm() {
+            println!(
+                "✗ 快照 {} 使用 {} 算法，与仓库设置的 {} 不符",
+                Formatter::short_hash(&snapshot.id),
+                snapshot.hash_algorithm,
+                repo.content_store.hash_algorithm()
+            );
+            problems += 1;
+        } else if canonical_ids && snapshot.compute_id() != snapshot.id {
+            println!(
+                "✗ 快照 {} 的 ID 与其元数据不符",
+                Formatter::short_hash(&snapshot.id)
+            );
+            problems += 1;
+        }
+    }
+    if !canonical_ids {
+        println!("! 仓库格式较旧，快照 ID 无法校验，可运行 'gam upgrade' 升级");
+    }
+
+    // 3. 每个快照引用的对象（含增量链上的基础对象）
+    let existing: HashSet<&str> = hashes.iter().map(|h| h.as_str()).collect();
+    let mut referenced: HashSet<String> = HashSet::new();
+    for snapshot in &snapshots {
+        let mut lines = Vec::new();
+        for file in &snapshot.files {
+            for hash in file.object_hashes() {
+                let mut needed = vec![hash.to_string()];
+                if existing.contains(hash) {
+                    match repo.content_store.delta_chain(hash) {
+                        Ok(chain) => needed.extend(chain),
+                        Err(e) => {
+                            problems += 1;
+                            lines.push(format!(
+                                "损坏 {} ({}): {}",
+                                file.path.display(),
+                                Formatter::short_hash(hash),
+                                e
+                            ))
+                        }
+                    }
+                }
+                for object in needed {
+                    if !existing.contains(object.as_str()) {
+                        // 损坏对象已在上面计数，这里只计入缺失的对象
+                        problems += 1;
+                        lines.push(format!(
+                            "缺失 {} ({})",
+                            file.path.display(),
+                            Formatter::short_hash(&object)
+                        ));
+                    } else if let Some(reason) = corrupt.get(&object) {
+                        lines.push(format!(
+                            "损坏 {} ({}): {}",
+                            file.path.display(),
+                            Formatter::short_hash(&object),
+                            reason
+                        ));
+                    }
+                    referenced.insert(object);
+                }
+            }
+        }
+        if !lines.is_empty() {
+            println!(
+                "✗ 快照 {} ({}):",
+                Formatter::short_hash(&snapshot.id),
+                snapshot.name
+            );
+            for line in &lines {
+                println!("    {}", line);
+            }
+        }
+    }
+
+    // 4. 时间线、HEAD 和标签
+    let valid: HashSet<&str> = snapshots.iter().map(|s| s.id.as_str()).collect();
+    let mut dangling = Vec::new();
+    for timeline in repo.timeline_manager.list()? {
+        if !timeline.head_snapshot.is_empty() && !valid.contains(timeline.head_snapshot.as_str()) {
+            dangling.push(format!(
+                "时间线 '{}' -> {}",
+                timeline.name,
+                Formatter::short_hash(&timeline.head_snapshot)
+            ));
+        }
+    }
+    let head = std::fs::read_to_string(gam_dir.join("HEAD")).unwrap_or_default();
+    let head = head.trim();
+    if !head.is_empty() && !head.starts_with("ref:") && !valid.contains(head) {
+        dangling.push(format!("HEAD -> {}", Formatter::short_hash(head)));
+    }
+    for (tag, id) in TagStore::new(gam_dir).all_tags() {
+        if !valid.contains(id.as_str()) {
+            dangling.push(format!("标签 '{}' -> {}", tag, Formatter::short_hash(id)));
+        }
+    }
+    if dangling.is_empty() {
+        println!("✓ 所有时间线、HEAD 和标签都指向有效快照");
+    } else {
+        println!("✗ 悬空引用 ({} 个):", dangling.len());
+        dangling.sort();
+        for line in &dangling {
+            println!("    {}", line);
+        }
+        problems += dangling.len();
+    }
+
+    // 未被任何快照引用的对象不算错误，由 gc 清理
+    let unreferenced = hashes.iter().filter(|h| !referenced.contains(*h)).count();
+    if unreferenced > 0 {
+        println!(
+            "! {} 个悬空对象未被任何快照引用，可运行 'gam gc' 清理",
+            unreferenced
+        );
+    }
+
+    println!("\n================================");
+    if problems == 0 {
+        print_success("完整性检查通过");
+        Ok(())
+    } else {
+        Err(crate::core::error::GamError::IntegrityCheckFailed(problems))
+    }
+}
+
+/// 格式升级步骤
+struct UpgradeStep {
+    /// 升级前的格式版本，执行后变为 `from + 1`
+    from: u32,
+    /// 步骤说明
+    description: &'static str,
+    /// 预览将要进行的修改（`--dry-run`）
+    preview: fn(&Repository) -> GamResult<String>,
+    /// 执行升级并返回结果摘要。中途中断后会重新执行，因此必须可以重复执行
+    run: fn(&mut Repository) -> GamResult<String>,
+}
+
+/// 按版本顺序排列的升级步骤
+const UPGRADE_STEPS: &[UpgradeStep] = &[
+    UpgradeStep {
+        from: 1,
+        description: "按规范序列化重写快照 ID",
+        preview: preview_canonical_ids,
+        run: upgrade_canonical_ids,
+    },
+    UpgradeStep {
+        from: 2,
+        description: "创建格式描述文件 .gam/format",
+        preview: preview_format_descriptor,
+        run: upgrade_format_descriptor,
+    },
+];
+
+/// 1 -> 2: 预览需要重写的快照
+fn preview_canonical_ids(repo: &Repository) -> GamResult<String> {
+    let mut count = 0;
+    for id in repo.snapshot_store.ids()? {
+        if repo.snapshot_store.get(&id)?.compute_id() != id {
+            count += 1;
+        }
+    }
+    Ok(format!("约 {} 个快照 ID 将被重写（其后代快照随之改变）", count))
+}
+
+/// 1 -> 2: 按规范序列化重写快照 ID，同步更新时间线、HEAD 和标签
+fn upgrade_canonical_ids(repo: &mut Repository) -> GamResult<String> {
+    let mapping = rewrite_snapshots(
+        &mut repo.snapshot_store,
+        &repo.timeline_manager,
+        &repo.gam_dir,
+        |_| Ok(()),
+    )?;
+    repo.format.require(format::FEATURE_CANONICAL_IDS);
+    Ok(format!("重写了 {} 个快照 ID", mapping.len()))
+}
+
+/// 2 -> 3: 预览格式描述文件的内容
+fn preview_format_descriptor(repo: &Repository) -> GamResult<String> {
+    let mut features = vec![format::FEATURE_CANONICAL_IDS];
+    if repo.content_store.hash_algorithm() == HashAlgorithm::Blake3 {
+        features.push(format::FEATURE_BLAKE3);
+    }
+    Ok(format!(
+        "写入 .gam/format（必需特性: {}），并从配置中移除 core.format_version",
+        features.join(", ")
+    ))
+}
+
+/// 2 -> 3: 创建格式描述文件，版本号不再记录在配置文件中
+fn upgrade_format_descriptor(repo: &mut Repository) -> GamResult<String> {
+    repo.format.require(format::FEATURE_CANONICAL_IDS);
+    if repo.content_store.hash_algorithm() == HashAlgorithm::Blake3 {
+        repo.format.require(format::FEATURE_BLAKE3);
+    }
+    // 先写入描述文件再移除旧的版本号，中断后不会退回到更旧的版本
+    repo.format.version = 3;
+    repo.format.save(&repo.gam_dir)?;
+    remove_config_value(&repo.gam_dir, "core", "format_version")?;
+    Ok("已创建 .gam/format".to_string())
+}
+
+/// 升级前备份时跳过的路径（升级不会修改内容对象，且它们占据了仓库的绝大部分空间）
+const UPGRADE_BACKUP_SKIP: &[&str] = &[
+    "objects/content",
+    "objects/pack/content",
+    "backups",
+    "lock",
+    "lock.reclaim",
+    "readers",
+];
+
+/// 备份仓库元数据（配置、引用、快照等），返回备份目录
+///
+/// 对象保存在独立目录时，其中的快照和包索引同样备份到 `objects/` 下
+fn backup_metadata(gam_dir: &Path, objects_dir: &Path, version: u32) -> GamResult<PathBuf> {
+    let backup_dir = gam_dir.join("backups").join(format!(
+        "upgrade-v{}-{}",
+        version,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    copy_metadata(gam_dir, &backup_dir, "")?;
+    if objects_dir != gam_dir.join("objects") {
+        copy_metadata(objects_dir, &backup_dir.join("objects"), "objects/")?;
+    }
+    Ok(backup_dir)
+}
+
+/// 递归复制 `src` 到 `dst`，跳过 `UPGRADE_BACKUP_SKIP` 中的路径
+///
+/// `prefix` 为 `src` 相对于仓库目录的路径（以 `/` 结尾，仓库目录本身为空）
+fn copy_metadata(src: &Path, dst: &Path, prefix: &str) -> GamResult<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let key = format!("{}{}", prefix, name.to_string_lossy());
+        if UPGRADE_BACKUP_SKIP.contains(&key.as_str()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_metadata(&entry.path(), &dst.join(&name), &format!("{}/", key))?;
+        } else {
+            std::fs::copy(entry.path(), dst.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// 处理 upgrade 命令 - 按顺序执行升级步骤，将仓库升级到当前格式
+///
+/// 每完成一步都会写入 `.gam/format` 记录进度，中断后再次执行会从未完成的步骤继续
+pub fn handle_upgrade(gam_dir: &PathBuf, dry_run: bool) -> GamResult<()> {
+    let (_lock, access) = if dry_run {
+        (RepoLock::shared(gam_dir)?, Access::Read)
+    } else {
+        (RepoLock::exclusive(gam_dir)?, Access::Write)
+    };
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, access)?;
+
+    let version = repo.format.version;
+    let steps: Vec<&UpgradeStep> = UPGRADE_STEPS.iter().filter(|s| s.from >= version).collect();
+    if steps.is_empty() {
+        print_info(&format!("仓库已是最新格式 (版本 {})", version));
+        return Ok(());
+    }
+
+    if dry_run {
+        println!(
+            "将仓库从格式版本 {} 升级到 {}（预览，不会修改仓库）:",
+            version,
+            format::CURRENT_VERSION
+        );
+        for step in &steps {
+            println!("  {} -> {}: {}", step.from, step.from + 1, step.description);
+            println!("      {}", (step.preview)(&repo)?);
+        }
+        println!("  升级前会将仓库元数据备份到 .gam/backups/");
+        return Ok(());
+    }
+
+    // 中断的保存会引用旧的快照 ID，必须先恢复
+    if let Some(pending) = Journal::new(gam_dir).pending()? {
+        return Err(crate::core::error::GamError::IncompleteOperation(
+            pending.operation.to_string(),
+        ));
+    }
+
+    let backup_dir = backup_metadata(gam_dir, repo.storage.objects_dir(), version)?;
+    print_info(&format!("已备份仓库元数据到 {}", backup_dir.display()));
+
+    let mut summary = Vec::new();
+    for step in steps {
+        let result = (step.run)(&mut repo)?;
+        repo.format.version = step.from + 1;
+        repo.format.save(gam_dir)?;
+        summary.push(format!("  {} -> {}: {}", step.from, step.from + 1, result));
+    }
+
+    let engine = ActivityEngine::new(gam_dir);
+    engine.log(
+        ActivityAction::Upgrade,
+        None,
+        None,
+        Some(&format!("format {} -> {}", version, repo.format.version)),
+    )?;
+
+    print_success(&format!(
+        "仓库已升级到格式版本 {}\n{}",
+        repo.format.version,
+        summary.join("\n")
+    ));
+    Ok(())
+}
+
+/// 处理 key change 命令 - 更换保护仓库主密钥的口令或密钥文件
+///
+/// 只重新加密 `.gam/keys` 中的主密钥，对象和快照不变。
+/// 指定 `key_file` 时改用密钥文件（不存在时自动生成），否则设置新口令（可由 `GAM_NEW_PASSPHRASE` 提供）
+pub fn handle_key_change(gam_dir: &PathBuf, key_file: Option<String>) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    if !KeyStore::exists(gam_dir) {
+        return Err(crate::core::error::GamError::Encryption(
+            "repository is not encrypted. Use 'gam init --encrypt' for a new repository".to_string(),
+        ));
+    }
+
+    let store = KeyStore::read(gam_dir)?;
+    let key = store.unwrap(&read_credential(gam_dir, &store)?)?;
+
+    let (credential, key_file_path) = match &key_file {
+        Some(path) => {
+            let (credential, path) = open_or_create_key_file(Path::new(path))?;
+            (credential, Some(path))
+        }
+        None => (read_new_passphrase("GAM_NEW_PASSPHRASE")?, None),
+    };
+    KeyStore::wrap(&key, &credential)?.save(gam_dir)?;
+
+    match &key_file_path {
+        Some(path) => set_config_value(
+            gam_dir,
+            "encryption",
+            "key_file",
+            &format!("\"{}\"", path.to_string_lossy().replace('\\', "/")),
+        )?,
+        None => remove_config_value(gam_dir, "encryption", "key_file")?,
+    }
+
+    let engine = ActivityEngine::new(gam_dir);
+    engine.log(ActivityAction::KeyChange, None, None, None)?;
+
+    match key_file_path {
+        Some(path) => print_success(&format!("仓库现在由密钥文件 {} 保护", path.display())),
+        None => print_success("已更换仓库口令"),
+    }
+    Ok(())
+}
+
+/// 处理 storage show 命令 - 显示对象存储的位置
+pub fn handle_storage_show(gam_dir: &PathBuf) -> GamResult<()> {
+    let storage = open_storage(gam_dir)?;
+    println!("对象存储: {}", storage.describe());
+    println!("  目录: {}", storage.objects_dir().display());
+    if storage.objects_dir().exists() {
+        println!(
+            "  大小: {}",
+            FileUtils::format_size(FileUtils::get_dir_size(storage.objects_dir())?)
+        );
+    }
+    Ok(())
+}
+
+/// 处理 storage move 命令 - 将对象和快照迁移到另一个目录（`path` 为空时迁回 `.gam/objects`）
+///
+/// 先把全部对象复制到新位置并更新配置，再删除旧位置的对象；复制中断时旧位置保持完整，
+/// 再次迁移到同一位置时继续复制
+pub fn handle_storage_move(gam_dir: &PathBuf, path: Option<String>) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+    if let Some(pending) = Journal::new(gam_dir).pending()? {
+        return Err(crate::core::error::GamError::IncompleteOperation(
+            pending.operation.to_string(),
+        ));
+    }
+
+    let source = repo.storage.objects_dir().to_path_buf();
+    let target = match &path {
+        Some(dir) => {
+            let dir = Path::new(dir);
+            if dir.exists() && dir.canonicalize()? == source.canonicalize()? {
+                print_info(&format!("对象已保存在 {}", repo.storage.describe()));
+                return Ok(());
+            }
+            StorageLocation::directory(dir.to_path_buf(), String::new())
+        }
+        None => {
+            let local = StorageLocation::local(gam_dir);
+            if local.objects_dir() == source {
+                print_info(&format!("对象已保存在 {}", repo.storage.describe()));
+                return Ok(());
+            }
+            local
+        }
+    };
+    let (target, resumed) = target.prepare_move_target(&source)?;
+    if resumed {
+        print_info(&format!("继续之前中断的迁移: {}", target.describe()));
+    }
+
+    // 继续中断的迁移时覆盖已复制的部分
+    let mut copied = 0u64;
+    for entry in std::fs::read_dir(&source)? {
+        let entry = entry?;
+        if entry.file_name() == MARKER_FILE || entry.file_name() == MOVE_MARKER_FILE {
+            continue;
+        }
+        let dst = target.objects_dir().join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copied += FileUtils::copy_dir_all(&entry.path(), &dst)?;
+        } else {
+            copied += std::fs::copy(entry.path(), &dst)?;
+        }
+    }
+    target.init()?;
+
+    match target.repo_id() {
+        Some(id) => {
+            let dir = target.objects_dir().to_string_lossy().replace('\\', "/");
+            set_config_value(gam_dir, "storage", "path", &format!("\"{}\"", dir))?;
+            set_config_value(gam_dir, "storage", "id", &format!("\"{}\"", id))?;
+        }
+        None => {
+            remove_config_value(gam_dir, "storage", "path")?;
+            remove_config_value(gam_dir, "storage", "id")?;
+        }
+    }
+    target.finish_move()?;
+
+    // 配置已指向新位置，旧位置的对象不再使用
+    if source == StorageLocation::local(gam_dir).objects_dir() {
+        std::fs::remove_dir_all(&source)?;
+    } else {
+        for entry in std::fs::read_dir(&source)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                std::fs::remove_dir_all(entry.path())?;
+            } else {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+    }
+
+    let engine = ActivityEngine::new(gam_dir);
+    engine.log(
+        ActivityAction::StorageMove,
+        None,
+        None,
+        Some(&target.objects_dir().to_string_lossy()),
+    )?;
+
+    print_success(&format!(
+        "已将 {} 对象迁移到 {}",
+        FileUtils::format_size(copied),
+        target.describe()
+    ));
+    Ok(())
+}
+
+/// 处理 migrate-hash 命令 - 使用新的哈希算法重写整个仓库
+pub fn handle_migrate_hash(gam_dir: &PathBuf, algorithm: &str) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+
+    let target: HashAlgorithm = algorithm
+        .parse()
+        .map_err(crate::core::error::GamError::InvalidConfig)?;
+    let current = repo.content_store.hash_algorithm();
+    if current == target {
+        print_info(&format!("仓库已使用 {} 算法", target));
+        return Ok(());
+    }
+
+    // 迁移会按规范序列化重写快照 ID，旧格式的仓库需要先升级
+    if repo.format.version < format::CURRENT_VERSION {
+        return Err(crate::core::error::GamError::OutdatedFormat(repo.format.version));
+    }
+
+    if let Some(pending) = Journal::new(gam_dir).pending()? {
+        return Err(crate::core::error::GamError::IncompleteOperation(
+            pending.operation.to_string(),
+        ));
+    }
+
+    // 1. 以新算法写入所有对象
+    let mapping = repo.content_store.rehash(target)?;
+
+    // 2. 替换快照中的文件哈希，快照 ID 随之改变
+    let content_store = &repo.content_store;
+    let snapshot_ids = rewrite_snapshots(
+        &mut repo.snapshot_store,
+        &repo.timeline_manager,
+        gam_dir,
+        |snapshot| {
+            if snapshot.hash_algorithm == target {
+                return Ok(());
+            }
+            for file in &mut snapshot.files {
+                if file.kind == EntryKind::Dir {
+                    continue;
+                }
+                if let Some(link_target) = &file.symlink_target {
+                    file.hash = content_store.hash_symlink_with(link_target, target);
+                } else if file.is_chunked() {
+                    file.hash = content_store.hash_entry(file, target)?;
+                    for chunk in &mut file.chunks {
+                        *chunk = remap_hash(&mapping, chunk, &snapshot.id)?;
+                    }
+                } else {
+                    file.hash = remap_hash(&mapping, &file.hash, &snapshot.id)?;
+                }
+            }
+            snapshot.hash_algorithm = target;
+            snapshot.content_hash = Snapshot::compute_content_hash(&snapshot.files);
+            Ok(())
+        },
+    )?;
+
+    // 3. 更新配置，删除旧对象并重新计算引用计数
+    set_config_value(gam_dir, "core", "hash_algorithm", &format!("\"{}\"", target))?;
+    if target == HashAlgorithm::Blake3 {
+        repo.format.require(format::FEATURE_BLAKE3);
+    } else {
+        repo.format.remove(format::FEATURE_BLAKE3);
+    }
+    repo.format.save(gam_dir)?;
+    let old_objects: HashSet<String> = mapping
+        .iter()
+        .filter(|(old, new)| old != new)
+        .map(|(old, _)| old.clone())
+        .collect();
+    repo.content_store.remove_objects(&old_objects)?;
+    let snapshots = repo.snapshot_store.list_all()?;
+    repo.content_store
+        .rebuild_refcounts(&ContentStore::count_references(&snapshots))?;
+
+    let engine = ActivityEngine::new(gam_dir);
+    engine.log(
+        ActivityAction::MigrateHash,
+        None,
+        None,
+        Some(&format!("{} -> {}", current, target)),
+    )?;
+
+    print_success(&format!(
+        "已将仓库哈希算法从 {} 迁移到 {}\n  重写了 {} 个对象和 {} 个快照",
+        current,
+        target,
+        old_objects.len(),
+        snapshot_ids.len()
+    ));
+    Ok(())
+}
+
+/// 查找对象的新哈希，对象缺失时无法迁移
+fn remap_hash(mapping: &HashMap<String, String>, hash: &str, snapshot_id: &str) -> GamResult<String> {
+    mapping.get(hash).cloned().ok_or_else(|| {
+        crate::core::error::GamError::Hash(format!(
+            "object {} referenced by snapshot {} is missing. Run 'gam fsck' first",
+            Formatter::short_hash(hash),
+            Formatter::short_hash(snapshot_id)
+        ))
+    })
+}
+
+/// 重写所有快照并按规范序列化重新计算 ID，同步更新父快照链接、时间线、HEAD 和标签
+///
+/// `transform` 在计算新 ID 之前修改每个快照的内容。返回旧 ID 到新 ID 的映射。
+/// 新 ID 只由快照内容决定，中途中断后重新执行会得到相同的结果
+fn rewrite_snapshots<F>(
+    snapshot_store: &mut SnapshotStore,
+    timeline_manager: &TimelineManager,
+    gam_dir: &Path,
+    mut transform: F,
+) -> GamResult<HashMap<String, String>>
+where
+    F: FnMut(&mut Snapshot) -> GamResult<()>,
+{
+    let mut pending: HashMap<String, Snapshot> = HashMap::new();
+    for id in snapshot_store.ids()? {
+        let mut snapshot = snapshot_store.get(&id)?;
+        transform(&mut snapshot)?;
+        pending.insert(id, snapshot);
+    }
+
+    // 父快照的新 ID 参与子快照 ID 的计算，因此按父快照优先的顺序处理
+    let mut new_ids: HashMap<String, String> = HashMap::new();
+    let mut rewritten: Vec<Snapshot> = Vec::new();
+    while !pending.is_empty() {
+        let ready: Vec<String> = pending
+            .iter()
+            .filter(|(_, s)| match &s.parent {
+                Some(parent) => new_ids.contains_key(parent) || !pending.contains_key(parent),
+                None => true,
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        if ready.is_empty() {
+            return Err(crate::core::error::GamError::Unknown(
+                "snapshot parent links form a cycle".to_string(),
+            ));
+        }
+
+        for old_id in ready {
+            let mut snapshot = pending.remove(&old_id).unwrap();
+            if let Some(parent) = &snapshot.parent
+                && let Some(new_parent) = new_ids.get(parent)
+            {
+                snapshot.parent = Some(new_parent.clone());
+            }
+            snapshot.id = snapshot.compute_id();
+            new_ids.insert(old_id.clone(), snapshot.id.clone());
+            if snapshot.id != old_id {
+                rewritten.push(snapshot);
+            }
+        }
+    }
+
+    let mapping: HashMap<String, String> = new_ids
+        .into_iter()
+        .filter(|(old, new)| old != new)
+        .collect();
+    if mapping.is_empty() {
+        return Ok(mapping);
+    }
+
+    // 先写入新快照，再更新引用，最后删除旧快照；索引在下次列出快照时重建
+    snapshot_store.drop_index()?;
+    for snapshot in &rewritten {
+        snapshot_store.save(snapshot)?;
+    }
+
+    for timeline in timeline_manager.list()? {
+        if let Some(new_id) = mapping.get(&timeline.head_snapshot) {
+            timeline_manager.update_head(&timeline.name, new_id)?;
+        }
+    }
+
+    let head_path = gam_dir.join("HEAD");
+    let head = std::fs::read_to_string(&head_path)?;
+    if let Some(new_id) = mapping.get(head.trim()) {
+        FileUtils::atomic_write(&head_path, new_id.as_bytes())?;
+    }
+
+    let mut tags = TagStore::new(&gam_dir.to_path_buf());
+    tags.remap_snapshots(&mapping);
+    tags.save(&gam_dir.to_path_buf())?;
+
+    let old_ids: Vec<String> = mapping.keys().cloned().collect();
+    snapshot_store.delete_many(&old_ids)?;
+
+    Ok(mapping)
+}
+
+/// 按预写日志恢复中断的操作，返回修复说明
+///
+/// 快照文件已写入时补完时间线引用；否则回滚已增加的引用计数，
+/// 已写入的对象不再被引用，留给 gc 清理
+fn recover_journal(gam_dir: &PathBuf, entry: &JournalEntry) -> GamResult<String> {
+    let mut repo = Repository::new(gam_dir.clone(), get_game_path(gam_dir)?, Access::Write)?;
+
+    let saved = entry
+        .snapshot_id
+        .as_ref()
+        .filter(|_| entry.stage == JournalStage::Committing)
+        .filter(|id| repo.snapshot_store.get(id).is_ok());
+
+    let message = match saved {
+        Some(id) => {
+            // 时间线仍指向操作开始时的快照才推进，不覆盖之后的修改
+            let head = repo
+                .timeline_manager
+                .get(&entry.timeline)?
+                .map(|t| t.head_snapshot);
+            if head == Some(entry.parent.clone().unwrap_or_default()) {
+                repo.timeline_manager.update_head(&entry.timeline, id)?;
+            }
+            format!(
+                "已完成中断的快照保存 {} (时间线 '{}')",
+                Formatter::short_hash(id),
+                entry.timeline
+            )
+        }
+        None => {
+            let snapshots = repo.snapshot_store.list_all()?;
+            repo.content_store
+                .rebuild_refcounts(&ContentStore::count_references(&snapshots))?;
+            "已回滚中断的快照保存，残留对象可运行 'gam gc' 清理".to_string()
+        }
+    };
+
+    Journal::new(gam_dir).finish()?;
+    Ok(message)
+}
+
 /// 处理 doctor 命令 - 诊断并修复仓库问题
 pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
-    let mut issues = Vec::new();
-    let mut fixes = Vec::new();
+    let _lock = if fix {
+        RepoLock::exclusive(gam_dir)?
+    } else {
+        RepoLock::shared(gam_dir)?
+    };
+    let mut issues: Vec<String> = Vec::new();
+    let mut fixes: Vec<String> = Vec::new();
 
     println!("Game Archive Manager - 健康检查");
     println!("================================\n");
@@ -1315,21 +3299,43 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
     // 1. 检查 .gam 目录是否存在
     let gam_exists = gam_dir.exists();
     if !gam_exists {
-        issues.push("未找到 .gam 目录，仓库未初始化");
+        issues.push("未找到 .gam 目录，仓库未初始化".to_string());
         if fix {
             // 提示用户运行 gam init
-            fixes.push("请运行 'gam init --path <存档目录>' 初始化仓库");
+            fixes.push("请运行 'gam init --path <存档目录>' 初始化仓库".to_string());
         }
     } else {
         println!("✓ .gam 目录存在");
 
+        // 与 Access::Write 打开仓库相同的格式检查：本程序不支持的格式只做诊断，不做任何修复
+        let writable = match load_format(gam_dir) {
+            Ok(format) => match format.unsupported() {
+                None => {
+                    println!("✓ 仓库格式版本 {}", format.version);
+                    true
+                }
+                Some(reason) => {
+                    issues.push(format!("仓库使用了本程序不支持的格式 ({})", reason));
+                    false
+                }
+            },
+            Err(e) => {
+                issues.push(format!("仓库格式无法读取: {}", e));
+                false
+            }
+        };
+        if fix && !writable {
+            print_warning("仓库格式不受支持，不会执行自动修复，请先升级 gam");
+        }
+        let fix = fix && writable;
+
         // 2. 检查配置文件
         let config_path = gam_dir.join("config");
         if !config_path.exists() {
-            issues.push("配置文件 config 不存在");
+            issues.push("配置文件 config 不存在".to_string());
             if fix {
                 // 尝试从 HEAD 推断
-                fixes.push("配置文件损坏，请手动删除 .gam 目录后重新运行 gam init");
+                fixes.push("配置文件损坏，请手动删除 .gam 目录后重新运行 gam init".to_string());
             }
         } else {
             println!("✓ 配置文件存在");
@@ -1339,9 +3345,9 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
                 if content.parse::<toml::Value>().is_ok() {
                     println!("✓ 配置文件格式正确");
                 } else {
-                    issues.push("配置文件格式错误");
+                    issues.push("配置文件格式错误".to_string());
                     if fix {
-                        fixes.push("配置文件损坏，请手动删除 .gam 目录后重新运行 gam init");
+                        fixes.push("配置文件损坏，请手动删除 .gam 目录后重新运行 gam init".to_string());
                     }
                 }
             }
@@ -1350,10 +3356,10 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
         // 3. 检查 HEAD
         let head_path = gam_dir.join("HEAD");
         if !head_path.exists() {
-            issues.push("HEAD 文件不存在");
+            issues.push("HEAD 文件不存在".to_string());
             if fix {
-                std::fs::write(&head_path, "ref: refs/timelines/main\n")?;
-                fixes.push("已创建 HEAD 文件并指向 main 时间线");
+                FileUtils::atomic_write(&head_path, b"ref: refs/timelines/main\n")?;
+                fixes.push("已创建 HEAD 文件并指向 main 时间线".to_string());
             }
         } else {
             let head_content = std::fs::read_to_string(&head_path)?;
@@ -1362,10 +3368,10 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
                     .trim_start_matches("ref: refs/timelines/")
                     .trim();
                 if timeline.is_empty() {
-                    issues.push("HEAD 引用了空的时间线");
+                    issues.push("HEAD 引用了空的时间线".to_string());
                     if fix {
-                        std::fs::write(&head_path, "ref: refs/timelines/main\n")?;
-                        fixes.push("已修复 HEAD，指向 main 时间线");
+                        FileUtils::atomic_write(&head_path, b"ref: refs/timelines/main\n")?;
+                        fixes.push("已修复 HEAD，指向 main 时间线".to_string());
                     }
                 } else {
                     println!("✓ HEAD 指向时间线 '{}'", timeline);
@@ -1375,69 +3381,81 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
                 if head_content.trim().len() >= 8 {
                     println!("✓ HEAD 处于分离状态 (指向快照)");
                 } else {
-                    issues.push("HEAD 内容无效");
+                    issues.push("HEAD 内容无效".to_string());
                     if fix {
-                        std::fs::write(&head_path, "ref: refs/timelines/main\n")?;
-                        fixes.push("已修复 HEAD");
+                        FileUtils::atomic_write(&head_path, b"ref: refs/timelines/main\n")?;
+                        fixes.push("已修复 HEAD".to_string());
                     }
                 }
             }
         }
 
-        // 4. 检查 objects 目录结构
-        let objects_dir = gam_dir.join("objects");
-        if !objects_dir.exists() {
-            issues.push("objects 目录不存在");
-            if fix {
-                std::fs::create_dir_all(&objects_dir.join("snapshot"))?;
-                std::fs::create_dir_all(&objects_dir.join("content"))?;
-                fixes.push("已创建 objects 目录结构");
+        // 4. 检查对象存储和 objects 目录结构
+        let storage = match open_storage(gam_dir) {
+            Ok(storage) => {
+                println!("✓ 对象存储可用: {}", storage.describe());
+                Some(storage)
             }
-        } else {
-            println!("✓ objects 目录存在");
-
-            // 检查 snapshot 子目录
-            let snapshot_dir = objects_dir.join("snapshot");
-            if !snapshot_dir.exists() {
-                issues.push("snapshot 目录不存在");
+            Err(e) => {
+                issues.push(format!("对象存储不可用: {}", e));
+                None
+            }
+        };
+        if let Some(storage) = &storage {
+            let objects_dir = storage.objects_dir();
+            if !objects_dir.exists() {
+                issues.push("objects 目录不存在".to_string());
                 if fix {
-                    std::fs::create_dir_all(&snapshot_dir)?;
-                    fixes.push("已创建 snapshot 目录");
+                    std::fs::create_dir_all(objects_dir.join("snapshot"))?;
+                    std::fs::create_dir_all(objects_dir.join("content"))?;
+                    fixes.push("已创建 objects 目录结构".to_string());
                 }
             } else {
-                println!("✓ snapshot 目录存在");
-            }
+                println!("✓ objects 目录存在");
 
-            // 检查 content 子目录
-            let content_dir = objects_dir.join("content");
-            if !content_dir.exists() {
-                issues.push("content 目录不存在");
-                if fix {
-                    std::fs::create_dir_all(&content_dir)?;
-                    fixes.push("已创建 content 目录");
+                // 检查 snapshot 子目录
+                let snapshot_dir = objects_dir.join("snapshot");
+                if !snapshot_dir.exists() {
+                    issues.push("snapshot 目录不存在".to_string());
+                    if fix {
+                        std::fs::create_dir_all(&snapshot_dir)?;
+                        fixes.push("已创建 snapshot 目录".to_string());
+                    }
+                } else {
+                    println!("✓ snapshot 目录存在");
+                }
+
+                // 检查 content 子目录
+                let content_dir = objects_dir.join("content");
+                if !content_dir.exists() {
+                    issues.push("content 目录不存在".to_string());
+                    if fix {
+                        std::fs::create_dir_all(&content_dir)?;
+                        fixes.push("已创建 content 目录".to_string());
+                    }
+                } else {
+                    println!("✓ content 目录存在");
                 }
-            } else {
-                println!("✓ content 目录存在");
             }
         }
 
         // 5. 检查 refs 目录
         let refs_dir = gam_dir.join("refs");
         if !refs_dir.exists() {
-            issues.push("refs 目录不存在");
+            issues.push("refs 目录不存在".to_string());
             if fix {
                 std::fs::create_dir_all(&refs_dir.join("timelines"))?;
-                fixes.push("已创建 refs 目录结构");
+                fixes.push("已创建 refs 目录结构".to_string());
             }
         } else {
             let timelines_dir = refs_dir.join("timelines");
             if !timelines_dir.exists() {
-                issues.push("timelines 目录不存在");
+                issues.push("timelines 目录不存在".to_string());
                 if fix {
                     std::fs::create_dir_all(&timelines_dir)?;
                     // 创建默认 main 时间线
-                    std::fs::write(&timelines_dir.join("main"), "")?;
-                    fixes.push("已创建 timelines 目录并创建 main 时间线");
+                    FileUtils::atomic_write(&timelines_dir.join("main"), b"")?;
+                    fixes.push("已创建 timelines 目录并创建 main 时间线".to_string());
                 }
             } else {
                 println!("✓ timelines 目录存在");
@@ -1449,10 +3467,10 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
                     .count();
 
                 if timelines == 0 {
-                    issues.push("没有时间线存在");
+                    issues.push("没有时间线存在".to_string());
                     if fix {
-                        std::fs::write(&timelines_dir.join("main"), "")?;
-                        fixes.push("已创建默认 main 时间线");
+                        FileUtils::atomic_write(&timelines_dir.join("main"), b"")?;
+                        fixes.push("已创建默认 main 时间线".to_string());
                     }
                 } else {
                     println!("✓ 存在 {} 个时间线", timelines);
@@ -1460,16 +3478,124 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
             }
         }
 
-        // 6. 检查 .gamignore 格式（如果存在）
+        // 6. 检查增量链：每个增量对象的基础对象都必须存在
+        if let Some(storage) = &storage
+            && storage.objects_dir().join("content").exists()
+        {
+            let mut content_store = ContentStore::new(storage)?;
+            content_store.set_key(load_key(gam_dir)?);
+            let mut delta_count = 0usize;
+            let mut broken = 0usize;
+
+            for hash in content_store.index().entries.keys() {
+                if !content_store.exists(hash) {
+                    continue;
+                }
+                match content_store.delta_chain(hash) {
+                    Ok(chain) if chain.is_empty() => {}
+                    Ok(chain) => {
+                        delta_count += 1;
+                        if let Some(missing) = chain.iter().find(|b| !content_store.exists(b)) {
+                            broken += 1;
+                            issues.push(format!(
+                                "增量对象 {} 的基础对象 {} 缺失",
+                                Formatter::short_hash(hash),
+                                Formatter::short_hash(missing)
+                            ));
+                        }
+                    }
+                    Err(e) => {
+                        broken += 1;
+                        issues.push(format!(
+                            "增量对象 {} 的增量链无效: {}",
+                            Formatter::short_hash(hash),
+                            e
+                        ));
+                    }
+                }
+            }
+
+            if broken == 0 && delta_count > 0 {
+                println!("✓ 增量链完整 ({} 个增量对象)", delta_count);
+            } else if broken > 0 && fix {
+                fixes.push("增量链损坏无法自动修复，受影响的快照需要删除后重新保存".to_string());
+            }
+        }
+
+        // 7. 检查引用计数是否与快照清单一致
+        if let Some(storage) = &storage
+            && storage.objects_dir().join("content").exists()
+        {
+            let key = load_key(gam_dir)?;
+            let mut content_store = ContentStore::new(storage)?;
+            content_store.set_key(key.clone());
+            let mut snapshot_store = SnapshotStore::new(storage)?;
+            snapshot_store.set_key(key);
+            let snapshots = snapshot_store.list_all()?;
+            let counts = ContentStore::count_references(&snapshots);
+            let mismatches = content_store.index().refcount_mismatches(&counts);
+
+            if mismatches.is_empty() {
+                println!("✓ 引用计数正确 ({} 个对象)", content_store.index().entries.len());
+            } else {
+                for mismatch in &mismatches {
+                    issues.push(match mismatch.recorded {
+                        Some(recorded) => format!(
+                            "对象 {} 的引用计数为 {}，实际被引用 {} 次",
+                            Formatter::short_hash(&mismatch.hash),
+                            recorded,
+                            mismatch.actual
+                        ),
+                        None => format!(
+                            "对象 {} 被引用 {} 次，但不在索引中",
+                            Formatter::short_hash(&mismatch.hash),
+                            mismatch.actual
+                        ),
+                    });
+                }
+                if fix {
+                    content_store.rebuild_refcounts(&counts)?;
+                    fixes.push(format!("已重建 {} 个对象的引用计数", mismatches.len()));
+                }
+            }
+        }
+
+        // 8. 检查是否有中途中断的操作
+        match Journal::new(gam_dir).pending() {
+            Ok(None) => println!("✓ 没有未完成的操作"),
+            Ok(Some(entry)) => {
+                issues.push(format!(
+                    "存在未完成的操作 '{}' (时间线 '{}', 开始于 {})",
+                    entry.operation,
+                    entry.timeline,
+                    entry.started_at.format("%Y-%m-%d %H:%M:%S")
+                ));
+                if fix {
+                    fixes.push(recover_journal(gam_dir, &entry)?);
+                }
+            }
+            Err(e) => {
+                issues.push(format!("预写日志已损坏: {}", e));
+                if fix {
+                    Journal::new(gam_dir).finish()?;
+                    fixes.push(
+                        "已删除损坏的预写日志，请运行 'gam index rebuild' 校正引用计数"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+
+        // 9. 检查 .gamignore 格式（如果存在）
         let ignore_file = gam_dir.join(".gamignore");
         if ignore_file.exists() {
             if let Ok(content) = std::fs::read_to_string(&ignore_file) {
                 if IgnoreEngine::parse_gamignore(&content).is_ok() {
                     println!("✓ .gamignore 格式正确");
                 } else {
-                    issues.push(".gamignore 格式错误");
+                    issues.push(".gamignore 格式错误".to_string());
                     if fix {
-                        fixes.push(".gamignore 格式错误，请手动检查并修复");
+                        fixes.push(".gamignore 格式错误，请手动检查并修复".to_string());
                     }
                 }
             }
@@ -1509,6 +3635,7 @@ pub fn handle_doctor(gam_dir: &PathBuf, fix: bool) -> GamResult<()> {
 
 /// 处理 ignore list 命令
 pub fn handle_ignore_list(gam_dir: &PathBuf) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
     let engine = load_ignore_engine(gam_dir)?;
     let patterns = engine.patterns();
 
@@ -1530,6 +3657,7 @@ pub fn handle_ignore_list(gam_dir: &PathBuf) -> GamResult<()> {
 
 /// 处理 ignore init 命令
 pub fn handle_ignore_init(gam_dir: &PathBuf, force: bool) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
     let ignore_file = gam_dir.join(".gamignore");
 
     if ignore_file.exists() && !force {
@@ -1538,7 +3666,7 @@ pub fn handle_ignore_init(gam_dir: &PathBuf, force: bool) -> GamResult<()> {
     }
 
     let template = IgnoreEngine::default_gamignore_template();
-    std::fs::write(&ignore_file, template)?;
+    FileUtils::atomic_write(&ignore_file, template.as_bytes())?;
 
     print_success("已创建 .gamignore 模板");
 
@@ -1547,6 +3675,7 @@ pub fn handle_ignore_init(gam_dir: &PathBuf, force: bool) -> GamResult<()> {
 
 /// 处理 ignore add 命令
 pub fn handle_ignore_add(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
     let ignore_file = gam_dir.join(".gamignore");
 
     // 读取现有内容
@@ -1559,7 +3688,7 @@ pub fn handle_ignore_add(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
     // 添加新规则
     content.push_str(&format!("{}\n", pattern));
 
-    std::fs::write(&ignore_file, content)?;
+    FileUtils::atomic_write(&ignore_file, content.as_bytes())?;
 
     // 记录活动
     let engine = ActivityEngine::new(gam_dir);
@@ -1572,6 +3701,7 @@ pub fn handle_ignore_add(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
 
 /// 处理 ignore remove 命令
 pub fn handle_ignore_remove(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
+    let _lock = RepoLock::exclusive(gam_dir)?;
     let ignore_file = gam_dir.join(".gamignore");
 
     if !ignore_file.exists() {
@@ -1601,7 +3731,7 @@ pub fn handle_ignore_remove(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
     }
 
     if removed {
-        std::fs::write(&ignore_file, new_lines.join("\n"))?;
+        FileUtils::atomic_write(&ignore_file, new_lines.join("\n").as_bytes())?;
 
         // 记录活动
         let engine = ActivityEngine::new(gam_dir);
@@ -1617,6 +3747,7 @@ pub fn handle_ignore_remove(gam_dir: &PathBuf, pattern: &str) -> GamResult<()> {
 
 /// 处理 ignore check 命令
 pub fn handle_ignore_check(gam_dir: &PathBuf, file: &str) -> GamResult<()> {
+    let _lock = RepoLock::shared(gam_dir)?;
     let engine = load_ignore_engine(gam_dir)?;
     let path = std::path::PathBuf::from(file);
 
@@ -1650,3 +3781,265 @@ fn print_confirm(msg: &str) {
     use std::io::Write;
     std::io::stdout().flush().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::store::backend::object_key;
+
+    /// 在临时目录中初始化仓库，返回临时目录和 `.gam` 目录
+    fn init_repo() -> (tempfile::TempDir, PathBuf) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().to_string_lossy().to_string();
+        handle_init(Some(path), false, false, None, None).unwrap();
+        let gam_dir = temp_dir.path().join(".gam");
+        (temp_dir, gam_dir)
+    }
+
+    fn open(gam_dir: &PathBuf) -> Repository {
+        let game_path = get_game_path(gam_dir).unwrap();
+        Repository::new(gam_dir.clone(), game_path, Access::Read).unwrap()
+    }
+
+    /// 保存快照并返回新的 HEAD 快照
+    fn save(gam_dir: &PathBuf, message: &str) -> Snapshot {
+        handle_snapshot_save(gam_dir, Some(message.to_string()), None, true).unwrap();
+        head_snapshot(&open(gam_dir)).unwrap().unwrap()
+    }
+
+    fn refcounts(gam_dir: &PathBuf) -> HashMap<String, u32> {
+        open(gam_dir)
+            .content_store
+            .index()
+            .entries
+            .values()
+            .map(|e| (e.hash.clone(), e.refcount))
+            .collect()
+    }
+
+    fn hash_of(snapshot: &Snapshot, path: &str) -> String {
+        snapshot
+            .files
+            .iter()
+            .find(|f| f.path == Path::new(path))
+            .unwrap()
+            .hash
+            .clone()
+    }
+
+    #[test]
+    fn test_refcounts_after_delete_and_rebuild() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("shared.sav"), b"shared").unwrap();
+        std::fs::write(temp_dir.path().join("slot.sav"), b"first").unwrap();
+        let first = save(&gam_dir, "first");
+        std::fs::write(temp_dir.path().join("slot.sav"), b"second").unwrap();
+        let second = save(&gam_dir, "second");
+
+        let shared = hash_of(&first, "shared.sav");
+        let counts = refcounts(&gam_dir);
+        assert_eq!(counts[&shared], 2);
+        assert_eq!(counts[&hash_of(&first, "slot.sav")], 1);
+        assert_eq!(counts[&hash_of(&second, "slot.sav")], 1);
+
+        handle_snapshot_delete(&gam_dir, &first.id, true).unwrap();
+        let counts = refcounts(&gam_dir);
+        assert_eq!(counts[&shared], 1);
+        assert_eq!(counts.get(&hash_of(&first, "slot.sav")), Some(&0));
+        assert_eq!(counts[&hash_of(&second, "slot.sav")], 1);
+
+        // 损坏索引中的计数并删除一个条目，重建后恢复为准确值
+        let expected = refcounts(&gam_dir);
+        let index_path = gam_dir.join("objects").join("content").join("index");
+        let mut index: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&index_path).unwrap()).unwrap();
+        let entries = index["entries"].as_object_mut().unwrap();
+        entries.values_mut().for_each(|e| e["refcount"] = 7.into());
+        entries.remove(&shared);
+        std::fs::write(&index_path, serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_ne!(refcounts(&gam_dir), expected);
+
+        handle_index_rebuild(&gam_dir).unwrap();
+        assert_eq!(refcounts(&gam_dir), expected);
+    }
+
+    #[test]
+    fn test_metadata_only_changes_are_saved() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("slot.sav"), b"data").unwrap();
+        let first = save(&gam_dir, "first");
+
+        // 内容不变时不创建新快照
+        assert_eq!(save(&gam_dir, "unchanged").id, first.id);
+
+        // 新增空目录
+        std::fs::create_dir(temp_dir.path().join("empty")).unwrap();
+        let second = save(&gam_dir, "empty dir");
+        assert_ne!(second.id, first.id);
+
+        // 只修改权限
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = temp_dir.path().join("slot.sav");
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+            let third = save(&gam_dir, "mode");
+            assert_ne!(third.id, second.id);
+        }
+    }
+
+    #[cfg(unix)]
+    /// 工作目录相对快照的变更路径
+    struct WorkingDiff {
+        id: String,
+        added: Vec<String>,
+        deleted: Vec<String>,
+        modified: Vec<String>,
+    }
+
+    /// 比较 `id1`（为空时为 HEAD）与工作目录，尚无 HEAD 时返回空
+    fn diff_working(gam_dir: &PathBuf, id1: Option<&str>) -> Option<WorkingDiff> {
+        let repo = open(gam_dir);
+        let sides = diff_sides(&repo, id1, None).unwrap()?;
+        assert!(sides.new.is_none());
+
+        let changes = ChangeSet::compare(&sides.old.files, &sides.working);
+        let paths = |files: &[&FileEntry]| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| f.path.to_string_lossy().to_string())
+                .collect()
+        };
+        let modified: Vec<&FileEntry> = changes.modified.iter().map(|(_, new)| *new).collect();
+        Some(WorkingDiff {
+            id: sides.old.id.clone(),
+            added: paths(&changes.added),
+            deleted: paths(&changes.deleted),
+            modified: paths(&modified),
+        })
+    }
+
+    #[test]
+    fn test_diff_working_tree_against_head() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("slot1.sav"), b"first").unwrap();
+        std::fs::write(temp_dir.path().join("slot2.sav"), b"second").unwrap();
+        let head = save(&gam_dir, "first");
+
+        // 工作目录与 HEAD 一致
+        let diff = diff_working(&gam_dir, None).unwrap();
+        assert_eq!(diff.id, head.id);
+        assert!(diff.added.is_empty() && diff.deleted.is_empty() && diff.modified.is_empty());
+
+        std::fs::write(temp_dir.path().join("slot1.sav"), b"changed").unwrap();
+        std::fs::remove_file(temp_dir.path().join("slot2.sav")).unwrap();
+        std::fs::write(temp_dir.path().join("slot3.sav"), b"third").unwrap();
+        let diff = diff_working(&gam_dir, None).unwrap();
+        assert_eq!(diff.added, vec!["slot3.sav"]);
+        assert_eq!(diff.deleted, vec!["slot2.sav"]);
+        assert_eq!(diff.modified, vec!["slot1.sav"]);
+        handle_diff(&gam_dir, None, None, true, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_diff_working_tree_against_snapshot() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("slot.sav"), b"first").unwrap();
+        let first = save(&gam_dir, "first");
+        std::fs::write(temp_dir.path().join("slot.sav"), b"second").unwrap();
+        std::fs::write(temp_dir.path().join("extra.sav"), b"extra").unwrap();
+        save(&gam_dir, "second");
+
+        // 与 HEAD 相比没有变化，与较早的快照相比有变化
+        let diff = diff_working(&gam_dir, None).unwrap();
+        assert!(diff.added.is_empty() && diff.modified.is_empty());
+
+        let diff = diff_working(&gam_dir, Some(&first.id[..8])).unwrap();
+        assert_eq!(diff.id, first.id);
+        assert_eq!(diff.added, vec!["extra.sav"]);
+        assert!(diff.deleted.is_empty());
+        assert_eq!(diff.modified, vec!["slot.sav"]);
+        handle_diff(&gam_dir, Some(&first.id), None, false, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_diff_without_head() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("slot.sav"), b"data").unwrap();
+
+        assert!(diff_working(&gam_dir, None).is_none());
+        handle_diff(&gam_dir, None, None, false, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_diff_working_tree_applies_ignore_rules() {
+        let (temp_dir, gam_dir) = init_repo();
+        std::fs::write(temp_dir.path().join("slot.sav"), b"data").unwrap();
+        save(&gam_dir, "first");
+
+        handle_ignore_add(&gam_dir, "*.tmp").unwrap();
+        handle_ignore_add(&gam_dir, "cache/").unwrap();
+        std::fs::write(temp_dir.path().join("autosave.tmp"), b"scratch").unwrap();
+        std::fs::create_dir(temp_dir.path().join("cache")).unwrap();
+        std::fs::write(temp_dir.path().join("cache").join("thumb.png"), b"png").unwrap();
+        std::fs::write(temp_dir.path().join("slot2.sav"), b"more").unwrap();
+
+        let diff = diff_working(&gam_dir, None).unwrap();
+        assert_eq!(diff.added, vec!["slot2.sav"]);
+        assert!(diff.deleted.is_empty() && diff.modified.is_empty());
+    }
+
+    #[test]
+    fn test_restore_write_only_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, gam_dir) = init_repo();
+        let path = temp_dir.path().join("slot.sav");
+        std::fs::write(&path, b"data").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o200)).unwrap();
+        let snapshot = save(&gam_dir, "write only");
+        let entry = snapshot
+            .files
+            .iter()
+            .find(|f| f.path == Path::new("slot.sav"))
+            .unwrap();
+        assert_eq!(entry.mode, Some(0o200));
+
+        std::fs::remove_file(&path).unwrap();
+        let repo = open(&gam_dir);
+        restore_entry(&repo.content_store, entry, &path).unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o200);
+        let mtime = metadata
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(Some(mtime.as_nanos() as i64), entry.mtime);
+        assert_eq!(metadata.len(), 4);
+    }
+
+    #[test]
+    fn test_restore_truncated_object_fails() {
+        let (temp_dir, gam_dir) = init_repo();
+        let path = temp_dir.path().join("slot.sav");
+        std::fs::write(&path, b"complete save data").unwrap();
+        let snapshot = save(&gam_dir, "first");
+        let entry = &snapshot.files[0];
+
+        // 对象被截断时恢复报告数据错误，而不是留下不完整的文件
+        let repo = open(&gam_dir);
+        repo.storage
+            .backend()
+            .put(&object_key("content", &entry.hash), b"short")
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match restore_entry(&repo.content_store, entry, &path) {
+            Err(crate::core::error::GamError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
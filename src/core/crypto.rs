@@ -0,0 +1,583 @@
+//! 仓库加密模块
+//!
+//! 加密仓库使用随机生成的主密钥加密内容对象和快照。主密钥本身由口令（Argon2id）或密钥文件
+//! 派生的密钥加密后保存在 `.gam/keys` 中，更换口令只需重新加密主密钥，对象不必重写。
+//!
+//! 对象按 64 KiB 分段使用 XChaCha20-Poly1305 加密，附加数据绑定对象名，
+//! 对象被篡改、截断或互相调换都会在读取时发现。
+//! 内容地址改用由主密钥派生的带密钥哈希，相同内容仍得到相同地址，去重不受影响
+
+use crate::core::error::{GamError, GamResult};
+use crate::utils::FileUtils;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload, rand_core::RngCore};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// 密钥文件名（位于 `.gam` 下）
+const KEYS_FILE: &str = "keys";
+/// 加密对象的魔数
+const OBJECT_MAGIC: &[u8; 8] = b"GAMCRYPT";
+/// 每个对象随机生成的 nonce 前缀长度，后 8 字节为分段序号
+const NONCE_PREFIX_LEN: usize = 16;
+/// 明文分段大小
+const SEGMENT_SIZE: usize = 64 * 1024;
+/// 每段的认证标签长度
+const TAG_LEN: usize = 16;
+/// 最后一段的序号带有该标志，截断的对象无法通过认证
+const LAST_SEGMENT: u64 = 1 << 63;
+
+/// Argon2id 默认参数（内存 KiB、迭代次数、并行度）
+const ARGON2_M_COST: u32 = 19 * 1024;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+
+/// 派生子密钥使用的上下文
+const OBJECT_KEY_CONTEXT: &str = "game-archive-manager object encryption key v1";
+const HASH_KEY_CONTEXT: &str = "game-archive-manager content address key v1";
+const KEY_FILE_CONTEXT: &str = "game-archive-manager key file v1";
+/// 加密主密钥时的附加数据
+const MASTER_KEY_AAD: &[u8] = b"gam master key";
+
+/// 仓库主密钥及其派生的子密钥
+#[derive(Clone)]
+pub struct RepoKey {
+    master: Zeroizing<[u8; 32]>,
+    hash_key: Zeroizing<[u8; 32]>,
+    cipher: XChaCha20Poly1305,
+}
+
+impl RepoKey {
+    /// 随机生成新的主密钥
+    pub fn generate() -> Self {
+        let mut master = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(master.as_mut());
+        Self::from_master(master)
+    }
+
+    fn from_master(master: Zeroizing<[u8; 32]>) -> Self {
+        let object_key = Zeroizing::new(blake3::derive_key(OBJECT_KEY_CONTEXT, master.as_ref()));
+        let hash_key = Zeroizing::new(blake3::derive_key(HASH_KEY_CONTEXT, master.as_ref()));
+        RepoKey {
+            cipher: XChaCha20Poly1305::new(object_key.as_ref().into()),
+            hash_key,
+            master,
+        }
+    }
+
+    /// 计算内容地址使用的哈希密钥
+    pub fn hash_key(&self) -> &[u8; 32] {
+        &self.hash_key
+    }
+
+    /// 加密内存中的数据
+    pub fn encrypt(&self, aad: &str, data: &[u8]) -> GamResult<Vec<u8>> {
+        let capacity = encrypted_len(data.len() as u64) as usize;
+        let mut writer = self.encryptor(aad, Vec::with_capacity(capacity))?;
+        writer.write_all(data)?;
+        Ok(writer.finish()?)
+    }
+
+    /// 解密内存中的数据
+    pub fn decrypt(&self, aad: &str, data: &[u8]) -> GamResult<Vec<u8>> {
+        let mut plain = Vec::with_capacity(data.len());
+        self.decryptor(aad, data)?.read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    /// 创建加密写入器，写入完成后必须调用 [`EncryptingWriter::finish`]
+    pub fn encryptor<W: Write>(&self, aad: &str, mut inner: W) -> io::Result<EncryptingWriter<W>> {
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        OsRng.fill_bytes(&mut prefix);
+        inner.write_all(OBJECT_MAGIC)?;
+        inner.write_all(&prefix)?;
+        Ok(EncryptingWriter {
+            inner,
+            cipher: self.cipher.clone(),
+            aad: aad.as_bytes().to_vec(),
+            prefix,
+            counter: 0,
+            buffer: Vec::with_capacity(SEGMENT_SIZE),
+        })
+    }
+
+    /// 创建解密读取器
+    pub fn decryptor<R: Read>(&self, aad: &str, mut inner: R) -> io::Result<DecryptingReader<R>> {
+        let mut header = [0u8; OBJECT_MAGIC.len() + NONCE_PREFIX_LEN];
+        inner.read_exact(&mut header).map_err(|_| not_encrypted())?;
+        if &header[..OBJECT_MAGIC.len()] != OBJECT_MAGIC {
+            return Err(not_encrypted());
+        }
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        prefix.copy_from_slice(&header[OBJECT_MAGIC.len()..]);
+        Ok(DecryptingReader {
+            inner,
+            cipher: self.cipher.clone(),
+            aad: aad.as_bytes().to_vec(),
+            prefix,
+            counter: 0,
+            plain: Vec::new(),
+            pos: 0,
+            carry: None,
+            done: false,
+        })
+    }
+}
+
+/// 明文长度为 `len` 的数据加密后的长度
+pub fn encrypted_len(len: u64) -> u64 {
+    let segments = len.div_ceil(SEGMENT_SIZE as u64).max(1);
+    (OBJECT_MAGIC.len() + NONCE_PREFIX_LEN) as u64 + len + segments * TAG_LEN as u64
+}
+
+fn segment_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u64, last: bool) -> XNonce {
+    let mut nonce = [0u8; 24];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    let counter = if last { counter | LAST_SEGMENT } else { counter };
+    nonce[NONCE_PREFIX_LEN..].copy_from_slice(&counter.to_be_bytes());
+    nonce.into()
+}
+
+fn not_encrypted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "object is not encrypted")
+}
+
+fn decryption_failed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "decryption failed: wrong key or corrupted object",
+    )
+}
+
+/// 分段加密写入器
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    cipher: XChaCha20Poly1305,
+    aad: Vec<u8>,
+    prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u64,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    fn seal_segment(&mut self, last: bool) -> io::Result<()> {
+        let nonce = segment_nonce(&self.prefix, self.counter, last);
+        let sealed = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &self.buffer,
+                    aad: &self.aad,
+                },
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.inner.write_all(&sealed)?;
+        self.buffer.clear();
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// 写入最后一段并返回内部写入器
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal_segment(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // 缓冲区满且还有后续数据时，该段一定不是最后一段
+        if self.buffer.len() == SEGMENT_SIZE && !buf.is_empty() {
+            self.seal_segment(false)?;
+        }
+        let n = buf.len().min(SEGMENT_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 分段解密读取器
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    cipher: XChaCha20Poly1305,
+    aad: Vec<u8>,
+    prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u64,
+    plain: Vec<u8>,
+    pos: usize,
+    /// 判断是否为最后一段时预读的字节
+    carry: Option<u8>,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    fn open_segment(&mut self) -> io::Result<()> {
+        let full = SEGMENT_SIZE + TAG_LEN;
+        let mut sealed = Vec::with_capacity(full);
+        sealed.extend(self.carry.take());
+        let missing = (full - sealed.len()) as u64;
+        (&mut self.inner).take(missing).read_to_end(&mut sealed)?;
+
+        let last = if sealed.len() < full {
+            true
+        } else {
+            let mut next = [0u8; 1];
+            if self.inner.read(&mut next)? == 0 {
+                true
+            } else {
+                self.carry = Some(next[0]);
+                false
+            }
+        };
+        if sealed.len() < TAG_LEN {
+            return Err(decryption_failed());
+        }
+
+        let nonce = segment_nonce(&self.prefix, self.counter, last);
+        self.plain = self
+            .cipher
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: &sealed,
+                    aad: &self.aad,
+                },
+            )
+            .map_err(|_| decryption_failed())?;
+        self.pos = 0;
+        self.counter += 1;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.open_segment()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// 解锁主密钥的凭据
+pub enum Credential {
+    /// 口令
+    Passphrase(Zeroizing<String>),
+    /// 密钥文件的内容
+    KeyFile(Zeroizing<Vec<u8>>),
+}
+
+impl Credential {
+    /// 口令凭据
+    pub fn passphrase(passphrase: String) -> Self {
+        Credential::Passphrase(Zeroizing::new(passphrase))
+    }
+
+    /// 读取密钥文件
+    pub fn read_key_file(path: &Path) -> GamResult<Self> {
+        let content = Zeroizing::new(fs::read(path).map_err(|e| {
+            GamError::Encryption(format!("cannot read key file {}: {}", path.display(), e))
+        })?);
+        if content.is_empty() {
+            return Err(GamError::Encryption(format!(
+                "key file {} is empty",
+                path.display()
+            )));
+        }
+        Ok(Credential::KeyFile(content))
+    }
+
+    /// 生成新的随机密钥文件（仅当前用户可读）
+    pub fn create_key_file(path: &Path) -> GamResult<Self> {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(secret.as_mut());
+        let content = Zeroizing::new(format!("{}\n", to_hex(secret.as_ref())));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(Credential::KeyFile(Zeroizing::new(content.as_bytes().to_vec())))
+    }
+}
+
+/// 主密钥的保护方式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Kdf {
+    /// 由口令经 Argon2id 派生
+    Argon2id {
+        salt: String,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    /// 由密钥文件内容派生
+    KeyFile,
+}
+
+impl Kdf {
+    /// 由凭据派生加密主密钥的密钥
+    fn derive(&self, credential: &Credential) -> GamResult<Zeroizing<[u8; 32]>> {
+        let mut kek = Zeroizing::new([0u8; 32]);
+        match (self, credential) {
+            (
+                Kdf::Argon2id {
+                    salt,
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                },
+                Credential::Passphrase(passphrase),
+            ) => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(32))
+                    .map_err(|e| GamError::Encryption(format!("invalid argon2 parameters: {}", e)))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), &from_hex(salt)?, kek.as_mut())
+                    .map_err(|e| GamError::Encryption(format!("key derivation failed: {}", e)))?;
+            }
+            (Kdf::KeyFile, Credential::KeyFile(content)) => {
+                *kek = blake3::derive_key(KEY_FILE_CONTEXT, content);
+            }
+            (Kdf::KeyFile, Credential::Passphrase(_)) => {
+                return Err(GamError::Encryption(
+                    "repository is protected by a key file, not a passphrase".to_string(),
+                ));
+            }
+            (Kdf::Argon2id { .. }, Credential::KeyFile(_)) => {
+                return Err(GamError::Encryption(
+                    "repository is protected by a passphrase, not a key file".to_string(),
+                ));
+            }
+        }
+        Ok(kek)
+    }
+}
+
+/// `.gam/keys`：被加密保存的主密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyStore {
+    /// 密钥文件格式版本
+    pub version: u32,
+    /// 主密钥的保护方式
+    pub kdf: Kdf,
+    nonce: String,
+    wrapped_key: String,
+}
+
+impl KeyStore {
+    /// 仓库是否加密
+    pub fn exists(gam_dir: &Path) -> bool {
+        gam_dir.join(KEYS_FILE).exists()
+    }
+
+    /// 读取密钥文件
+    pub fn read(gam_dir: &Path) -> GamResult<Self> {
+        let content = fs::read(gam_dir.join(KEYS_FILE))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// 写入密钥文件
+    pub fn save(&self, gam_dir: &Path) -> GamResult<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        FileUtils::atomic_write(&gam_dir.join(KEYS_FILE), &content)?;
+        Ok(())
+    }
+
+    /// 使用凭据加密主密钥
+    pub fn wrap(key: &RepoKey, credential: &Credential) -> GamResult<Self> {
+        Self::wrap_with(key, credential, ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST)
+    }
+
+    fn wrap_with(
+        key: &RepoKey,
+        credential: &Credential,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    ) -> GamResult<Self> {
+        let kdf = match credential {
+            Credential::Passphrase(_) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                Kdf::Argon2id {
+                    salt: to_hex(&salt),
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                }
+            }
+            Credential::KeyFile(_) => Kdf::KeyFile,
+        };
+
+        let kek = kdf.derive(credential)?;
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let wrapped = XChaCha20Poly1305::new(kek.as_ref().into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: key.master.as_ref(),
+                    aad: MASTER_KEY_AAD,
+                },
+            )
+            .map_err(|_| GamError::Encryption("failed to wrap master key".to_string()))?;
+
+        Ok(KeyStore {
+            version: 1,
+            kdf,
+            nonce: to_hex(&nonce),
+            wrapped_key: to_hex(&wrapped),
+        })
+    }
+
+    /// 使用凭据解密主密钥
+    pub fn unwrap(&self, credential: &Credential) -> GamResult<RepoKey> {
+        let kek = self.kdf.derive(credential)?;
+        let nonce: [u8; 24] = from_hex(&self.nonce)?
+            .try_into()
+            .map_err(|_| GamError::Encryption("corrupt key nonce".to_string()))?;
+        let master = Zeroizing::new(
+            XChaCha20Poly1305::new(kek.as_ref().into())
+                .decrypt(
+                    &nonce.into(),
+                    Payload {
+                        msg: &from_hex(&self.wrapped_key)?,
+                        aad: MASTER_KEY_AAD,
+                    },
+                )
+                .map_err(|_| GamError::WrongKey)?,
+        );
+
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        if master.len() != bytes.len() {
+            return Err(GamError::Encryption("corrupt master key".to_string()));
+        }
+        bytes.copy_from_slice(&master);
+        Ok(RepoKey::from_master(bytes))
+    }
+
+    /// 是否使用口令保护
+    pub fn uses_passphrase(&self) -> bool {
+        matches!(self.kdf, Kdf::Argon2id { .. })
+    }
+}
+
+/// 生成随机标识（16 字节，十六进制表示）
+pub fn random_id() -> String {
+    let mut id = [0u8; 16];
+    OsRng.fill_bytes(&mut id);
+    to_hex(&id)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> GamResult<Vec<u8>> {
+    let invalid = || GamError::Encryption(format!("invalid hex string: {}", hex));
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            let byte = hex.get(i..i + 2).ok_or_else(invalid)?;
+            u8::from_str_radix(byte, 16).map_err(|_| invalid())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passphrase(s: &str) -> Credential {
+        Credential::passphrase(s.to_string())
+    }
+
+    #[test]
+    fn test_roundtrip_across_segments() {
+        let key = RepoKey::generate();
+        for len in [0, 1, SEGMENT_SIZE - 1, SEGMENT_SIZE, SEGMENT_SIZE + 1, SEGMENT_SIZE * 3] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let sealed = key.encrypt("content:abc", &data).unwrap();
+            assert_eq!(sealed.len() as u64, encrypted_len(len as u64));
+            assert_eq!(key.decrypt("content:abc", &sealed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let key = RepoKey::generate();
+        let data = vec![7u8; SEGMENT_SIZE * 2 + 10];
+        let sealed = key.encrypt("content:abc", &data).unwrap();
+
+        // 附加数据不同（对象被调换）
+        assert!(key.decrypt("content:abd", &sealed).is_err());
+        // 内容被修改
+        let mut flipped = sealed.clone();
+        flipped[100] ^= 1;
+        assert!(key.decrypt("content:abc", &flipped).is_err());
+        // 在分段边界截断
+        let truncated = &sealed[..24 + SEGMENT_SIZE + TAG_LEN];
+        assert!(key.decrypt("content:abc", truncated).is_err());
+        // 其他密钥
+        assert!(RepoKey::generate().decrypt("content:abc", &sealed).is_err());
+        // 未加密的数据
+        assert!(key.decrypt("content:abc", b"{\"id\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_wrap_and_unwrap_master_key() {
+        let key = RepoKey::generate();
+        let store = KeyStore::wrap_with(&key, &passphrase("correct horse"), 64, 1, 1).unwrap();
+
+        let unlocked = store.unwrap(&passphrase("correct horse")).unwrap();
+        assert_eq!(unlocked.hash_key(), key.hash_key());
+        let sealed = key.encrypt("snapshot:1", b"data").unwrap();
+        assert_eq!(unlocked.decrypt("snapshot:1", &sealed).unwrap(), b"data");
+
+        assert!(matches!(store.unwrap(&passphrase("wrong")), Err(GamError::WrongKey)));
+        assert!(store.unwrap(&Credential::KeyFile(Zeroizing::new(b"x".to_vec()))).is_err());
+    }
+
+    #[test]
+    fn test_key_file_credential() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("gam.key");
+        let created = Credential::create_key_file(&path).unwrap();
+        assert!(Credential::create_key_file(&path).is_err());
+
+        let key = RepoKey::generate();
+        let store = KeyStore::wrap(&key, &created).unwrap();
+        store.save(temp_dir.path()).unwrap();
+        assert!(KeyStore::exists(temp_dir.path()));
+
+        let loaded = KeyStore::read(temp_dir.path()).unwrap();
+        assert!(!loaded.uses_passphrase());
+        let unlocked = loaded.unwrap(&Credential::read_key_file(&path).unwrap()).unwrap();
+        assert_eq!(unlocked.hash_key(), key.hash_key());
+    }
+}
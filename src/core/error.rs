@@ -96,6 +96,50 @@ pub enum GamError {
     #[error("TOML parse error: {0}")]
     TomlParse(String),
 
+    /// 存在未完成的操作（预写日志未清除）
+    #[error("Unfinished operation found: {0}. Run 'gam doctor --fix' to recover.")]
+    IncompleteOperation(String),
+
+    /// 快照文件清单与内容根哈希不符
+    #[error("Snapshot manifest does not match its content hash: {0}")]
+    ManifestMismatch(String),
+
+    /// 恢复后的目录与快照内容不一致
+    #[error("Restored files do not match snapshot {0}")]
+    VerificationFailed(String),
+
+    /// 完整性检查发现问题
+    #[error("Integrity check found {0} problem(s)")]
+    IntegrityCheckFailed(usize),
+
+    /// 仓库使用了本程序不支持的格式或必需特性
+    #[error("Repository uses an unsupported format ({0}). Upgrade gam to modify this repository.")]
+    UnsupportedFormat(String),
+
+    /// 仓库格式过旧，需要先升级
+    #[error("Repository format version {0} is outdated. Run 'gam upgrade' first.")]
+    OutdatedFormat(u32),
+
+    /// 口令或密钥文件无法解开仓库主密钥
+    #[error("Wrong passphrase or key file")]
+    WrongKey,
+
+    /// 加密相关错误
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    /// 对象存储目录不可用（未挂载、缺少标记或属于其他仓库）
+    #[error("Object storage is unavailable: {0}")]
+    StorageUnavailable(String),
+
+    /// 文本转换命令执行失败
+    #[error("Text conversion failed: {0}")]
+    TextConv(String),
+
+    /// 仓库被其他 gam 进程锁定
+    #[error("Repository is locked by another gam process ({0}). Use --wait <secs> to wait for it.")]
+    Locked(String),
+
     /// 未知错误
     #[error("Unknown error: {0}")]
     Unknown(String),
@@ -0,0 +1,168 @@
+//! 仓库格式描述模块
+//!
+//! `.gam/format` 记录仓库的格式版本和使用的特性。必需特性是正确写入仓库所必须理解的，
+//! 不认识其中任何一项的程序拒绝修改仓库；可选特性不认识时可以安全忽略
+
+use crate::core::error::{GamError, GamResult};
+use crate::utils::FileUtils;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// 格式描述文件名
+const FORMAT_FILE: &str = "format";
+
+/// 当前程序写入的格式版本
+///
+/// - 1: 快照 ID 由元数据的 Debug 格式和本地时间计算
+/// - 2: 快照 ID 由规范序列化计算，版本记录在配置文件的 `core.format_version` 中
+/// - 3: 引入 `.gam/format` 描述文件
+pub const CURRENT_VERSION: u32 = 3;
+
+/// 特性：快照 ID 由规范序列化计算
+pub const FEATURE_CANONICAL_IDS: &str = "canonical-snapshot-ids";
+/// 特性：内容哈希使用 BLAKE3
+pub const FEATURE_BLAKE3: &str = "blake3";
+/// 特性：对象和快照加密保存
+pub const FEATURE_ENCRYPTION: &str = "encryption";
+
+/// 本程序理解的特性
+const KNOWN_FEATURES: &[&str] = &[FEATURE_CANONICAL_IDS, FEATURE_BLAKE3, FEATURE_ENCRYPTION];
+
+/// 仓库格式描述
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoFormat {
+    /// 格式版本
+    pub version: u32,
+    /// 必需特性
+    #[serde(default)]
+    pub required: BTreeSet<String>,
+    /// 可选特性
+    #[serde(default)]
+    pub optional: BTreeSet<String>,
+}
+
+impl RepoFormat {
+    /// 新仓库使用的格式
+    pub fn current() -> Self {
+        Self::legacy(CURRENT_VERSION)
+    }
+
+    /// 没有描述文件的旧仓库的格式
+    pub fn legacy(version: u32) -> Self {
+        let mut required = BTreeSet::new();
+        if version >= 2 {
+            required.insert(FEATURE_CANONICAL_IDS.to_string());
+        }
+        RepoFormat {
+            version,
+            required,
+            optional: BTreeSet::new(),
+        }
+    }
+
+    /// 读取格式描述（文件不存在时为空）
+    pub fn read(gam_dir: &Path) -> GamResult<Option<Self>> {
+        let path = gam_dir.join(FORMAT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read(&path)?;
+        Ok(Some(serde_json::from_slice(&content)?))
+    }
+
+    /// 写入格式描述
+    pub fn save(&self, gam_dir: &Path) -> GamResult<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        FileUtils::atomic_write(&gam_dir.join(FORMAT_FILE), &content)?;
+        Ok(())
+    }
+
+    /// 是否使用了指定特性
+    pub fn has(&self, feature: &str) -> bool {
+        self.required.contains(feature) || self.optional.contains(feature)
+    }
+
+    /// 添加必需特性，返回是否有变化
+    pub fn require(&mut self, feature: &str) -> bool {
+        self.required.insert(feature.to_string())
+    }
+
+    /// 移除特性，返回是否有变化
+    pub fn remove(&mut self, feature: &str) -> bool {
+        self.required.remove(feature) | self.optional.remove(feature)
+    }
+
+    /// 本程序无法安全写入的原因（版本更新或存在未知的必需特性）
+    pub fn unsupported(&self) -> Option<String> {
+        let unknown: Vec<&str> = self
+            .required
+            .iter()
+            .map(|f| f.as_str())
+            .filter(|f| !KNOWN_FEATURES.contains(f))
+            .collect();
+
+        if self.version > CURRENT_VERSION {
+            Some(format!("format version {}", self.version))
+        } else if !unknown.is_empty() {
+            Some(unknown.join(", "))
+        } else {
+            None
+        }
+    }
+
+    /// 检查本程序能否写入仓库
+    pub fn check_writable(&self) -> GamResult<()> {
+        match self.unsupported() {
+            Some(reason) => Err(GamError::UnsupportedFormat(reason)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert!(RepoFormat::read(temp_dir.path()).unwrap().is_none());
+
+        let mut format = RepoFormat::current();
+        assert!(format.has(FEATURE_CANONICAL_IDS));
+        assert!(format.require(FEATURE_BLAKE3));
+        assert!(!format.require(FEATURE_BLAKE3));
+        format.save(temp_dir.path()).unwrap();
+
+        let loaded = RepoFormat::read(temp_dir.path()).unwrap().unwrap();
+        assert_eq!(loaded, format);
+        assert!(loaded.check_writable().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_required_feature_blocks_writes() {
+        let mut format = RepoFormat::current();
+        format.optional.insert("future-cache".to_string());
+        assert!(format.check_writable().is_ok());
+
+        format.required.insert("future-layout".to_string());
+        assert!(matches!(
+            format.check_writable(),
+            Err(GamError::UnsupportedFormat(reason)) if reason == "future-layout"
+        ));
+
+        let newer = RepoFormat {
+            version: CURRENT_VERSION + 1,
+            ..RepoFormat::current()
+        };
+        assert!(newer.check_writable().is_err());
+    }
+
+    #[test]
+    fn test_legacy_format() {
+        assert!(!RepoFormat::legacy(1).has(FEATURE_CANONICAL_IDS));
+        assert!(RepoFormat::legacy(2).has(FEATURE_CANONICAL_IDS));
+    }
+}
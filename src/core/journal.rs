@@ -0,0 +1,134 @@
+//! 预写日志模块
+//!
+//! 多步写入操作（如快照保存）开始前先记录日志，完成后清除。
+//! 进程中途崩溃时日志会保留下来，由 `gam doctor --fix` 据此回滚或完成操作
+
+use crate::core::error::GamResult;
+use crate::utils::FileUtils;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 日志记录的操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOperation {
+    /// 保存快照
+    SnapshotSave,
+}
+
+impl std::fmt::Display for JournalOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalOperation::SnapshotSave => write!(f, "snapshot save"),
+        }
+    }
+}
+
+/// 操作进行到的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStage {
+    /// 正在写入内容对象，快照文件尚未写入（恢复时回滚）
+    Storing,
+    /// 快照文件已写入，正在更新时间线引用（恢复时补完）
+    Committing,
+}
+
+/// 日志条目
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// 操作类型
+    pub operation: JournalOperation,
+    /// 当前阶段
+    pub stage: JournalStage,
+    /// 目标时间线
+    pub timeline: String,
+    /// 操作开始时时间线指向的快照
+    pub parent: Option<String>,
+    /// 已写入的快照 ID
+    pub snapshot_id: Option<String>,
+    /// 开始时间
+    pub started_at: DateTime<Local>,
+}
+
+impl JournalEntry {
+    /// 创建快照保存操作的日志条目
+    pub fn snapshot_save(timeline: &str, parent: Option<&str>) -> Self {
+        JournalEntry {
+            operation: JournalOperation::SnapshotSave,
+            stage: JournalStage::Storing,
+            timeline: timeline.to_string(),
+            parent: parent.map(|p| p.to_string()),
+            snapshot_id: None,
+            started_at: Local::now(),
+        }
+    }
+}
+
+/// 预写日志（`.gam/journal`）
+#[derive(Debug)]
+pub struct Journal {
+    /// 日志文件路径
+    path: PathBuf,
+}
+
+impl Journal {
+    /// 创建预写日志
+    pub fn new(gam_dir: &Path) -> Self {
+        Journal {
+            path: gam_dir.join("journal"),
+        }
+    }
+
+    /// 读取未完成的操作
+    pub fn pending(&self) -> GamResult<Option<JournalEntry>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read(&self.path)?;
+        Ok(Some(serde_json::from_slice(&content)?))
+    }
+
+    /// 记录操作（开始或进入新阶段时调用）
+    pub fn record(&self, entry: &JournalEntry) -> GamResult<()> {
+        let content = serde_json::to_vec_pretty(entry)?;
+        FileUtils::atomic_write(&self.path, &content)?;
+        Ok(())
+    }
+
+    /// 操作完成，清除日志
+    pub fn finish(&self) -> GamResult<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_lifecycle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(temp_dir.path());
+        assert!(journal.pending().unwrap().is_none());
+
+        let mut entry = JournalEntry::snapshot_save("main", Some("abc"));
+        journal.record(&entry).unwrap();
+        assert_eq!(journal.pending().unwrap(), Some(entry.clone()));
+
+        entry.stage = JournalStage::Committing;
+        entry.snapshot_id = Some("def".to_string());
+        journal.record(&entry).unwrap();
+        let pending = journal.pending().unwrap().unwrap();
+        assert_eq!(pending.stage, JournalStage::Committing);
+        assert_eq!(pending.snapshot_id.as_deref(), Some("def"));
+
+        journal.finish().unwrap();
+        assert!(journal.pending().unwrap().is_none());
+    }
+}
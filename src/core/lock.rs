@@ -0,0 +1,441 @@
+//! 仓库锁模块
+//!
+//! 修改仓库的命令持有排他锁（`.gam/lock`），只读命令持有共享锁（`.gam/readers/` 下的标记文件）。
+//! 锁文件记录持有者的 PID、主机名和获取时间，持有进程已退出或锁过期时视为失效并自动清除
+
+use crate::core::error::{GamError, GamResult};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// 排他锁文件名
+const LOCK_FILE: &str = "lock";
+/// 共享锁目录名
+const READERS_DIR: &str = "readers";
+/// 回收失效锁时持有的系统文件锁，保证同一时间只有一个进程在回收
+const RECLAIM_FILE: &str = "lock.reclaim";
+/// 无法确认持有进程状态（其他主机）时，锁在该时长后视为失效
+const STALE_AFTER_HOURS: i64 = 12;
+/// 锁文件内容无法解析时，创建超过该秒数即视为失效（写入锁信息前崩溃）
+const UNREADABLE_GRACE_SECS: u64 = 10;
+/// 等待锁时的重试间隔
+const RETRY_INTERVAL_MS: u64 = 100;
+
+/// 获取锁时最多等待的秒数（由 `--wait` 设置，默认不等待）
+static LOCK_WAIT_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// 设置获取锁时最多等待的秒数
+pub fn set_lock_wait(secs: u64) {
+    LOCK_WAIT_SECS.store(secs, Ordering::Relaxed);
+}
+
+/// 锁类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockKind {
+    /// 排他锁（修改仓库）
+    Exclusive,
+    /// 共享锁（只读）
+    Shared,
+}
+
+/// 锁持有者信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockOwner {
+    /// 锁类型
+    pub kind: LockKind,
+    /// 进程 ID
+    pub pid: u32,
+    /// 主机名
+    pub hostname: String,
+    /// 获取时间
+    pub acquired_at: DateTime<Local>,
+}
+
+impl LockOwner {
+    /// 当前进程的持有者信息
+    fn current(kind: LockKind) -> Self {
+        LockOwner {
+            kind,
+            pid: std::process::id(),
+            hostname: hostname(),
+            acquired_at: Local::now(),
+        }
+    }
+
+    /// 判断锁是否已失效
+    ///
+    /// 同一主机上持有进程已退出时立即失效；其他主机的锁无法检查进程，按获取时间判断
+    pub fn is_stale(&self) -> bool {
+        if self.hostname == hostname() {
+            return !process_alive(self.pid);
+        }
+        Local::now() - self.acquired_at > Duration::hours(STALE_AFTER_HOURS)
+    }
+}
+
+impl std::fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "PID {} on {} since {}",
+            self.pid,
+            self.hostname,
+            self.acquired_at.format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+/// 仓库锁，离开作用域时自动释放
+#[derive(Debug)]
+pub struct RepoLock {
+    /// 本进程创建的锁文件（仓库不存在时为空）
+    path: Option<PathBuf>,
+}
+
+impl RepoLock {
+    /// 获取排他锁
+    pub fn exclusive(gam_dir: &Path) -> GamResult<Self> {
+        Self::acquire(gam_dir, LockKind::Exclusive)
+    }
+
+    /// 获取共享锁
+    pub fn shared(gam_dir: &Path) -> GamResult<Self> {
+        Self::acquire(gam_dir, LockKind::Shared)
+    }
+
+    /// 获取锁，被占用时按 `--wait` 设置重试
+    fn acquire(gam_dir: &Path, kind: LockKind) -> GamResult<Self> {
+        // 仓库不存在时不加锁，由命令自身报告错误
+        if !gam_dir.exists() {
+            return Ok(RepoLock { path: None });
+        }
+
+        let wait = std::time::Duration::from_secs(LOCK_WAIT_SECS.load(Ordering::Relaxed));
+        let start = Instant::now();
+        loop {
+            match Self::try_acquire(gam_dir, kind)? {
+                Ok(lock) => return Ok(lock),
+                Err(holder) if start.elapsed() >= wait => {
+                    return Err(GamError::Locked(holder.to_string()));
+                }
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(RETRY_INTERVAL_MS)),
+            }
+        }
+    }
+
+    /// 尝试获取一次锁，被占用时返回当前持有者
+    ///
+    /// 双方都在创建自己的锁文件之后再检查对方，因此排他锁与共享锁不会同时成立
+    fn try_acquire(gam_dir: &Path, kind: LockKind) -> GamResult<Result<Self, LockOwner>> {
+        let owner = LockOwner::current(kind);
+        let lock_path = gam_dir.join(LOCK_FILE);
+
+        match kind {
+            LockKind::Exclusive => {
+                if let Some(holder) = live_holder(gam_dir, &lock_path)? {
+                    return Ok(Err(holder));
+                }
+                if !create_lock_file(&lock_path, &owner)? {
+                    // 与其他进程竞争失败，下次重试时会读到对方的信息
+                    return Ok(Err(read_owner(&lock_path).unwrap_or(owner)));
+                }
+                let lock = RepoLock {
+                    path: Some(lock_path),
+                };
+                if let Some(reader) = live_readers(gam_dir)?.into_iter().next() {
+                    return Ok(Err(reader));
+                }
+                Ok(Ok(lock))
+            }
+            LockKind::Shared => {
+                if let Some(holder) = live_holder(gam_dir, &lock_path)? {
+                    return Ok(Err(holder));
+                }
+                let readers = gam_dir.join(READERS_DIR);
+                fs::create_dir_all(&readers)?;
+                let reader_path = readers.join(format!(
+                    "{}-{}-{}",
+                    owner.hostname,
+                    owner.pid,
+                    owner.acquired_at.timestamp_nanos_opt().unwrap_or_default()
+                ));
+                create_lock_file(&reader_path, &owner)?;
+                let lock = RepoLock {
+                    path: Some(reader_path),
+                };
+                if let Some(holder) = live_holder(gam_dir, &lock_path)? {
+                    return Ok(Err(holder));
+                }
+                Ok(Ok(lock))
+            }
+        }
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// 以独占方式创建锁文件并写入持有者信息，文件已存在时返回 false
+fn create_lock_file(path: &Path, owner: &LockOwner) -> GamResult<bool> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    file.write_all(&serde_json::to_vec(owner)?)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// 读取锁文件中的持有者信息
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    let content = fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// 返回仍然有效的锁持有者，失效的锁文件会被回收
+fn live_holder(gam_dir: &Path, path: &Path) -> GamResult<Option<LockOwner>> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    match serde_json::from_slice::<LockOwner>(&content).ok() {
+        Some(owner) if !owner.is_stale() => Ok(Some(owner)),
+        Some(_) => {
+            reclaim_stale(gam_dir, path, &content)?;
+            Ok(None)
+        }
+        None => {
+            // 锁文件刚创建、尚未写入内容时短暂不可读
+            let age = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            if age >= UNREADABLE_GRACE_SECS {
+                reclaim_stale(gam_dir, path, &content)?;
+                Ok(None)
+            } else {
+                Ok(Some(LockOwner {
+                    kind: LockKind::Exclusive,
+                    pid: 0,
+                    hostname: "unknown".to_string(),
+                    acquired_at: Local::now(),
+                }))
+            }
+        }
+    }
+}
+
+/// 返回所有仍然有效的共享锁持有者
+fn live_readers(gam_dir: &Path) -> GamResult<Vec<LockOwner>> {
+    let readers = gam_dir.join(READERS_DIR);
+    let mut owners = Vec::new();
+    if !readers.exists() {
+        return Ok(owners);
+    }
+
+    for entry in fs::read_dir(&readers)? {
+        if let Some(owner) = live_holder(gam_dir, &entry?.path())? {
+            owners.push(owner);
+        }
+    }
+    Ok(owners)
+}
+
+/// 回收失效的锁文件，`stale` 为判定失效时读到的内容
+///
+/// 判定与删除之间锁可能已被其他进程回收并重新获取，直接按路径删除会误删新锁。
+/// 因此回收者先取得 `lock.reclaim` 上的系统文件锁，再确认锁文件仍是失效的那份内容才删除；
+/// 失效锁的持有者已不在运行，回收者之间又互斥，确认之后锁文件不会再被替换
+fn reclaim_stale(gam_dir: &Path, path: &Path, stale: &[u8]) -> GamResult<()> {
+    let guard = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(gam_dir.join(RECLAIM_FILE))?;
+    guard.lock()?;
+
+    let result = match fs::read(path) {
+        Ok(content) if content == stale => fs::remove_file(path),
+        // 已被其他进程回收
+        _ => Ok(()),
+    };
+    guard.unlock()?;
+    result.map_err(Into::into)
+}
+
+/// 当前主机名
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if ret == 0 {
+            let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// 检查本机进程是否仍在运行
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    // 信号 0 只做存在性检查；EPERM 表示进程存在但属于其他用户
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// 检查本机进程是否仍在运行
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    if pid == std::process::id() {
+        return true;
+    }
+    let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if handle.is_null() {
+        // 拒绝访问表示进程存在但无权查询（例如属于其他用户或受保护）
+        return std::io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED as i32);
+    }
+    let mut code: DWORD = 0;
+    let ok = unsafe { GetExitCodeProcess(handle, &mut code) };
+    unsafe { CloseHandle(handle) };
+    // 无法取得退出码时保守地认为仍在运行
+    ok == 0 || code == STILL_ACTIVE
+}
+
+/// 检查本机进程是否仍在运行（无法检查时保守地认为仍在运行，依靠超时判断）
+#[cfg(not(any(unix, windows)))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_excludes_everyone() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let lock = RepoLock::exclusive(temp_dir.path()).unwrap();
+        assert!(matches!(
+            RepoLock::exclusive(temp_dir.path()),
+            Err(GamError::Locked(_))
+        ));
+        assert!(matches!(
+            RepoLock::shared(temp_dir.path()),
+            Err(GamError::Locked(_))
+        ));
+        drop(lock);
+        assert!(RepoLock::exclusive(temp_dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_shared_locks_coexist() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let first = RepoLock::shared(temp_dir.path()).unwrap();
+        let _second = RepoLock::shared(temp_dir.path()).unwrap();
+        assert!(RepoLock::exclusive(temp_dir.path()).is_err());
+        drop(first);
+        drop(_second);
+        assert!(RepoLock::exclusive(temp_dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_stale_lock_is_reclaimed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stale = LockOwner {
+            kind: LockKind::Exclusive,
+            pid: u32::MAX - 1,
+            hostname: "elsewhere".to_string(),
+            acquired_at: Local::now() - Duration::hours(STALE_AFTER_HOURS + 1),
+        };
+        fs::write(
+            temp_dir.path().join(LOCK_FILE),
+            serde_json::to_vec(&stale).unwrap(),
+        )
+        .unwrap();
+        assert!(RepoLock::exclusive(temp_dir.path()).is_ok());
+    }
+
+    fn write_stale_lock(path: &Path) -> Vec<u8> {
+        let stale = LockOwner {
+            kind: LockKind::Exclusive,
+            pid: u32::MAX - 1,
+            hostname: "elsewhere".to_string(),
+            acquired_at: Local::now() - Duration::hours(STALE_AFTER_HOURS + 1),
+        };
+        let content = serde_json::to_vec(&stale).unwrap();
+        fs::write(path, &content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_late_reclaimer_keeps_new_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let lock_path = temp_dir.path().join(LOCK_FILE);
+        let stale = write_stale_lock(&lock_path);
+
+        // 两个进程都读到了失效的锁，第一个回收后立即获取了新锁
+        reclaim_stale(temp_dir.path(), &lock_path, &stale).unwrap();
+        let lock = RepoLock::exclusive(temp_dir.path()).unwrap();
+
+        // 第二个进程随后回收，不能删除新锁
+        reclaim_stale(temp_dir.path(), &lock_path, &stale).unwrap();
+        assert!(lock_path.exists());
+        assert!(RepoLock::exclusive(temp_dir.path()).is_err());
+        drop(lock);
+    }
+
+    #[test]
+    fn test_concurrent_reclaimers_get_one_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_stale_lock(&temp_dir.path().join(LOCK_FILE));
+
+        let threads = 8;
+        let start = std::sync::Barrier::new(threads);
+        let done = std::sync::Barrier::new(threads);
+        let acquired = AtomicU64::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    start.wait();
+                    let lock = RepoLock::exclusive(temp_dir.path());
+                    if lock.is_ok() {
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                    }
+                    // 所有线程都尝试过之后才释放
+                    done.wait();
+                });
+            }
+        });
+        assert_eq!(acquired.load(Ordering::SeqCst), 1);
+    }
+}
@@ -3,28 +3,36 @@
 //! 提供 GAM 的核心数据结构和管理功能
 
 pub mod activity;
+pub mod changes;
 pub mod commands;
+pub mod crypto;
 pub mod error;
+pub mod format;
 pub mod ignore;
+pub mod journal;
+pub mod lock;
 pub mod messages;
+pub mod stat_cache;
 pub mod store;
 pub mod tag;
+pub mod textconv;
 pub mod types;
 
 pub use activity::{ActivityAction, ActivityEngine, ActivityEntry};
 pub use commands::{
-    handle_activity, handle_config, handle_diff, handle_doctor, handle_gc, handle_history,
+    handle_activity, handle_config, handle_diff, handle_doctor, handle_fsck, handle_gc, handle_history,
     handle_ignore_add, handle_ignore_check, handle_ignore_init, handle_ignore_list,
-    handle_ignore_remove, handle_init, handle_restore, handle_snapshot_delete,
+    handle_ignore_remove, handle_index_rebuild, handle_init, handle_key_change, handle_migrate_hash, handle_pack, handle_restore, handle_snapshot_delete,
     handle_snapshot_info, handle_snapshot_list, handle_snapshot_save, handle_snapshot_tag,
-    handle_status, handle_timeline_create, handle_timeline_current, handle_timeline_delete,
-    handle_timeline_list, handle_timeline_rename, handle_timeline_switch,
+    handle_status, handle_storage_move, handle_storage_show, handle_timeline_create, handle_timeline_current, handle_timeline_delete,
+    handle_timeline_list, handle_timeline_rename, handle_timeline_switch, handle_unpack,
+    handle_upgrade,
 };
 pub use error::{GamError, GamResult};
 pub use ignore::IgnoreEngine;
 pub use messages::{global_messages, MessageManager};
 pub use tag::TagStore;
 pub use types::{
-    Config, FileEntry, GamIgnoreConfig, IgnorePattern, PatternType, RetentionPolicy, Snapshot,
+    CompressionCodec, Config, EntryKind, FileEntry, GamIgnoreConfig, HashAlgorithm, IgnorePattern, PatternType, RetentionPolicy, Snapshot,
     StorageStrategy, Timeline,
 };
@@ -0,0 +1,177 @@
+//! 文件状态缓存模块
+//!
+//! `.gam/stat-cache` 记录上次扫描时每个存档文件的大小、修改时间、inode 和内容哈希。
+//! 这些信息都没有变化的文件可以直接使用缓存的哈希，不必重新读取。
+//! 缓存只是加速手段，读取失败或算法不符时视为空缓存
+
+use crate::core::error::GamResult;
+use crate::core::types::HashAlgorithm;
+use crate::utils::FileUtils;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 缓存文件名
+const CACHE_FILE: &str = "stat-cache";
+
+/// 修改时间距缓存写入不足该时长的文件不写入缓存
+///
+/// 文件系统的时间戳精度有限，在同一时间单位内再次修改的文件大小和修改时间都可能不变
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// 文件的状态信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    /// 文件大小
+    pub size: u64,
+    /// 修改时间（Unix 纪元起的纳秒数）
+    pub mtime: i64,
+    /// inode 编号（不支持的平台为 0）
+    pub inode: u64,
+}
+
+impl FileStat {
+    /// 从文件元数据提取状态信息
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+
+        FileStat {
+            size: metadata.len(),
+            mtime,
+            inode,
+        }
+    }
+}
+
+/// 缓存条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    stat: FileStat,
+    hash: String,
+}
+
+/// 文件状态缓存（键为使用 `/` 分隔的相对路径）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatCache {
+    /// 缓存中哈希使用的算法
+    algorithm: HashAlgorithm,
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl StatCache {
+    /// 创建空缓存
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        StatCache {
+            algorithm,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// 读取缓存，文件不存在、无法解析或算法不符时返回空缓存
+    pub fn load(gam_dir: &Path, algorithm: HashAlgorithm) -> Self {
+        std::fs::read(gam_dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<StatCache>(&content).ok())
+            .filter(|cache| cache.algorithm == algorithm)
+            .unwrap_or_else(|| Self::new(algorithm))
+    }
+
+    /// 写入缓存
+    pub fn save(&self, gam_dir: &Path) -> GamResult<()> {
+        let content = serde_json::to_vec(self)?;
+        FileUtils::atomic_write(&gam_dir.join(CACHE_FILE), &content)?;
+        Ok(())
+    }
+
+    /// 缓存中哈希使用的算法
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// 状态与缓存完全一致时返回缓存的哈希
+    pub fn lookup(&self, path: &str, stat: &FileStat) -> Option<&str> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.stat == *stat)
+            .map(|entry| entry.hash.as_str())
+    }
+
+    /// 记录文件的状态和哈希，刚修改过的文件不记录
+    pub fn insert(&mut self, path: String, stat: FileStat, hash: String) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+        if now - stat.mtime < RACY_WINDOW.as_nanos() as i64 {
+            return;
+        }
+        self.entries.insert(path, CacheEntry { stat, hash });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn old_stat(size: u64) -> FileStat {
+        FileStat {
+            size,
+            mtime: 1_600_000_000_000_000_000,
+            inode: 42,
+        }
+    }
+
+    #[test]
+    fn test_lookup_requires_identical_stat() {
+        let mut cache = StatCache::new(HashAlgorithm::Sha256);
+        cache.insert("save/a.sav".to_string(), old_stat(10), "abc".to_string());
+
+        assert_eq!(cache.lookup("save/a.sav", &old_stat(10)), Some("abc"));
+        assert_eq!(cache.lookup("save/a.sav", &old_stat(11)), None);
+        let moved = FileStat {
+            inode: 43,
+            ..old_stat(10)
+        };
+        assert_eq!(cache.lookup("save/a.sav", &moved), None);
+        assert_eq!(cache.lookup("save/b.sav", &old_stat(10)), None);
+    }
+
+    #[test]
+    fn test_recently_modified_files_are_not_cached() {
+        let mut cache = StatCache::new(HashAlgorithm::Sha256);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64;
+        let racy = FileStat {
+            mtime: now,
+            ..old_stat(10)
+        };
+        cache.insert("a".to_string(), racy, "abc".to_string());
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_load_discards_other_algorithm() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut cache = StatCache::new(HashAlgorithm::Sha256);
+        cache.insert("a".to_string(), old_stat(1), "abc".to_string());
+        cache.save(temp_dir.path()).unwrap();
+
+        assert_eq!(StatCache::load(temp_dir.path(), HashAlgorithm::Sha256).entries.len(), 1);
+        assert!(StatCache::load(temp_dir.path(), HashAlgorithm::Blake3).entries.is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! 存储后端模块
+//!
+//! 内容对象、快照和包文件都按键读写，键是以 `/` 分隔的相对路径，例如 `content/ab/cdef…`、
+//! `snapshot/index` 和 `pack/content/pack-….pack`。后端决定键对应的数据保存在哪里，
+//! 由 [`StorageLocation::backend`](super::StorageLocation::backend) 按存储位置创建
+
+use crate::core::error::{GamError, GamResult};
+use crate::core::store::location::MARKER_FILE;
+use crate::utils::FileUtils;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// 键对应数据的元信息
+#[derive(Debug, Clone, Copy)]
+pub struct KeyStat {
+    /// 数据长度
+    pub size: u64,
+    /// 最后写入时间（纳秒），无法获取时为 0
+    pub modified: u64,
+}
+
+/// 对象存储后端
+///
+/// 写入是原子的：读取方只会看到写入前或写入完成后的数据。写入过程中的临时数据不会出现在
+/// [`StorageBackend::list`] 的结果中
+pub trait StorageBackend: Send + Sync {
+    /// 检查后端当前是否可用
+    fn check(&self) -> GamResult<()>;
+
+    /// 打开键对应的数据，键不存在时返回 [`GamError::NotFound`]
+    fn get(&self, key: &str) -> GamResult<Box<dyn Read>>;
+
+    /// 打开键对应数据中从 `offset` 开始的 `length` 个字节
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> GamResult<Box<dyn Read>> {
+        let mut reader = self.get(key)?;
+        io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+        Ok(Box::new(reader.take(length)))
+    }
+
+    /// 写入键对应的数据，`write` 将全部内容写入给定的写入器，返回写入的字节数
+    ///
+    /// 可以在只持有共享锁时调用，多个进程同时写入同一个键时以最后完成的为准
+    fn put_with(
+        &self,
+        key: &str,
+        write: &mut dyn FnMut(&mut dyn Write) -> GamResult<()>,
+    ) -> GamResult<u64>;
+
+    /// 写入内存中的数据
+    fn put(&self, key: &str, data: &[u8]) -> GamResult<u64> {
+        self.put_with(key, &mut |writer| Ok(writer.write_all(data)?))
+    }
+
+    /// 获取键对应数据的元信息，键不存在时为空
+    fn stat(&self, key: &str) -> Option<KeyStat>;
+
+    /// 检查键是否存在
+    fn exists(&self, key: &str) -> bool {
+        self.stat(key).is_some()
+    }
+
+    /// 删除键，键不存在时不报错
+    fn delete(&self, key: &str) -> GamResult<()>;
+
+    /// 列出 `prefix` 下的所有键（`prefix` 为 `content` 时包括 `content/ab/cdef…`），顺序不定
+    fn list(&self, prefix: &str) -> GamResult<Vec<String>>;
+}
+
+/// 按哈希寻址的对象的键：`{dir}/{前两位}/{其余部分}`
+pub fn object_key(dir: &str, hash: &str) -> String {
+    format!("{}/{}/{}", dir, &hash[..2], &hash[2..])
+}
+
+/// 本地目录后端：键对应对象根目录下的同名文件
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    /// 使用 `root` 作为对象根目录
+    pub fn new(root: PathBuf) -> Self {
+        LocalBackend { root }
+    }
+
+    /// 键对应的文件路径
+    fn path(&self, key: &str) -> PathBuf {
+        key.split('/')
+            .fold(self.root.clone(), |path, part| path.join(part))
+    }
+
+    /// 递归收集目录中的文件，跳过写入过程中的临时文件
+    fn collect(&self, dir: &Path, key: &str, keys: &mut Vec<String>) -> GamResult<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let child = format!("{}/{}", key, name);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.collect(&entry.path(), &child, keys)?;
+            } else if file_type.is_file() && !FileUtils::is_temp_file(&entry.path()) {
+                keys.push(child);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn check(&self) -> GamResult<()> {
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> GamResult<Box<dyn Read>> {
+        let path = self.path(key);
+        match File::open(&path) {
+            Ok(file) => Ok(Box::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(GamError::NotFound(path)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> GamResult<Box<dyn Read>> {
+        let mut file = File::open(self.path(key))?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Box::new(io::BufReader::new(file.take(length))))
+    }
+
+    fn put_with(
+        &self,
+        key: &str,
+        write: &mut dyn FnMut(&mut dyn Write) -> GamResult<()>,
+    ) -> GamResult<u64> {
+        let path = self.path(key);
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)?;
+
+        // 使用唯一的临时文件名，完整落盘后再重命名，避免留下截断的数据
+        let prefix = FileUtils::temp_path(&path)
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        let tmp = tempfile::Builder::new().prefix(&prefix).tempfile_in(dir)?;
+        let mut writer = BufWriter::new(tmp);
+        write(&mut writer)?;
+        let tmp = writer.into_inner().map_err(|e| e.into_error())?;
+        let size = tmp.as_file().metadata()?.len();
+
+        // 重命名成功后临时路径已不存在，TempPath 的清理不再生效
+        FileUtils::persist(&tmp.into_temp_path(), &path)?;
+        Ok(size)
+    }
+
+    fn stat(&self, key: &str) -> Option<KeyStat> {
+        let metadata = fs::metadata(self.path(key)).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as u64);
+        Some(KeyStat {
+            size: metadata.len(),
+            modified,
+        })
+    }
+
+    fn delete(&self, key: &str) -> GamResult<()> {
+        match fs::remove_file(self.path(key)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        // 删除变空的中间目录（例如哈希前缀目录），保留 `content` 等顶层目录
+        let parts: Vec<&str> = key.split('/').collect();
+        for depth in (2..parts.len()).rev() {
+            let dir = self.path(&parts[..depth].join("/"));
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> GamResult<Vec<String>> {
+        let mut keys = Vec::new();
+        self.collect(&self.path(prefix), prefix, &mut keys)?;
+        Ok(keys)
+    }
+}
+
+/// 独立目录后端：键对应仓库之外的目录中的文件
+///
+/// 目录中的标记文件记录所属仓库的标识，标记缺失（例如磁盘没有挂载）或属于其他仓库时
+/// [`StorageBackend::check`] 拒绝使用
+pub struct DirectoryBackend {
+    local: LocalBackend,
+    repo_id: String,
+}
+
+impl DirectoryBackend {
+    /// 使用 `root` 作为对象根目录，`repo_id` 为标记文件中应有的仓库标识
+    pub fn new(root: PathBuf, repo_id: String) -> Self {
+        DirectoryBackend {
+            local: LocalBackend::new(root),
+            repo_id,
+        }
+    }
+}
+
+impl StorageBackend for DirectoryBackend {
+    fn check(&self) -> GamResult<()> {
+        let root = &self.local.root;
+        let marker = fs::read_to_string(root.join(MARKER_FILE)).map_err(|_| {
+            GamError::StorageUnavailable(format!(
+                "{} is missing or not a gam storage directory (is the disk mounted?)",
+                root.display()
+            ))
+        })?;
+        if marker.trim() != self.repo_id {
+            return Err(GamError::StorageUnavailable(format!(
+                "{} belongs to another repository",
+                root.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> GamResult<Box<dyn Read>> {
+        self.local.get(key)
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> GamResult<Box<dyn Read>> {
+        self.local.get_range(key, offset, length)
+    }
+
+    fn put_with(
+        &self,
+        key: &str,
+        write: &mut dyn FnMut(&mut dyn Write) -> GamResult<()>,
+    ) -> GamResult<u64> {
+        self.local.put_with(key, write)
+    }
+
+    fn stat(&self, key: &str) -> Option<KeyStat> {
+        self.local.stat(key)
+    }
+
+    fn delete(&self, key: &str) -> GamResult<()> {
+        self.local.delete(key)
+    }
+
+    fn list(&self, prefix: &str) -> GamResult<Vec<String>> {
+        self.local.list(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_backend_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backend = LocalBackend::new(temp_dir.path().to_path_buf());
+
+        let key = object_key("content", "abcdef");
+        assert_eq!(key, "content/ab/cdef");
+        assert!(!backend.exists(&key));
+        assert!(matches!(backend.get(&key), Err(GamError::NotFound(_))));
+
+        assert_eq!(backend.put(&key, b"hello world").unwrap(), 11);
+        backend.put("content/index", b"{}").unwrap();
+        assert_eq!(backend.stat(&key).unwrap().size, 11);
+
+        let mut data = String::new();
+        backend
+            .get_range(&key, 6, 5)
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "world");
+
+        // 写入过程中遗留的临时文件不算作键
+        fs::write(
+            FileUtils::temp_path(&temp_dir.path().join("content/ab/cdef")),
+            b"partial",
+        )
+        .unwrap();
+        let mut keys = backend.list("content").unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["content/ab/cdef", "content/index"]);
+        assert!(backend.list("snapshot").unwrap().is_empty());
+
+        // 删除最后一个对象时一并删除前缀目录，顶层目录保留
+        fs::remove_file(FileUtils::temp_path(
+            &temp_dir.path().join("content/ab/cdef"),
+        ))
+        .unwrap();
+        backend.delete(&key).unwrap();
+        backend.delete(&key).unwrap();
+        assert!(!temp_dir.path().join("content/ab").exists());
+        assert!(temp_dir.path().join("content").exists());
+    }
+
+    #[test]
+    fn test_directory_backend_checks_marker() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backend = DirectoryBackend::new(temp_dir.path().to_path_buf(), "repo".to_string());
+        assert!(matches!(
+            backend.check(),
+            Err(GamError::StorageUnavailable(_))
+        ));
+
+        fs::write(temp_dir.path().join(MARKER_FILE), "other\n").unwrap();
+        assert!(matches!(
+            backend.check(),
+            Err(GamError::StorageUnavailable(_))
+        ));
+
+        fs::write(temp_dir.path().join(MARKER_FILE), "repo\n").unwrap();
+        backend.check().unwrap();
+        backend.put("snapshot/ab/cd", b"data").unwrap();
+        assert_eq!(backend.list("snapshot").unwrap(), vec!["snapshot/ab/cd"]);
+    }
+}
@@ -1,139 +1,968 @@
+use crate::core::crypto::{self, RepoKey};
 use crate::core::error::{GamError, GamResult};
-use crate::utils::HashUtils;
+use crate::core::store::backend::object_key;
+use crate::core::store::{PackStore, StorageBackend, StorageLocation};
+use crate::core::types::{CompressionCodec, EntryKind, FileEntry, HashAlgorithm, Snapshot};
+use crate::utils::{Chunker, CompressionUtils, ContentHasher, DeltaUtils};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 增量对象魔数
+const DELTA_MAGIC: &[u8; 8] = b"GAMDELTA";
+/// 超过该大小的文件不做增量存储（大文件请使用分块）
+const DELTA_MAX_SIZE: u64 = 64 * 1024 * 1024;
+/// 读取增量链时允许的最大深度（防止损坏的对象形成环）
+const DELTA_HARD_LIMIT: usize = 256;
+/// `put` 在内存中完成哈希和写入的最大内容长度，更长的内容先写入临时文件
+const PUT_MEMORY_LIMIT: u64 = 8 * 1024 * 1024;
+/// 松散对象所在的键前缀
+const CONTENT_DIR: &str = "content";
+/// 内容索引的键
+const INDEX_KEY: &str = "content/index";
+/// 包文件所在的键前缀
+const PACK_DIR: &str = "pack/content";
+
+/// 内容对象的读取器（已解密、解压并展开增量），附带内容长度提示
+pub struct ObjectReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    size: Option<u64>,
+}
+
+impl ObjectReader<'_> {
+    /// 内容长度，索引中没有记录时为空
+    pub fn size_hint(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+impl Read for ObjectReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// 按顺序拼接分块对象的读取器，每个分块在读到时才打开
+struct ChunkReader<'a> {
+    store: &'a ContentStore,
+    chunks: std::slice::Iter<'a, String>,
+    current: Option<ObjectReader<'a>>,
+}
+
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(reader) = &mut self.current {
+                let read = reader.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+            }
+            match self.chunks.next() {
+                Some(hash) => self.current = Some(self.store.open(hash).map_err(io::Error::other)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// 新写入的对象，写入完成后登记到索引
+struct NewObject {
+    hash: String,
+    size: u64,
+    codec: CompressionCodec,
+    stored_size: u64,
+    delta_base: Option<String>,
+}
+
+/// 写入过程中对对象的一次引用
+enum ObjectRef {
+    /// 本次新写入的对象
+    New(NewObject),
+    /// 已存在（或由其他任务写入）的对象
+    Existing(String),
+}
+
+/// 尚未登记到索引的写入，由并行的写入任务共享
+#[derive(Default)]
+struct WriteBatch {
+    /// 已由某个任务负责写入的对象，保证同一对象只写入一次
+    claimed: Mutex<HashSet<String>>,
+    /// 所有对象引用，操作结束后统一登记到索引
+    refs: Mutex<Vec<ObjectRef>>,
+}
+
+impl WriteBatch {
+    /// 认领对象的写入，返回调用方是否需要写入该对象
+    fn claim(&self, store: &ContentStore, hash: &str) -> bool {
+        !store.exists(hash)
+            && self
+                .claimed
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(hash.to_string())
+    }
+
+    /// 记录一次对象引用
+    fn push(&self, object: ObjectRef) {
+        self.refs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(object);
+    }
+}
 
 /// 内容可寻址存储
 ///
-/// 将文件内容存储在 `.gam/objects/content/{hash_prefix}/{full_hash}` 中
+/// 将文件内容存储在存储后端的 `content/{hash_prefix}/{full_hash}` 键中
 /// 支持去重：相同内容的文件只存储一份
+/// 设置了压缩算法时，新写入的对象会被透明压缩，读取时自动解压
+/// 启用增量存储时，同一路径的新版本可以存为相对旧版本对象的二进制增量
+/// 对象可以是松散对象，也可以位于 `pack/content` 下的包文件中
+/// 设置了仓库密钥时，对象在压缩后加密，内容地址使用带密钥哈希
 pub struct ContentStore {
-    /// 存储根目录
-    pub root: PathBuf,
+    /// 存储后端
+    backend: Box<dyn StorageBackend>,
     /// 内容索引
     pub index: ContentIndex,
+    /// 新对象使用的压缩算法
+    codec: CompressionCodec,
+    /// 达到该大小的文件按内容分块存储（为空表示不分块）
+    chunk_threshold: Option<u64>,
+    /// 增量链最大深度（为空表示不使用增量存储）
+    delta_max_depth: Option<usize>,
+    /// 包文件
+    packs: PackStore,
+    /// 内容寻址使用的哈希算法
+    hash_algorithm: HashAlgorithm,
+    /// 仓库密钥（为空表示不加密）
+    key: Option<RepoKey>,
+    /// 已写入但尚未登记到索引的对象引用
+    pending: WriteBatch,
 }
 
 impl ContentStore {
-    /// 创建新的内容存储
-    pub fn new(root: PathBuf) -> GamResult<Self> {
-        let index_path = root.join("index");
+    /// 在存储位置的对象目录中打开内容存储
+    pub fn new(location: &StorageLocation) -> GamResult<Self> {
+        let backend = location.backend();
+        let index = ContentStore::load_index(backend.as_ref())?;
+        let packs = PackStore::open(location.backend(), PACK_DIR)?;
+
+        Ok(ContentStore {
+            backend,
+            packs,
+            index,
+            codec: CompressionCodec::None,
+            chunk_threshold: None,
+            delta_max_depth: None,
+            hash_algorithm: HashAlgorithm::default(),
+            key: None,
+            pending: WriteBatch::default(),
+        })
+    }
+
+    /// 设置仓库密钥
+    pub fn set_key(&mut self, key: Option<RepoKey>) {
+        self.key = key;
+    }
+
+    /// 创建内容哈希计算器（加密仓库使用带密钥哈希）
+    pub fn hasher(&self, algorithm: HashAlgorithm) -> ContentHasher {
+        ContentHasher::keyed(algorithm, self.key.as_ref().map(|key| key.hash_key()))
+    }
+
+    /// 使用指定算法计算数据的内容地址
+    pub fn hash_data_with(&self, data: &[u8], algorithm: HashAlgorithm) -> String {
+        let mut hasher = self.hasher(algorithm);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// 使用指定算法计算文件的内容地址
+    pub fn hash_file_with(&self, path: &Path, algorithm: HashAlgorithm) -> GamResult<String> {
+        let mut hasher = self.hasher(algorithm);
+        Self::feed(&mut hasher, File::open(path)?)?;
+        Ok(hasher.finalize())
+    }
+
+    /// 使用指定算法计算符号链接的内容地址（由链接目标决定）
+    pub fn hash_symlink_with(&self, target: &Path, algorithm: HashAlgorithm) -> String {
+        self.hash_data_with(target.to_string_lossy().as_bytes(), algorithm)
+    }
+
+    /// 设置内容寻址使用的哈希算法
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.hash_algorithm = algorithm;
+    }
+
+    /// 获取内容寻址使用的哈希算法
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// 设置增量链最大深度
+    pub fn set_delta_max_depth(&mut self, depth: Option<usize>) {
+        self.delta_max_depth = depth;
+    }
+
+    /// 设置分块存储阈值
+    pub fn set_chunk_threshold(&mut self, threshold: Option<u64>) {
+        self.chunk_threshold = threshold;
+    }
+
+    /// 判断指定大小的文件是否应分块存储
+    pub fn should_chunk(&self, size: u64) -> bool {
+        self.chunk_threshold.is_some_and(|t| size >= t)
+    }
+
+    /// 设置新对象使用的压缩算法
+    pub fn set_codec(&mut self, codec: CompressionCodec) {
+        self.codec = codec;
+    }
+
+    /// 获取新对象使用的压缩算法
+    pub fn codec(&self) -> CompressionCodec {
+        self.codec
+    }
+
+    /// 存储读取器中的全部内容，返回内容哈希
+    ///
+    /// 所有按内容寻址的写入都经过这里，可以在多个线程中同时调用，相同内容只写入一次。
+    /// `size_hint` 为预计的内容长度，不超过 `PUT_MEMORY_LIMIT` 时在内存中完成哈希和写入，
+    /// 否则先写入系统临时目录中的文件；提示与实际长度不符时不影响结果。
+    /// 写入的对象在调用 [`ContentStore::flush`] 后才登记到索引
+    pub fn put(&self, mut reader: impl Read, size_hint: Option<u64>) -> GamResult<String> {
+        let in_memory = size_hint.is_none_or(|size| size <= PUT_MEMORY_LIMIT);
+        let mut data = Vec::new();
+        if in_memory {
+            data.reserve(size_hint.unwrap_or(0) as usize);
+            (&mut reader)
+                .take(PUT_MEMORY_LIMIT + 1)
+                .read_to_end(&mut data)?;
+        }
+
+        if in_memory && data.len() as u64 <= PUT_MEMORY_LIMIT {
+            let hash = self.hash_data_with(&data, self.hash_algorithm);
+            if self.pending.claim(self, &hash) {
+                let (codec, stored_size) = self.write_bytes(&data, &hash)?;
+                self.pending.push(ObjectRef::New(NewObject {
+                    hash: hash.clone(),
+                    size: data.len() as u64,
+                    codec,
+                    stored_size,
+                    delta_base: None,
+                }));
+            } else {
+                self.pending.push(ObjectRef::Existing(hash.clone()));
+            }
+            return Ok(hash);
+        }
 
-        let index = if index_path.exists() {
-            ContentStore::load_index(&index_path)?
+        // 临时文件在离开作用域时删除
+        let mut spool = tempfile::NamedTempFile::new()?;
+        spool.write_all(&data)?;
+        let size = data.len() as u64 + io::copy(&mut reader, &mut spool)?;
+        spool.flush()?;
+        let hash = self.hash_file_with(spool.path(), self.hash_algorithm)?;
+        if self.pending.claim(self, &hash) {
+            let object = self.write_whole(File::open(spool.path())?, size, &hash)?;
+            self.pending.push(ObjectRef::New(object));
         } else {
-            ContentIndex::new()
+            self.pending.push(ObjectRef::Existing(hash.clone()));
+        }
+        Ok(hash)
+    }
+
+    /// 并行存储多个文件条目（`files[i]` 对应 `entries[i]`），索引只在全部完成后写入一次
+    ///
+    /// 并行度由调用方所在的线程池决定。`bases` 按路径提供父快照中的条目，用作增量存储的基础。
+    /// 出错时已写入的对象仍会登记到索引中
+    pub fn store_entries(
+        &mut self,
+        files: &[PathBuf],
+        entries: &mut [FileEntry],
+        bases: &HashMap<PathBuf, FileEntry>,
+    ) -> GamResult<()> {
+        let written = files
+            .par_iter()
+            .zip(entries.par_iter_mut())
+            .try_for_each(|(path, entry)| self.write_entry(path, entry, bases.get(&entry.path)));
+        self.flush()?;
+        written?;
+
+        for entry in entries.iter_mut() {
+            entry.compressed_size = self.compressed_size(entry)?;
+        }
+        Ok(())
+    }
+
+    /// 写入文件条目对应的对象（整文件、分块或增量）并补全分块列表，不修改索引
+    fn write_entry(
+        &self,
+        path: &Path,
+        entry: &mut FileEntry,
+        base: Option<&FileEntry>,
+    ) -> GamResult<()> {
+        // 目录和符号链接没有内容对象
+        if entry.kind != EntryKind::File {
+            return Ok(());
+        }
+
+        if self.should_chunk(entry.size) {
+            entry.chunks = self.write_chunked(File::open(path)?)?;
+            return Ok(());
+        }
+
+        if !self.pending.claim(self, &entry.hash) {
+            self.pending.push(ObjectRef::Existing(entry.hash.clone()));
+            return Ok(());
+        }
+
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let delta_enabled = self.delta_max_depth.is_some_and(|depth| depth > 0);
+        let object = match base.filter(|b| !b.is_chunked() && b.hash != entry.hash) {
+            // 增量编码需要完整内容，读入内存后两种写法共用
+            Some(b) if delta_enabled && size <= DELTA_MAX_SIZE => {
+                let mut target = Vec::with_capacity(size as usize);
+                file.read_to_end(&mut target)?;
+                match self.write_delta(&target, &entry.hash, &b.hash)? {
+                    Some(object) => object,
+                    None => {
+                        self.write_whole(target.as_slice(), target.len() as u64, &entry.hash)?
+                    }
+                }
+            }
+            _ => self.write_whole(file, size, &entry.hash)?,
         };
+        self.pending.push(ObjectRef::New(object));
+        Ok(())
+    }
 
-        // 确保目录结构存在
-        fs::create_dir_all(&root)?;
+    /// 以内容哈希 `hash` 写入长度为 `size` 的完整对象，不修改索引
+    fn write_whole(&self, reader: impl Read, size: u64, hash: &str) -> GamResult<NewObject> {
+        // 压缩或直接复制内容
+        let (codec, stored_size) = self.write_object(reader, size, hash)?;
 
-        Ok(ContentStore { root, index })
+        Ok(NewObject {
+            hash: hash.to_string(),
+            size,
+            codec,
+            stored_size,
+            delta_base: None,
+        })
     }
 
-    /// 存储文件，返回内容哈希
-    pub fn store(&mut self, path: &Path) -> GamResult<String> {
-        if !path.exists() {
-            return Err(GamError::NotFound(path.to_path_buf()));
+    /// 按内容分块写入，返回按顺序排列的分块哈希，不修改索引
+    fn write_chunked(&self, reader: impl Read) -> GamResult<Vec<String>> {
+        let mut chunks = Vec::new();
+        for chunk in Chunker::new(BufReader::new(reader)) {
+            let chunk = chunk?;
+            chunks.push(self.put(chunk.as_slice(), Some(chunk.len() as u64))?);
         }
+        Ok(chunks)
+    }
 
-        let hash = HashUtils::hash_file(path)?;
-        self.store_with_hash(path, &hash)
+    /// 尝试以内容哈希 `hash` 写入 `target` 相对 `base_hash` 的增量对象，不值得使用增量时返回空，不修改索引
+    fn write_delta(
+        &self,
+        target: &[u8],
+        hash: &str,
+        base_hash: &str,
+    ) -> GamResult<Option<NewObject>> {
+        let max_depth = match self.delta_max_depth {
+            Some(depth) if depth > 0 => depth,
+            _ => return Ok(None),
+        };
+
+        let size = target.len() as u64;
+        if size > DELTA_MAX_SIZE || !self.exists(base_hash) {
+            return Ok(None);
+        }
+        if self.delta_chain(base_hash)?.len() + 1 > max_depth {
+            return Ok(None);
+        }
+
+        let base = self.read_all(base_hash)?;
+        let delta = DeltaUtils::encode(&base, target);
+
+        // 增量至少要比完整副本小一半才值得
+        if (delta.len() as u64) * 2 >= size {
+            return Ok(None);
+        }
+
+        let payload = Self::delta_payload(base_hash, &delta);
+        let (codec, stored_size) = self.write_bytes(&payload, hash)?;
+        Ok(Some(NewObject {
+            hash: hash.to_string(),
+            size,
+            codec,
+            stored_size,
+            delta_base: Some(base_hash.to_string()),
+        }))
     }
 
-    /// 使用已知哈希存储文件（用于去重）
-    pub fn store_with_hash(&mut self, path: &Path, hash: &str) -> GamResult<String> {
-        // 检查是否已存在
-        if self.exists(hash) {
-            // 增加引用计数
-            self.index.increment_refcount(hash);
-            self.save_index()?;
-            return Ok(hash.to_string());
+    /// 将尚未登记的写入登记到索引并保存索引
+    ///
+    /// 先登记新对象再增加已有对象的引用计数，因为同一对象的新建和再次引用可能来自不同的任务
+    pub fn flush(&mut self) -> GamResult<()> {
+        let batch = std::mem::take(&mut self.pending);
+        let refs = batch.refs.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut existing = Vec::new();
+        for object in refs {
+            match object {
+                ObjectRef::New(object) => {
+                    self.index.add_entry(
+                        &object.hash,
+                        object.size,
+                        object.codec,
+                        object.stored_size,
+                    );
+                    if let Some(base) = &object.delta_base {
+                        self.index.set_delta_base(&object.hash, base);
+                    }
+                }
+                ObjectRef::Existing(hash) => existing.push(hash),
+            }
+        }
+        for hash in existing {
+            self.index.increment_refcount(&hash);
         }
+        self.save_index()
+    }
 
-        // 创建存储路径
-        let (prefix, suffix) = Self::hash_parts(hash);
-        let storage_path = self.root.join(prefix);
+    /// 获取增量对象的基础对象哈希（完整对象返回空）
+    ///
+    /// 对象类型以索引中写入对象时登记的基础对象为准。索引中缺少记录时，
+    /// 负载的哈希与对象名一致即为完整对象（文件内容恰好以增量魔数开头也不会误判），否则按增量对象头解析
+    pub fn delta_base(&self, hash: &str) -> GamResult<Option<String>> {
+        if let Some(entry) = self.index.entries.get(hash) {
+            return Ok(entry.delta_base.clone());
+        }
 
-        fs::create_dir_all(&storage_path)?;
+        let mut payload = Vec::new();
+        self.open_payload(hash)?.read_to_end(&mut payload)?;
+        if self.hash_data_with(&payload, self.hash_algorithm) == hash {
+            return Ok(None);
+        }
+        Ok(Some(Self::parse_delta(&payload)?.0))
+    }
 
-        let full_path = storage_path.join(suffix);
+    /// 获取对象的完整增量链（从直接基础对象到完整对象）
+    pub fn delta_chain(&self, hash: &str) -> GamResult<Vec<String>> {
+        let mut chain = Vec::new();
+        let mut seen = HashSet::new();
+        seen.insert(hash.to_string());
 
-        // 复制文件
-        fs::copy(path, &full_path)?;
+        let mut current = hash.to_string();
+        while let Some(base) = self.delta_base(&current)? {
+            if !seen.insert(base.clone()) || chain.len() >= DELTA_HARD_LIMIT {
+                return Err(GamError::Unknown(format!(
+                    "delta chain of {} is cyclic or too deep",
+                    hash
+                )));
+            }
+            chain.push(base.clone());
+            if !self.exists(&base) {
+                break;
+            }
+            current = base;
+        }
 
-        // 更新索引
-        let size = fs::metadata(&full_path)?.len();
-        self.index.add_entry(hash, size);
-        self.save_index()?;
+        Ok(chain)
+    }
+
+    /// 计算文件条目的压缩后大小（没有任何对象被压缩或增量存储时为空）
+    pub fn compressed_size(&self, entry: &FileEntry) -> GamResult<Option<u64>> {
+        let mut compressed = false;
+        let mut stored = 0u64;
+
+        for hash in entry.object_hashes() {
+            compressed |= self.codec_of(hash)? != CompressionCodec::None
+                || self.delta_base(hash)?.is_some();
+            stored += self.stored_size(hash).unwrap_or(0);
+        }
+
+        Ok(if compressed { Some(stored) } else { None })
+    }
+
+    /// 写入内存中的对象数据，返回实际使用的压缩算法和落盘大小
+    fn write_bytes(&self, data: &[u8], hash: &str) -> GamResult<(CompressionCodec, u64)> {
+        let key = Self::key_of(hash);
+        if self.codec != CompressionCodec::None {
+            let mut compressed = Vec::new();
+            CompressionUtils::compress(data, &mut compressed, self.codec)?;
+            if compressed.len() < data.len() {
+                let stored = self.seal(hash, &compressed)?;
+                self.backend.put(&key, &stored)?;
+                return Ok((self.codec, stored.len() as u64));
+            }
+        }
+
+        let stored = self.seal(hash, data)?;
+        self.backend.put(&key, &stored)?;
+        Ok((CompressionCodec::None, stored.len() as u64))
+    }
+
+    /// 写入对象，返回实际使用的压缩算法和落盘大小
+    ///
+    /// 压缩后没有变小的内容（例如本身已压缩的存档）按原样存储
+    fn write_object(
+        &self,
+        mut reader: impl Read,
+        size: u64,
+        hash: &str,
+    ) -> GamResult<(CompressionCodec, u64)> {
+        let key = Self::key_of(hash);
+        if self.codec == CompressionCodec::None {
+            let stored_size = self.backend.put_with(&key, &mut |writer| {
+                self.write_stream(&mut reader, writer, hash, CompressionCodec::None)
+            })?;
+            return Ok((CompressionCodec::None, stored_size));
+        }
+
+        // 先压缩到临时文件，确认变小后再写入后端
+        let mut spool = BufWriter::new(tempfile::tempfile()?);
+        self.write_stream(reader, &mut spool, hash, self.codec)?;
+        let mut compressed = spool.into_inner().map_err(|e| e.into_error())?;
+        let compressed_size = compressed.metadata()?.len();
+        compressed.rewind()?;
+        if compressed_size < self.sealed_len(size) {
+            let stored_size = self.backend.put_with(&key, &mut |writer| {
+                io::copy(&mut compressed, writer)?;
+                Ok(())
+            })?;
+            return Ok((self.codec, stored_size));
+        }
 
-        Ok(hash.to_string())
+        // 读取器只能读一遍，从压缩后的临时文件中读回原始内容再按原样写入
+        let sealed: Box<dyn Read> = match &self.key {
+            Some(repo_key) => Box::new(repo_key.decryptor(&Self::aad(hash), compressed)?),
+            None => Box::new(compressed),
+        };
+        let mut plain = CompressionUtils::decoder(sealed, self.codec)?;
+        let stored_size = self.backend.put_with(&key, &mut |writer| {
+            self.write_stream(&mut plain, writer, hash, CompressionCodec::None)
+        })?;
+        Ok((CompressionCodec::None, stored_size))
     }
 
-    /// 获取文件路径
-    pub fn get(&self, hash: &str) -> GamResult<PathBuf> {
-        let (prefix, suffix) = Self::hash_parts(hash);
-        let path = self.root.join(prefix).join(suffix);
+    /// 将读取器的内容按指定算法压缩（加密仓库中再加密）后写入 `writer`
+    fn write_stream(
+        &self,
+        reader: impl Read,
+        writer: &mut dyn Write,
+        hash: &str,
+        codec: CompressionCodec,
+    ) -> GamResult<()> {
+        let reader = BufReader::new(reader);
+        match &self.key {
+            Some(key) => {
+                let mut encryptor = key.encryptor(&Self::aad(hash), writer)?;
+                CompressionUtils::compress(reader, &mut encryptor, codec)?;
+                encryptor.finish()?.flush()?;
+            }
+            None => CompressionUtils::compress(reader, writer, codec)?,
+        }
+        Ok(())
+    }
 
-        if path.exists() {
-            Ok(path)
+    /// 内容对象的键
+    fn key_of(hash: &str) -> String {
+        object_key(CONTENT_DIR, hash)
+    }
+
+    /// 加密仓库中加密对象数据，否则原样返回
+    fn seal<'a>(&self, hash: &str, data: &'a [u8]) -> GamResult<Cow<'a, [u8]>> {
+        match &self.key {
+            Some(key) => Ok(Cow::Owned(key.encrypt(&Self::aad(hash), data)?)),
+            None => Ok(Cow::Borrowed(data)),
+        }
+    }
+
+    /// 明文长度为 `len` 的对象的落盘大小（不压缩时）
+    fn sealed_len(&self, len: u64) -> u64 {
+        match self.key {
+            Some(_) => crypto::encrypted_len(len),
+            None => len,
+        }
+    }
+
+    /// 对象加密时的附加数据，将密文与对象名绑定
+    fn aad(hash: &str) -> String {
+        format!("content:{}", hash)
+    }
+
+    /// 打开内容对象，返回解压（并应用增量）后的读取器
+    pub fn open(&self, hash: &str) -> GamResult<ObjectReader<'_>> {
+        let inner = if self.delta_base(hash)?.is_none() {
+            self.open_payload(hash)?
         } else {
-            Err(GamError::NotFound(path))
+            Box::new(io::Cursor::new(self.read_all(hash)?))
+        };
+        Ok(ObjectReader {
+            inner,
+            size: self.index.get_size(hash),
+        })
+    }
+
+    /// 打开普通文件条目的内容（整文件或按顺序拼接的分块）
+    pub fn open_entry<'a>(&'a self, entry: &'a FileEntry) -> GamResult<ObjectReader<'a>> {
+        if entry.kind != EntryKind::File {
+            return Err(GamError::NotFound(entry.path.clone()));
+        }
+
+        let inner: Box<dyn Read + 'a> = if entry.is_chunked() {
+            Box::new(ChunkReader {
+                store: self,
+                chunks: entry.chunks.iter(),
+                current: None,
+            })
+        } else {
+            self.open(&entry.hash)?.inner
+        };
+        Ok(ObjectReader {
+            inner,
+            size: Some(entry.size),
+        })
+    }
+
+    /// 读取对象的完整内容
+    fn read_all(&self, hash: &str) -> GamResult<Vec<u8>> {
+        self.read_at_depth(hash, 0)
+    }
+
+    fn read_at_depth(&self, hash: &str, depth: usize) -> GamResult<Vec<u8>> {
+        if depth > DELTA_HARD_LIMIT {
+            return Err(GamError::Unknown(format!(
+                "delta chain of {} is cyclic or too deep",
+                hash
+            )));
+        }
+
+        let is_delta = self.delta_base(hash)?.is_some();
+        let mut payload = Vec::new();
+        self.open_payload(hash)?.read_to_end(&mut payload)?;
+
+        if !is_delta {
+            return Ok(payload);
+        }
+
+        let (base_hash, delta) = Self::parse_delta(&payload)?;
+        let base = self.read_at_depth(&base_hash, depth + 1)?;
+        Ok(DeltaUtils::apply(&base, delta)?)
+    }
+
+    /// 组装增量对象：魔数、基础对象哈希（带长度前缀）和增量指令
+    fn delta_payload(base_hash: &str, delta: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(delta.len() + base_hash.len() + 16);
+        payload.extend_from_slice(DELTA_MAGIC);
+        DeltaUtils::write_varint(&mut payload, base_hash.len() as u64);
+        payload.extend_from_slice(base_hash.as_bytes());
+        payload.extend_from_slice(delta);
+        payload
+    }
+
+    /// 解析增量对象的负载，返回基础对象哈希和增量指令
+    fn parse_delta(data: &[u8]) -> GamResult<(String, &[u8])> {
+        let data = data
+            .strip_prefix(DELTA_MAGIC)
+            .ok_or_else(|| GamError::Hash("corrupt delta header".to_string()))?;
+        let mut pos = 0usize;
+        let len = DeltaUtils::read_varint(data, &mut pos)? as usize;
+        let base = data
+            .get(pos..pos + len)
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .ok_or_else(|| GamError::Hash("corrupt delta header".to_string()))?;
+        Ok((base.to_string(), &data[pos + len..]))
+    }
+
+    /// 打开对象文件，返回解压后的原始负载（增量对象不会被展开）
+    fn open_payload(&self, hash: &str) -> GamResult<Box<dyn Read>> {
+        let codec = self.codec_of(hash)?;
+        Ok(CompressionUtils::decoder(self.open_plain(hash)?, codec)?)
+    }
+
+    /// 打开对象并解密，返回压缩后的数据（未加密的仓库即落盘字节）
+    fn open_plain(&self, hash: &str) -> GamResult<Box<dyn Read>> {
+        let raw = self.open_raw(hash)?;
+        match &self.key {
+            Some(key) => Ok(Box::new(key.decryptor(&Self::aad(hash), raw)?)),
+            None => Ok(raw),
+        }
+    }
+
+    /// 打开对象的落盘字节（松散对象或包内对象）
+    fn open_raw(&self, hash: &str) -> GamResult<Box<dyn Read>> {
+        let key = Self::key_of(hash);
+        if self.backend.exists(&key) {
+            return self.backend.get(&key);
+        }
+
+        self.packs
+            .reader(hash)?
+            .ok_or(GamError::NotFound(key.into()))
+    }
+
+    /// 获取对象的压缩算法
+    ///
+    /// 索引中缺少记录时根据文件头推断
+    pub fn codec_of(&self, hash: &str) -> GamResult<CompressionCodec> {
+        if let Some(entry) = self.index.entries.get(hash) {
+            return Ok(entry.codec);
         }
+
+        let mut header = [0u8; 4];
+        let read = self.open_plain(hash)?.read(&mut header)?;
+        Ok(CompressionUtils::sniff(&header[..read]))
+    }
+
+    /// 获取对象落盘大小（压缩后）
+    pub fn stored_size(&self, hash: &str) -> Option<u64> {
+        self.index.entries.get(hash).map(|e| e.stored_size())
     }
 
-    /// 检查内容是否存在
+    /// 对象实际占用的字节数（松散对象或包内对象），对象不存在时为 0
+    pub fn disk_size(&self, hash: &str) -> u64 {
+        self.loose_size(hash)
+            .or_else(|| self.packs.object_size(hash))
+            .unwrap_or(0)
+    }
+
+    /// 松散对象的落盘大小，对象不是松散对象时为空
+    fn loose_size(&self, hash: &str) -> Option<u64> {
+        self.backend.stat(&Self::key_of(hash)).map(|s| s.size)
+    }
+
+    /// 检查内容是否存在（松散或已打包）
     pub fn exists(&self, hash: &str) -> bool {
-        let (prefix, suffix) = Self::hash_parts(hash);
-        self.root.join(prefix).join(suffix).exists()
+        self.backend.exists(&Self::key_of(hash)) || self.packs.contains(hash)
     }
 
-    /// 获取内容大小
-    pub fn size(&self, hash: &str) -> Option<u64> {
-        self.index.get_size(hash)
+    /// 将所有松散对象合并为包文件，返回打包的对象数和字节数
+    pub fn pack(&mut self) -> GamResult<(usize, u64)> {
+        self.packs.pack_loose(CONTENT_DIR, |_| true)
     }
 
-    /// 获取引用计数
-    pub fn refcount(&self, hash: &str) -> u32 {
-        self.index.get_refcount(hash).unwrap_or(0)
+    /// 将所有包还原为松散对象，返回还原的对象数
+    pub fn unpack(&mut self) -> GamResult<usize> {
+        self.packs.unpack_into(CONTENT_DIR)
     }
 
-    /// 删除未引用的内容
-    pub fn garbage_collect(&mut self) -> GamResult<u64> {
-        let mut freed = 0u64;
+    /// 松散对象数量
+    pub fn loose_count(&self) -> GamResult<usize> {
+        Ok(PackStore::loose_objects(self.backend.as_ref(), CONTENT_DIR)?.len())
+    }
 
-        // 遍历所有存储的内容
-        for entry in fs::read_dir(&self.root)? {
-            let entry = entry?;
-            if entry.file_type().unwrap().is_dir() {
-                for file in fs::read_dir(entry.path())? {
-                    let file = file?;
-                    if file.file_type().unwrap().is_file() {
-                        let file_name = file.file_name().to_string_lossy().to_string();
-                        if let Some(hash) = self.find_hash_in_directory(entry.path(), &file_name) {
-                            let refcount = self.index.get_refcount(&hash).unwrap_or(0);
-                            if refcount == 0 {
-                                // 删除未引用的内容
-                                let size = fs::metadata(file.path())?.len();
-                                fs::remove_file(file.path())?;
-                                self.index.remove_entry(&hash);
-                                freed += size;
-                            }
-                        }
-                    }
+    /// 所有已打包的对象哈希
+    pub fn packed_hashes(&self) -> Vec<String> {
+        self.packs.hashes().map(|h| h.to_string()).collect()
+    }
+
+    /// 所有对象哈希（松散和已打包，已排序）
+    pub fn all_hashes(&self) -> GamResult<Vec<String>> {
+        let mut hashes = PackStore::loose_objects(self.backend.as_ref(), CONTENT_DIR)?;
+        hashes.extend(self.packed_hashes());
+        hashes.sort();
+        hashes.dedup();
+        Ok(hashes)
+    }
+
+    /// 校验对象
+    ///
+    /// 完整模式解压（并展开增量）后重新计算哈希，与对象名比较；
+    /// 快速模式只比较落盘大小与索引记录
+    pub fn verify(&self, hash: &str, quick: bool) -> ObjectCheck {
+        if quick {
+            let Some(entry) = self.index.entries.get(hash) else {
+                return ObjectCheck::Unindexed;
+            };
+            let actual = self
+                .loose_size(hash)
+                .or_else(|| self.packs.object_size(hash));
+            return match actual {
+                Some(size) if size == entry.stored_size() => ObjectCheck::Ok,
+                Some(size) => ObjectCheck::Corrupt(format!(
+                    "大小不符: 索引记录 {} 字节，实际 {} 字节",
+                    entry.stored_size(),
+                    size
+                )),
+                None => ObjectCheck::Corrupt("对象文件不存在".to_string()),
+            };
+        }
+
+        let actual = self.open(hash).and_then(|reader| {
+            let mut hasher = self.hasher(self.hash_algorithm);
+            Self::feed(&mut hasher, reader)?;
+            Ok(hasher.finalize())
+        });
+        match actual {
+            Ok(actual) if actual == hash => ObjectCheck::Ok,
+            Ok(actual) => ObjectCheck::Corrupt(format!("哈希不符: 实际为 {}", actual)),
+            Err(e) => ObjectCheck::Corrupt(format!("无法读取: {}", e)),
+        }
+    }
+
+    /// 使用指定算法计算文件条目的整文件哈希（分块文件按顺序拼接所有分块）
+    pub fn hash_entry(&self, entry: &FileEntry, algorithm: HashAlgorithm) -> GamResult<String> {
+        let mut hasher = self.hasher(algorithm);
+        for hash in entry.object_hashes() {
+            Self::feed(&mut hasher, self.open(hash)?)?;
+        }
+        Ok(hasher.finalize())
+    }
+
+    /// 将读取器的全部内容送入哈希计算器，返回读取的字节数
+    fn feed(hasher: &mut ContentHasher, mut reader: impl Read) -> GamResult<u64> {
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut total = 0u64;
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(total);
+            }
+            hasher.update(&buffer[..read]);
+            total += read as u64;
+        }
+    }
+
+    /// 使用新的哈希算法重新写入所有对象，返回旧哈希到新哈希的映射
+    ///
+    /// 新对象以松散文件写入并登记到索引，旧对象保持不变，由调用方在更新快照后通过
+    /// [`ContentStore::remove_objects`] 删除。增量对象头中的基础对象哈希同步替换。
+    /// 已经存在的新对象不会重复写入，因此中断后可以重新执行
+    pub fn rehash(&mut self, algorithm: HashAlgorithm) -> GamResult<HashMap<String, String>> {
+        let hashes = self.all_hashes()?;
+
+        let mut mapping = HashMap::new();
+        let mut sizes = HashMap::new();
+        for hash in &hashes {
+            let mut hasher = self.hasher(algorithm);
+            let size = Self::feed(&mut hasher, self.open(hash)?)?;
+            mapping.insert(hash.clone(), hasher.finalize());
+            sizes.insert(hash.clone(), size);
+        }
+
+        for hash in &hashes {
+            let new_hash = &mapping[hash];
+            if new_hash == hash || self.exists(new_hash) {
+                continue;
+            }
+
+            // 完整对象按内容重新写入；增量对象保持增量，只替换其中的基础对象哈希
+            let object = if self.delta_base(hash)?.is_some() {
+                let mut payload = Vec::new();
+                self.open_payload(hash)?.read_to_end(&mut payload)?;
+                let (base, delta) = Self::parse_delta(&payload)?;
+                let new_base = mapping.get(&base).cloned().unwrap_or(base);
+                let payload = Self::delta_payload(&new_base, delta);
+                let (codec, stored_size) = self.write_bytes(&payload, new_hash)?;
+                NewObject {
+                    hash: new_hash.clone(),
+                    size: sizes[hash],
+                    codec,
+                    stored_size,
+                    delta_base: Some(new_base),
                 }
+            } else {
+                self.write_whole(self.open(hash)?, sizes[hash], new_hash)?
+            };
+
+            self.pending.push(ObjectRef::New(object));
+        }
+
+        self.hash_algorithm = algorithm;
+        self.flush()?;
+        Ok(mapping)
+    }
+
+    /// 删除指定对象（松散或已打包）及其索引记录，返回释放的字节数
+    pub fn remove_objects(&mut self, hashes: &HashSet<String>) -> GamResult<u64> {
+        let mut freed = 0u64;
+        let mut packed = HashSet::new();
+        for hash in hashes {
+            if let Some(size) = self.loose_size(hash) {
+                freed += size;
+                self.backend.delete(&Self::key_of(hash))?;
+            }
+            if self.packs.contains(hash) {
+                packed.insert(hash.clone());
             }
+            self.index.remove_entry(hash);
         }
 
+        if !packed.is_empty() {
+            freed += self.packs.remove(&packed)?;
+        }
         self.save_index()?;
         Ok(freed)
     }
 
-    /// 获取根目录
-    pub fn root(&self) -> &PathBuf {
-        &self.root
+    /// 释放文件条目对内容对象的引用（删除快照时调用）
+    pub fn release(&mut self, files: &[FileEntry]) -> GamResult<()> {
+        for file in files {
+            for hash in file.object_hashes() {
+                self.index.decrement_refcount(hash);
+            }
+        }
+        self.save_index()
+    }
+
+    /// 统计快照清单对每个内容对象的实际引用次数
+    ///
+    /// 每个文件条目（或分块）计一次引用，与保存快照时的计数方式一致
+    pub fn count_references(snapshots: &[Snapshot]) -> HashMap<String, u32> {
+        let mut counts = HashMap::new();
+        for snapshot in snapshots {
+            for file in &snapshot.files {
+                for hash in file.object_hashes() {
+                    *counts.entry(hash.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// 按实际引用次数重建引用计数，返回被修正的条目
+    ///
+    /// 被引用但索引中缺少记录的对象会根据对象数据重新登记
+    pub fn rebuild_refcounts(
+        &mut self,
+        counts: &HashMap<String, u32>,
+    ) -> GamResult<Vec<RefcountMismatch>> {
+        let mismatches = self.index.refcount_mismatches(counts);
+
+        for mismatch in &mismatches {
+            if mismatch.recorded.is_none() {
+                if !self.exists(&mismatch.hash) {
+                    continue;
+                }
+                self.reindex(&mismatch.hash)?;
+            }
+            self.index.set_refcount(&mismatch.hash, mismatch.actual);
+        }
+
+        self.save_index()?;
+        Ok(mismatches)
+    }
+
+    /// 根据对象数据重新登记索引条目
+    fn reindex(&mut self, hash: &str) -> GamResult<()> {
+        let codec = self.codec_of(hash)?;
+        let delta_base = self.delta_base(hash)?;
+        let size = io::copy(&mut self.open(hash)?, &mut io::sink())?;
+        let stored_size = self.disk_size(hash);
+
+        self.index.add_entry(hash, size, codec, stored_size);
+        if let Some(base) = delta_base {
+            self.index.set_delta_base(hash, &base);
+        }
+        Ok(())
     }
 
     /// 获取索引引用（用于 GC）
@@ -141,16 +970,10 @@ impl ContentStore {
         &self.index
     }
 
-    /// 获取索引可变引用（用于 GC）
-    pub fn index_mut(&mut self) -> &mut ContentIndex {
-        &mut self.index
-    }
-
     /// 保存索引（公开方法）
     pub fn save_index(&mut self) -> GamResult<()> {
-        let index_path = self.root.join("index");
-        let file = File::create(index_path)?;
-        serde_json::to_writer_pretty(file, &self.index)?;
+        let content = serde_json::to_vec_pretty(&self.index)?;
+        self.backend.put(INDEX_KEY, &content)?;
         Ok(())
     }
 
@@ -159,35 +982,13 @@ impl ContentStore {
         self.index.deduplication_savings()
     }
 
-    /// 分割哈希为前缀和后缀
-    fn hash_parts(hash: &str) -> (&str, &str) {
-        (&hash[..2], &hash[2..])
-    }
-
-    /// 在目录中查找哈希
-    fn find_hash_in_directory(&self, _dir: PathBuf, file_name: &str) -> Option<String> {
-        // 遍历父目录查找匹配的哈希
-        let prefix = file_name[..2].to_string();
-        for entry in fs::read_dir(self.root.join(&prefix)).ok()? {
-            let entry = entry.ok()?;
-            if entry.file_name().to_string_lossy() == file_name {
-                let full_hash = format!("{}{}", prefix, file_name);
-                if self.index.contains(&full_hash) {
-                    return Some(full_hash);
-                }
-            }
-        }
-        None
-    }
-
     /// 加载索引
-    fn load_index(path: &Path) -> GamResult<ContentIndex> {
-        if !path.exists() {
+    fn load_index(backend: &dyn StorageBackend) -> GamResult<ContentIndex> {
+        if !backend.exists(INDEX_KEY) {
             return Ok(ContentIndex::new());
         }
 
-        let file = File::open(path)?;
-        let index: ContentIndex = serde_json::from_reader(file)?;
+        let index: ContentIndex = serde_json::from_reader(backend.get(INDEX_KEY)?)?;
         Ok(index)
     }
 }
@@ -209,23 +1010,75 @@ impl ContentIndex {
         }
     }
 
-    fn add_entry(&mut self, hash: &str, size: u64) {
+    /// 登记新对象，新对象总是由一次引用写入，因此初始引用计数为 1
+    fn add_entry(&mut self, hash: &str, size: u64, codec: CompressionCodec, stored_size: u64) {
         self.entries.insert(
             hash.to_string(),
             ContentEntry {
                 hash: hash.to_string(),
                 size,
-                refcount: 0,
+                refcount: 1,
+                codec,
+                compressed_size: (stored_size != size).then_some(stored_size),
+                delta_base: None,
             },
         );
     }
 
+    fn set_delta_base(&mut self, hash: &str, base: &str) {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.delta_base = Some(base.to_string());
+        }
+    }
+
     fn increment_refcount(&mut self, hash: &str) {
         if let Some(entry) = self.entries.get_mut(hash) {
             entry.refcount += 1;
         }
     }
 
+    fn decrement_refcount(&mut self, hash: &str) {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.refcount = entry.refcount.saturating_sub(1);
+        }
+    }
+
+    fn set_refcount(&mut self, hash: &str, refcount: u32) {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.refcount = refcount;
+        }
+    }
+
+    /// 比较索引中的引用计数与实际引用次数，返回不一致的条目（按哈希排序）
+    pub fn refcount_mismatches(&self, counts: &HashMap<String, u32>) -> Vec<RefcountMismatch> {
+        let mut mismatches: Vec<RefcountMismatch> = self
+            .entries
+            .values()
+            .filter_map(|entry| {
+                let actual = counts.get(&entry.hash).copied().unwrap_or(0);
+                (entry.refcount != actual).then(|| RefcountMismatch {
+                    hash: entry.hash.clone(),
+                    recorded: Some(entry.refcount),
+                    actual,
+                })
+            })
+            .collect();
+
+        mismatches.extend(
+            counts
+                .iter()
+                .filter(|(hash, _)| !self.entries.contains_key(*hash))
+                .map(|(hash, actual)| RefcountMismatch {
+                    hash: hash.clone(),
+                    recorded: None,
+                    actual: *actual,
+                }),
+        );
+
+        mismatches.sort_by(|a, b| a.hash.cmp(&b.hash));
+        mismatches
+    }
+
     fn get_size(&self, hash: &str) -> Option<u64> {
         self.entries.get(hash).map(|e| e.size)
     }
@@ -253,6 +1106,13 @@ impl ContentIndex {
 
         total_size.saturating_sub(total_unique_size)
     }
+
+    /// 所有对象的原始大小与落盘大小之和
+    pub fn compression_totals(&self) -> (u64, u64) {
+        self.entries.values().fold((0, 0), |(size, stored), e| {
+            (size + e.size, stored + e.stored_size())
+        })
+    }
 }
 
 /// 内容条目
@@ -264,4 +1124,341 @@ pub struct ContentEntry {
     pub size: u64,
     /// 引用计数
     pub refcount: u32,
+    /// 压缩算法
+    #[serde(default)]
+    pub codec: CompressionCodec,
+    /// 落盘大小（压缩或增量存储后，与原始大小相同时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<u64>,
+    /// 增量对象的基础对象哈希（完整对象为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_base: Option<String>,
+}
+
+impl ContentEntry {
+    /// 落盘大小
+    pub fn stored_size(&self) -> u64 {
+        self.compressed_size.unwrap_or(self.size)
+    }
+}
+
+/// 对象校验结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectCheck {
+    /// 完好
+    Ok,
+    /// 损坏（附原因）
+    Corrupt(String),
+    /// 快速模式下索引中没有记录，无法校验
+    Unindexed,
+}
+
+/// 索引中的引用计数与实际引用不一致的对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefcountMismatch {
+    /// 内容哈希
+    pub hash: String,
+    /// 索引中记录的引用计数（为空表示索引中缺少该对象）
+    pub recorded: Option<u32>,
+    /// 快照实际引用次数
+    pub actual: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::store::StorageLocation;
+    use std::fs;
+
+    fn store_file(
+        store: &mut ContentStore,
+        dir: &Path,
+        name: &str,
+        data: &[u8],
+        base: Option<&FileEntry>,
+    ) -> FileEntry {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        let hash = store.hash_data_with(data, store.hash_algorithm());
+        let mut entries = [FileEntry::new(PathBuf::from("save.dat"), hash, data.len() as u64)];
+        let bases = base.map(|b| (b.path.clone(), b.clone())).into_iter().collect();
+        store.store_entries(&[path], &mut entries, &bases).unwrap();
+        let [entry] = entries;
+        entry
+    }
+
+    fn read(store: &ContentStore, entry: &FileEntry) -> Vec<u8> {
+        let mut data = Vec::new();
+        store.open_entry(entry).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_full_object_starting_with_delta_magic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        store.set_delta_max_depth(Some(8));
+
+        let base_data: Vec<u8> = (0..4096u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut magic_data = b"GAMDELTA".to_vec();
+        magic_data.extend_from_slice(&base_data);
+        let base = store_file(&mut store, temp_dir.path(), "a", &base_data, None);
+        let magic = store_file(&mut store, temp_dir.path(), "b", &magic_data, None);
+        assert_eq!(store.delta_base(&magic.hash).unwrap(), None);
+        assert_eq!(read(&store, &magic), magic_data);
+
+        // 真正的增量对象
+        let mut new_data = base_data.clone();
+        new_data[100] ^= 0xff;
+        let delta = store_file(&mut store, temp_dir.path(), "c", &new_data, Some(&base));
+        assert_eq!(store.delta_base(&delta.hash).unwrap(), Some(base.hash.clone()));
+
+        // 索引缺少记录时根据对象内容判断类型
+        store.index.entries.clear();
+        assert_eq!(store.delta_base(&magic.hash).unwrap(), None);
+        assert_eq!(read(&store, &magic), magic_data);
+        assert_eq!(store.delta_base(&delta.hash).unwrap(), Some(base.hash.clone()));
+        assert_eq!(read(&store, &delta), new_data);
+    }
+
+    /// 在 `jobs` 个线程中并行存储文件
+    fn store_parallel(
+        store: &mut ContentStore,
+        files: &[PathBuf],
+        entries: &mut [FileEntry],
+        jobs: usize,
+    ) -> GamResult<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .unwrap();
+        pool.install(|| store.store_entries(files, entries, &HashMap::new()))
+    }
+
+    #[test]
+    fn test_parallel_duplicates_stored_once() {
+        for jobs in [1, 8] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+            let data = b"same save data";
+            let hash = store.hash_data_with(data, store.hash_algorithm());
+
+            let count = 64;
+            let mut files = Vec::new();
+            let mut entries = Vec::new();
+            for i in 0..count {
+                let path = temp_dir.path().join(format!("slot{}.sav", i));
+                fs::write(&path, data).unwrap();
+                files.push(path);
+                entries.push(FileEntry::new(
+                    PathBuf::from(format!("slot{}.sav", i)),
+                    hash.clone(),
+                    data.len() as u64,
+                ));
+            }
+            store_parallel(&mut store, &files, &mut entries, jobs).unwrap();
+
+            // 重新打开，确认写入的索引
+            let store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+            assert_eq!(store.all_hashes().unwrap(), vec![hash.clone()]);
+            assert_eq!(store.index().entries.len(), 1);
+            assert_eq!(store.index().entries[&hash].refcount, count);
+        }
+    }
+
+    #[test]
+    fn test_parallel_error_is_returned() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+
+        let mut files = Vec::new();
+        let mut entries = Vec::new();
+        for i in 0..32 {
+            let data = format!("save {}", i);
+            let path = temp_dir.path().join(format!("slot{}.sav", i));
+            // 其中一个文件在扫描之后被删除
+            if i != 20 {
+                fs::write(&path, &data).unwrap();
+            }
+            let hash = store.hash_data_with(data.as_bytes(), store.hash_algorithm());
+            files.push(path);
+            entries.push(FileEntry::new(
+                PathBuf::from(format!("slot{}.sav", i)),
+                hash,
+                data.len() as u64,
+            ));
+        }
+        assert!(store_parallel(&mut store, &files, &mut entries, 4).is_err());
+
+        // 出错前已写入的对象都登记在索引中
+        let store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        for hash in store.all_hashes().unwrap() {
+            assert_eq!(store.index().entries[&hash].refcount, 1);
+        }
+    }
+
+    /// 每次最多返回 `step` 字节的读取器
+    struct ShortReader<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for ShortReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.step).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    /// 不可压缩的伪随机数据
+    fn noise(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    /// 以不对齐的小缓冲区逐段读出全部内容
+    fn read_short(mut reader: impl Read) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4093];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                return data;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[test]
+    fn test_put_open_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        store.set_codec(CompressionCodec::Zstd);
+
+        let limit = PUT_MEMORY_LIMIT as usize;
+        let small = b"save data ".repeat(100);
+        let compressible = vec![7u8; limit + 1];
+        let incompressible = noise(limit + 4096, 1);
+        let empty = Vec::new();
+
+        // 提示为空、偏小或偏大时都按实际内容写入
+        let cases: [(&Vec<u8>, Option<u64>); 7] = [
+            (&small, Some(small.len() as u64)),
+            (&small, None),
+            (&small, Some(u64::MAX)),
+            (&compressible, Some(0)),
+            (&incompressible, None),
+            (&incompressible, Some(incompressible.len() as u64)),
+            (&empty, None),
+        ];
+        let mut hashes = Vec::new();
+        for (data, hint) in cases {
+            let hash = store.put(ShortReader { data, step: 4093 }, hint).unwrap();
+            assert_eq!(hash, store.hash_data_with(data, store.hash_algorithm()));
+            hashes.push(hash);
+        }
+        store.flush().unwrap();
+
+        for ((data, _), hash) in cases.iter().zip(&hashes) {
+            let reader = store.open(hash).unwrap();
+            assert_eq!(reader.size_hint(), Some(data.len() as u64));
+            assert_eq!(&read_short(reader), *data);
+        }
+
+        // 重复内容只保存一份，压缩后没有变小的内容按原样保存
+        let index = store.index();
+        assert_eq!(index.entries[&hashes[0]].refcount, 3);
+        assert_eq!(index.entries[&hashes[4]].refcount, 2);
+        assert_eq!(index.entries[&hashes[3]].codec, CompressionCodec::Zstd);
+        assert_eq!(index.entries[&hashes[4]].codec, CompressionCodec::None);
+    }
+
+    #[test]
+    fn test_encrypted_put_open_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        store.set_codec(CompressionCodec::Zstd);
+        store.set_key(Some(RepoKey::generate()));
+
+        let small = b"save data ".repeat(100);
+        let hash = store.put(small.as_slice(), None).unwrap();
+        store.flush().unwrap();
+        assert_eq!(read_short(store.open(&hash).unwrap()), small);
+
+        // 流式写入的不可压缩内容从加密的临时文件中读回后按原样保存
+        let data = noise(64 * 1024, 4);
+        let entry = store_file(&mut store, temp_dir.path(), "a", &data, None);
+        assert_eq!(store.codec_of(&entry.hash).unwrap(), CompressionCodec::None);
+        assert_eq!(read(&store, &entry), data);
+    }
+
+    #[test]
+    fn test_chunked_entry_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        store.set_chunk_threshold(Some(1024 * 1024));
+
+        let data = noise(6 * 1024 * 1024, 2);
+        let entry = store_file(&mut store, temp_dir.path(), "a", &data, None);
+        assert!(entry.chunks.len() > 1);
+        let reader = store.open_entry(&entry).unwrap();
+        assert_eq!(reader.size_hint(), Some(data.len() as u64));
+        assert_eq!(read_short(reader), data);
+
+        // 只修改末尾时前面的分块被复用
+        let mut changed = data.clone();
+        *changed.last_mut().unwrap() ^= 0xff;
+        let second = store_file(&mut store, temp_dir.path(), "b", &changed, None);
+        assert_eq!(second.chunks[0], entry.chunks[0]);
+        assert_eq!(store.index().entries[&entry.chunks[0]].refcount, 2);
+        assert_eq!(read(&store, &second), changed);
+    }
+
+    #[test]
+    fn test_delta_entry_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        store.set_codec(CompressionCodec::Zstd);
+        store.set_delta_max_depth(Some(8));
+
+        let base_data = noise(256 * 1024, 3);
+        let base = store_file(&mut store, temp_dir.path(), "a", &base_data, None);
+        let mut data = base_data.clone();
+        data[1000..1010].fill(0);
+        let delta = store_file(&mut store, temp_dir.path(), "b", &data, Some(&base));
+        assert_eq!(
+            store.delta_base(&delta.hash).unwrap(),
+            Some(base.hash.clone())
+        );
+
+        let reader = store.open_entry(&delta).unwrap();
+        assert_eq!(reader.size_hint(), Some(data.len() as u64));
+        assert_eq!(read_short(reader), data);
+        assert_eq!(read_short(store.open(&delta.hash).unwrap()), data);
+    }
+
+    #[test]
+    fn test_truncated_object_reads_short() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = ContentStore::new(&StorageLocation::local(temp_dir.path())).unwrap();
+        let data = b"complete save data".to_vec();
+        let entry = store_file(&mut store, temp_dir.path(), "a", &data, None);
+
+        // 截断对象文件：读出的内容比条目记录的短
+        store
+            .backend
+            .put(&ContentStore::key_of(&entry.hash), &data[..5])
+            .unwrap();
+        let reader = store.open_entry(&entry).unwrap();
+        assert_eq!(reader.size_hint(), Some(data.len() as u64));
+        assert_eq!(read_short(reader).len(), 5);
+    }
 }
@@ -0,0 +1,212 @@
+//! 对象存储位置模块
+//!
+//! 存储位置决定内容对象、快照和包文件保存在哪里。默认使用 `.gam/objects`；
+//! 配置了 `storage.path` 时改用该目录（例如另一块磁盘），`.gam` 中只保留配置、引用和日志
+
+use crate::core::crypto;
+use crate::core::error::{GamError, GamResult};
+use crate::core::store::backend::{DirectoryBackend, LocalBackend, StorageBackend};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 独立存储目录中的标记文件，记录所属仓库的标识
+pub const MARKER_FILE: &str = "gam-storage";
+
+/// 迁移进行中时目标目录中的标记文件，记录迁移来源的对象目录
+pub const MOVE_MARKER_FILE: &str = "gam-storage-move";
+
+/// 对象存储位置
+///
+/// 对象根目录下为 `content/`、`snapshot/` 和 `pack/`，由 [`ContentStore`](super::ContentStore)
+/// 和 [`SnapshotStore`](super::SnapshotStore) 通过 [`StorageLocation::backend`] 读写
+pub enum StorageLocation {
+    /// 默认位置：`.gam/objects`
+    Local { objects_dir: PathBuf },
+    /// 仓库之外的独立目录
+    ///
+    /// 目录中的标记文件记录所属仓库的标识。标记缺失（例如磁盘没有挂载）或属于其他仓库时拒绝使用，
+    /// 避免把对象写到挂载点下，也避免两个仓库共用同一个对象目录
+    Directory { objects_dir: PathBuf, repo_id: String },
+}
+
+impl StorageLocation {
+    /// 使用仓库目录下的 `objects`
+    pub fn local(gam_dir: &Path) -> Self {
+        StorageLocation::Local {
+            objects_dir: gam_dir.join("objects"),
+        }
+    }
+
+    /// 打开已有的独立存储目录
+    pub fn directory(objects_dir: PathBuf, repo_id: String) -> Self {
+        StorageLocation::Directory {
+            objects_dir,
+            repo_id,
+        }
+    }
+
+    /// 在目录中建立新的独立对象存储（目录不存在或为空）
+    pub fn create_directory(objects_dir: &Path) -> GamResult<Self> {
+        if objects_dir.exists() && fs::read_dir(objects_dir)?.next().is_some() {
+            return Err(GamError::StorageUnavailable(format!(
+                "{} is not empty",
+                objects_dir.display()
+            )));
+        }
+        fs::create_dir_all(objects_dir)?;
+
+        let objects_dir = objects_dir.canonicalize()?;
+        let repo_id = crypto::random_id();
+        fs::write(objects_dir.join(MARKER_FILE), format!("{}\n", repo_id))?;
+        Ok(StorageLocation::Directory {
+            objects_dir,
+            repo_id,
+        })
+    }
+
+    /// 位置说明（用于显示）
+    pub fn describe(&self) -> String {
+        match self {
+            StorageLocation::Local { .. } => "本地 (.gam/objects)".to_string(),
+            StorageLocation::Directory { objects_dir, .. } => {
+                format!("独立目录 ({})", objects_dir.display())
+            }
+        }
+    }
+
+    /// 对象根目录
+    pub fn objects_dir(&self) -> &Path {
+        match self {
+            StorageLocation::Local { objects_dir } => objects_dir,
+            StorageLocation::Directory { objects_dir, .. } => objects_dir,
+        }
+    }
+
+    /// 独立目录所属仓库的标识（默认位置为空）
+    pub fn repo_id(&self) -> Option<&str> {
+        match self {
+            StorageLocation::Local { .. } => None,
+            StorageLocation::Directory { repo_id, .. } => Some(repo_id),
+        }
+    }
+
+    /// 按存储位置创建读写对象的后端
+    pub fn backend(&self) -> Box<dyn StorageBackend> {
+        match self {
+            StorageLocation::Local { objects_dir } => {
+                Box::new(LocalBackend::new(objects_dir.clone()))
+            }
+            StorageLocation::Directory {
+                objects_dir,
+                repo_id,
+            } => Box::new(DirectoryBackend::new(objects_dir.clone(), repo_id.clone())),
+        }
+    }
+
+    /// 检查存储位置当前是否可用
+    pub fn check(&self) -> GamResult<()> {
+        self.backend().check()
+    }
+
+    /// 创建对象目录结构
+    pub fn init(&self) -> GamResult<()> {
+        fs::create_dir_all(self.objects_dir().join("snapshot"))?;
+        fs::create_dir_all(self.objects_dir().join("content"))?;
+        Ok(())
+    }
+
+    /// 准备作为迁移目标：目录须不存在或为空，或是从 `source` 迁移中断时留下的目标
+    ///
+    /// 继续中断的迁移时沿用目标中已有的仓库标识，返回值的第二项表示是否为继续迁移
+    pub fn prepare_move_target(self, source: &Path) -> GamResult<(Self, bool)> {
+        let objects_dir = self.objects_dir().to_path_buf();
+        let resumed = fs::read_to_string(objects_dir.join(MOVE_MARKER_FILE))
+            .is_ok_and(|from| Path::new(from.trim()) == source);
+
+        let target = match self {
+            StorageLocation::Directory { .. } if resumed => {
+                let repo_id = fs::read_to_string(objects_dir.join(MARKER_FILE))?;
+                StorageLocation::directory(objects_dir.canonicalize()?, repo_id.trim().to_string())
+            }
+            StorageLocation::Directory { .. } => StorageLocation::create_directory(&objects_dir)?,
+            local @ StorageLocation::Local { .. } => {
+                if !resumed
+                    && objects_dir.exists()
+                    && fs::read_dir(&objects_dir)?.next().is_some()
+                {
+                    return Err(GamError::StorageUnavailable(format!(
+                        "{} is not empty",
+                        objects_dir.display()
+                    )));
+                }
+                fs::create_dir_all(&objects_dir)?;
+                local
+            }
+        };
+
+        fs::write(
+            target.objects_dir().join(MOVE_MARKER_FILE),
+            format!("{}\n", source.display()),
+        )?;
+        Ok((target, resumed))
+    }
+
+    /// 迁移完成，移除目标中的迁移标记
+    pub fn finish_move(&self) -> GamResult<()> {
+        let marker = self.objects_dir().join(MOVE_MARKER_FILE);
+        if marker.exists() {
+            fs::remove_file(marker)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_checks_marker() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("objects");
+        let location = StorageLocation::create_directory(&dir).unwrap();
+        location.init().unwrap();
+        assert!(location.check().is_ok());
+        assert!(StorageLocation::create_directory(&dir).is_err());
+
+        let other = StorageLocation::directory(dir.clone(), "other".to_string());
+        assert!(matches!(other.check(), Err(GamError::StorageUnavailable(_))));
+
+        let unmounted = StorageLocation::directory(
+            temp_dir.path().join("missing"),
+            location.repo_id().unwrap().to_string(),
+        );
+        assert!(unmounted.check().is_err());
+    }
+
+    #[test]
+    fn test_resume_interrupted_move() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        let dir = temp_dir.path().join("target");
+        let target = StorageLocation::directory(dir.clone(), String::new());
+
+        // 第一次迁移复制了部分对象后中断
+        let (first, resumed) = target.prepare_move_target(&source).unwrap();
+        assert!(!resumed);
+        fs::create_dir_all(dir.join("content")).unwrap();
+
+        let target = StorageLocation::directory(dir.clone(), String::new());
+        let (second, resumed) = target.prepare_move_target(&source).unwrap();
+        assert!(resumed);
+        assert_eq!(second.repo_id(), first.repo_id());
+        assert!(second.check().is_ok());
+
+        // 来自其他迁移的目标目录不能继续使用
+        let target = StorageLocation::directory(dir.clone(), String::new());
+        assert!(target.prepare_move_target(&temp_dir.path().join("other")).is_err());
+
+        second.finish_move().unwrap();
+        assert!(!dir.join(MOVE_MARKER_FILE).exists());
+    }
+}
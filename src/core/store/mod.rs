@@ -1,9 +1,17 @@
 //! 存储引擎模块
 //!
-//! 提供内容可寻址存储和快照管理
+//! 提供存储后端、对象存储位置、内容可寻址存储、包文件、快照索引和快照管理
 
+pub mod backend;
 pub mod content_store;
+pub mod location;
+pub mod pack;
+pub mod snapshot_index;
 pub mod snapshot_store;
 
+pub use backend::StorageBackend;
 pub use content_store::ContentStore;
+pub use location::StorageLocation;
+pub use pack::PackStore;
+pub use snapshot_index::SnapshotIndex;
 pub use snapshot_store::{SnapshotStore, TimelineManager};
@@ -0,0 +1,414 @@
+use crate::core::error::{GamError, GamResult};
+use crate::core::store::backend::{StorageBackend, object_key};
+use crate::utils::HashUtils;
+use std::collections::HashSet;
+use std::io::{self, Read};
+
+/// 包文件魔数
+const PACK_MAGIC: &[u8; 8] = b"GAMPACK1";
+/// 包索引魔数
+const INDEX_MAGIC: &[u8; 8] = b"GAMIDX01";
+
+/// 包内对象位置
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    /// 对象哈希
+    pub hash: String,
+    /// 在包文件中的偏移
+    pub offset: u64,
+    /// 对象长度
+    pub length: u64,
+}
+
+/// 单个包文件及其索引
+#[derive(Debug)]
+pub struct Pack {
+    /// 包文件的键
+    pub pack_key: String,
+    /// 索引文件的键
+    pub index_key: String,
+    /// 按哈希排序的对象位置
+    pub entries: Vec<PackEntry>,
+}
+
+impl Pack {
+    /// 查找对象位置
+    pub fn find(&self, hash: &str) -> Option<&PackEntry> {
+        self.entries
+            .binary_search_by(|e| e.hash.as_str().cmp(hash))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// 读取包索引
+    ///
+    /// 格式：魔数、对象数 (u32)，随后按哈希排序的条目：
+    /// 哈希长度 (u8)、哈希、偏移 (u64)、长度 (u64)，整数均为小端序
+    fn load(backend: &dyn StorageBackend, index_key: &str) -> GamResult<Self> {
+        let mut data = Vec::new();
+        backend.get(index_key)?.read_to_end(&mut data)?;
+        let corrupt = || GamError::Unknown(format!("corrupt pack index: {}", index_key));
+
+        if data.len() < 12 || &data[..8] != INDEX_MAGIC {
+            return Err(corrupt());
+        }
+
+        let count = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = 12usize;
+
+        for _ in 0..count {
+            let len = *data.get(pos).ok_or_else(corrupt)? as usize;
+            pos += 1;
+            let hash = data
+                .get(pos..pos + len)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .ok_or_else(corrupt)?
+                .to_string();
+            pos += len;
+            let numbers = data.get(pos..pos + 16).ok_or_else(corrupt)?;
+            let offset = u64::from_le_bytes(numbers[..8].try_into().unwrap());
+            let length = u64::from_le_bytes(numbers[8..].try_into().unwrap());
+            pos += 16;
+            entries.push(PackEntry {
+                hash,
+                offset,
+                length,
+            });
+        }
+
+        if !entries.windows(2).all(|w| w[0].hash < w[1].hash) {
+            return Err(corrupt());
+        }
+
+        Ok(Pack {
+            pack_key: Self::pack_key_of(index_key),
+            index_key: index_key.to_string(),
+            entries,
+        })
+    }
+
+    /// 索引对应的包文件的键
+    fn pack_key_of(index_key: &str) -> String {
+        format!("{}.pack", index_key.trim_end_matches(".idx"))
+    }
+}
+
+/// 包存储
+///
+/// 将大量松散对象合并为 `{name}.pack` 包文件，并使用排序的偏移索引 `{name}.idx` 定位对象
+pub struct PackStore {
+    /// 存储后端
+    backend: Box<dyn StorageBackend>,
+    /// 包文件所在的键前缀，例如 `pack/content`
+    dir: String,
+    /// 已加载的包
+    packs: Vec<Pack>,
+}
+
+impl PackStore {
+    /// 加载后端中 `dir` 下的所有包
+    pub fn open(backend: Box<dyn StorageBackend>, dir: &str) -> GamResult<Self> {
+        let mut packs = Vec::new();
+
+        for key in backend.list(dir)? {
+            if key.ends_with(".idx") && backend.exists(&Pack::pack_key_of(&key)) {
+                packs.push(Pack::load(backend.as_ref(), &key)?);
+            }
+        }
+
+        Ok(PackStore {
+            backend,
+            dir: dir.to_string(),
+            packs,
+        })
+    }
+
+    /// 检查对象是否在某个包中
+    pub fn contains(&self, hash: &str) -> bool {
+        self.packs.iter().any(|p| p.find(hash).is_some())
+    }
+
+    /// 打开包内对象
+    pub fn reader(&self, hash: &str) -> GamResult<Option<Box<dyn Read>>> {
+        for pack in &self.packs {
+            if let Some(entry) = pack.find(hash) {
+                return Ok(Some(self.entry_reader(pack, entry)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 打开包内对象的读取器
+    fn entry_reader(&self, pack: &Pack, entry: &PackEntry) -> GamResult<Box<dyn Read>> {
+        self.backend
+            .get_range(&pack.pack_key, entry.offset, entry.length)
+    }
+
+    /// 包文件大小
+    fn pack_size(&self, pack_key: &str) -> u64 {
+        self.backend.stat(pack_key).map_or(0, |s| s.size)
+    }
+
+    /// 获取包内对象长度
+    pub fn object_size(&self, hash: &str) -> Option<u64> {
+        self.packs
+            .iter()
+            .find_map(|p| p.find(hash))
+            .map(|e| e.length)
+    }
+
+    /// 所有打包的对象哈希
+    pub fn hashes(&self) -> impl Iterator<Item = &str> {
+        self.packs
+            .iter()
+            .flat_map(|p| p.entries.iter().map(|e| e.hash.as_str()))
+    }
+
+    /// 将对象写入新包，返回新包文件的键
+    ///
+    /// `objects` 为 (哈希, 读取器) 列表；同一哈希只写入一次
+    pub fn write_pack(
+        &mut self,
+        objects: Vec<(String, Box<dyn Read>)>,
+    ) -> GamResult<Option<String>> {
+        if objects.is_empty() {
+            return Ok(None);
+        }
+
+        let mut objects = objects;
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        objects.dedup_by(|a, b| a.0 == b.0);
+
+        let name = format!(
+            "pack-{}",
+            HashUtils::hash_string(
+                &objects
+                    .iter()
+                    .map(|(h, _)| h.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        );
+        let pack_key = format!("{}/{}.pack", self.dir, name);
+        let index_key = format!("{}/{}.idx", self.dir, name);
+
+        // 写入包文件
+        let mut entries = Vec::with_capacity(objects.len());
+        self.backend.put_with(&pack_key, &mut |writer| {
+            writer.write_all(PACK_MAGIC)?;
+            let mut offset = PACK_MAGIC.len() as u64;
+            for (hash, reader) in objects.iter_mut() {
+                let length = io::copy(reader, writer)?;
+                entries.push(PackEntry {
+                    hash: hash.clone(),
+                    offset,
+                    length,
+                });
+                offset += length;
+            }
+            Ok(())
+        })?;
+
+        // 写入索引
+        let mut index = Vec::new();
+        index.extend_from_slice(INDEX_MAGIC);
+        index.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in &entries {
+            index.push(entry.hash.len() as u8);
+            index.extend_from_slice(entry.hash.as_bytes());
+            index.extend_from_slice(&entry.offset.to_le_bytes());
+            index.extend_from_slice(&entry.length.to_le_bytes());
+        }
+
+        // 先写入包文件，最后写入索引：只有索引存在时包才会被加载
+        self.backend.put(&index_key, &index)?;
+
+        self.packs.retain(|p| p.index_key != index_key);
+        self.packs.push(Pack {
+            pack_key: pack_key.clone(),
+            index_key,
+            entries,
+        });
+
+        Ok(Some(pack_key))
+    }
+
+    /// 从包中移除对象（重写受影响的包），返回释放的字节数
+    pub fn remove(&mut self, hashes: &HashSet<String>) -> GamResult<u64> {
+        let affected: Vec<usize> = (0..self.packs.len())
+            .filter(|&i| self.packs[i].entries.iter().any(|e| hashes.contains(&e.hash)))
+            .collect();
+
+        let mut freed = 0u64;
+        for i in affected.into_iter().rev() {
+            let pack = self.packs.remove(i);
+            let before = self.pack_size(&pack.pack_key);
+
+            let mut keep: Vec<(String, Box<dyn Read>)> = Vec::new();
+            for entry in pack.entries.iter().filter(|e| !hashes.contains(&e.hash)) {
+                keep.push((entry.hash.clone(), self.entry_reader(&pack, entry)?));
+            }
+
+            let new_key = self.write_pack(keep)?;
+            if new_key.as_deref() != Some(pack.pack_key.as_str()) {
+                self.backend.delete(&pack.index_key)?;
+                self.backend.delete(&pack.pack_key)?;
+            }
+
+            let after = new_key.map_or(0, |key| self.pack_size(&key));
+            freed += before.saturating_sub(after);
+        }
+
+        Ok(freed)
+    }
+
+    /// 将松散对象（`{dir}/{前缀}/{其余哈希}`）合并为新包，并删除松散对象
+    ///
+    /// `filter` 返回 false 的对象保持松散，返回打包的对象数和字节数
+    pub fn pack_loose(
+        &mut self,
+        dir: &str,
+        filter: impl Fn(&str) -> bool,
+    ) -> GamResult<(usize, u64)> {
+        let loose: Vec<String> = Self::loose_objects(self.backend.as_ref(), dir)?
+            .into_iter()
+            .filter(|hash| filter(hash))
+            .collect();
+
+        let mut objects: Vec<(String, Box<dyn Read>)> = Vec::with_capacity(loose.len());
+        let mut bytes = 0u64;
+        for hash in &loose {
+            let key = object_key(dir, hash);
+            bytes += self.backend.stat(&key).map_or(0, |s| s.size);
+            objects.push((
+                hash.clone(),
+                Box::new(io::BufReader::new(self.backend.get(&key)?)),
+            ));
+        }
+
+        if self.write_pack(objects)?.is_none() {
+            return Ok((0, 0));
+        }
+
+        // 包已写入，删除松散对象
+        for hash in &loose {
+            self.backend.delete(&object_key(dir, hash))?;
+        }
+
+        Ok((loose.len(), bytes))
+    }
+
+    /// 将所有包内对象还原为松散对象（`{dir}/{前缀}/{其余哈希}`）并删除包，返回还原的对象数
+    pub fn unpack_into(&mut self, dir: &str) -> GamResult<usize> {
+        let mut count = 0usize;
+
+        for pack in &self.packs {
+            for entry in &pack.entries {
+                let key = object_key(dir, &entry.hash);
+                if self.backend.exists(&key) {
+                    continue;
+                }
+
+                let mut reader = self.entry_reader(pack, entry)?;
+                self.backend.put_with(&key, &mut |writer| {
+                    io::copy(&mut reader, writer)?;
+                    Ok(())
+                })?;
+                count += 1;
+            }
+        }
+
+        self.clear()?;
+        Ok(count)
+    }
+
+    /// 列出后端中 `dir` 下的所有松散对象的哈希
+    pub fn loose_objects(backend: &dyn StorageBackend, dir: &str) -> GamResult<Vec<String>> {
+        let mut objects = Vec::new();
+        let prefix = format!("{}/", dir);
+        for key in backend.list(dir)? {
+            let Some((hash_prefix, rest)) = key
+                .strip_prefix(&prefix)
+                .and_then(|name| name.split_once('/'))
+            else {
+                continue;
+            };
+            if hash_prefix.len() == 2 && !rest.is_empty() && !rest.contains('/') {
+                objects.push(format!("{}{}", hash_prefix, rest));
+            }
+        }
+        Ok(objects)
+    }
+
+    /// 删除所有包（解包完成后调用）
+    pub fn clear(&mut self) -> GamResult<()> {
+        for pack in self.packs.drain(..) {
+            self.backend.delete(&pack.index_key)?;
+            self.backend.delete(&pack.pack_key)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::store::backend::LocalBackend;
+    use std::path::Path;
+
+    fn open(dir: &Path) -> PackStore {
+        PackStore::open(Box::new(LocalBackend::new(dir.to_path_buf())), "pack").unwrap()
+    }
+
+    fn object(data: &'static [u8]) -> Box<dyn Read> {
+        Box::new(data)
+    }
+
+    #[test]
+    fn test_write_and_read_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open(dir.path());
+        store
+            .write_pack(vec![
+                ("bb".to_string(), object(b"second")),
+                ("aa".to_string(), object(b"first")),
+            ])
+            .unwrap();
+
+        let reopened = open(dir.path());
+        assert!(reopened.contains("aa"));
+        assert!(!reopened.contains("cc"));
+
+        let mut data = String::new();
+        reopened
+            .reader("bb")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "second");
+    }
+
+    #[test]
+    fn test_remove_rewrites_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open(dir.path());
+        store
+            .write_pack(vec![
+                ("aa".to_string(), object(b"keep")),
+                ("bb".to_string(), object(b"drop me")),
+            ])
+            .unwrap();
+
+        let freed = store
+            .remove(&HashSet::from(["bb".to_string()]))
+            .unwrap();
+        assert!(freed > 0);
+
+        let reopened = open(dir.path());
+        assert_eq!(reopened.packs.len(), 1);
+        assert!(reopened.contains("aa"));
+        assert!(!reopened.contains("bb"));
+    }
+}
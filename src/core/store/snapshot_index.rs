@@ -0,0 +1,203 @@
+//! 快照索引模块
+//!
+//! 存储后端中的 `snapshot/index` 记录每个快照的 ID、父快照、时间线、时间戳、名称、大小和文件数，
+//! 列出和筛选快照时不必解析完整的快照文件。索引只是加速手段：
+//! 文件缺失、无法解析、与实际的快照集合不一致或快照文件被重写时，读取方会按快照文件补齐
+
+use crate::core::crypto::RepoKey;
+use crate::core::error::GamResult;
+use crate::core::store::StorageBackend;
+use crate::core::types::SnapshotSummary;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// 索引格式版本（版本 2 起每个条目记录快照文件的时间戳）
+const INDEX_VERSION: u32 = 2;
+
+/// 索引的键
+pub const INDEX_KEY: &str = "snapshot/index";
+
+/// 索引加密时的附加数据
+const INDEX_AAD: &str = "snapshot-index";
+
+/// 索引文件内容
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    snapshots: Vec<IndexEntry>,
+}
+
+/// 索引条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    #[serde(flatten)]
+    summary: SnapshotSummary,
+    /// 写入摘要时快照文件的时间戳，与当前值不同说明快照文件已被重写
+    stamp: u64,
+}
+
+/// 快照索引（键为快照 ID）
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotIndex {
+    entries: BTreeMap<String, IndexEntry>,
+}
+
+impl SnapshotIndex {
+    /// 从存储后端读取索引，索引不存在、无法解密或无法解析时返回 `None`
+    pub fn load(backend: &dyn StorageBackend, key: Option<&RepoKey>) -> Option<Self> {
+        let mut content = Vec::new();
+        backend
+            .get(INDEX_KEY)
+            .ok()?
+            .read_to_end(&mut content)
+            .ok()?;
+        if let Some(key) = key {
+            content = key.decrypt(INDEX_AAD, &content).ok()?;
+        }
+        let file: IndexFile = serde_json::from_slice(&content).ok()?;
+        if file.version != INDEX_VERSION {
+            return None;
+        }
+        Some(SnapshotIndex {
+            entries: file
+                .snapshots
+                .into_iter()
+                .map(|e| (e.summary.id.clone(), e))
+                .collect(),
+        })
+    }
+
+    /// 将索引写入存储后端（设置了仓库密钥时加密）
+    ///
+    /// 列出快照时只持有共享锁，后端的写入允许多个进程同时进行
+    pub fn save(&self, backend: &dyn StorageBackend, key: Option<&RepoKey>) -> GamResult<()> {
+        let file = IndexFile {
+            version: INDEX_VERSION,
+            snapshots: self.entries.values().cloned().collect(),
+        };
+        let mut content = serde_json::to_vec(&file)?;
+        if let Some(key) = key {
+            content = key.encrypt(INDEX_AAD, &content)?;
+        }
+        backend.put(INDEX_KEY, &content)?;
+        Ok(())
+    }
+
+    /// 添加或替换快照摘要，`stamp` 为快照文件当前的时间戳
+    pub fn insert(&mut self, summary: SnapshotSummary, stamp: u64) {
+        self.entries.insert(summary.id.clone(), IndexEntry { summary, stamp });
+    }
+
+    /// 移除快照摘要，返回是否存在
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.entries.remove(id).is_some()
+    }
+
+    /// 使索引与实际的快照集合一致，返回是否有变化
+    ///
+    /// `snapshots` 为按 ID 排序的（快照 ID，快照文件时间戳）。移除已不存在或时间戳不同的快照，
+    /// 缺少的快照通过 `load` 读取摘要；读取失败的快照跳过
+    pub fn sync<F>(&mut self, snapshots: &[(String, u64)], mut load: F) -> bool
+    where
+        F: FnMut(&str) -> GamResult<SnapshotSummary>,
+    {
+        let before = self.entries.len();
+        self.entries.retain(|id, entry| {
+            snapshots
+                .binary_search_by(|(other, _)| other.cmp(id))
+                .is_ok_and(|i| snapshots[i].1 == entry.stamp)
+        });
+        let mut changed = self.entries.len() != before;
+
+        for (id, stamp) in snapshots {
+            if !self.entries.contains_key(id)
+                && let Ok(summary) = load(id)
+            {
+                self.insert(summary, *stamp);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// 所有快照摘要，按时间从新到旧排序
+    pub fn newest_first(&self) -> Vec<SnapshotSummary> {
+        let mut summaries: Vec<SnapshotSummary> =
+            self.entries.values().map(|e| e.summary.clone()).collect();
+        summaries.sort_by_key(|s| Reverse(s.timestamp));
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::store::backend::LocalBackend;
+    use chrono::{Duration, Local};
+
+    fn summary(id: &str, age_secs: i64) -> SnapshotSummary {
+        SnapshotSummary {
+            id: id.to_string(),
+            parent: None,
+            timeline: "main".to_string(),
+            timestamp: Local::now() - Duration::seconds(age_secs),
+            name: format!("snapshot {}", id),
+            size: 10,
+            file_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_sync_adds_and_removes() {
+        let mut index = SnapshotIndex::default();
+        index.insert(summary("aa", 10), 1);
+        index.insert(summary("bb", 5), 1);
+
+        let ids = vec![("bb".to_string(), 1), ("cc".to_string(), 1)];
+        let mut loaded = Vec::new();
+        assert!(index.sync(&ids, |id| {
+            loaded.push(id.to_string());
+            Ok(summary(id, 0))
+        }));
+        assert_eq!(loaded, vec!["cc"]);
+
+        let order: Vec<String> = index.newest_first().into_iter().map(|s| s.id).collect();
+        assert_eq!(order, vec!["cc", "bb"]);
+        assert!(!index.sync(&ids, |_| panic!("index is already in sync")));
+    }
+
+    #[test]
+    fn test_sync_reloads_rewritten() {
+        let mut index = SnapshotIndex::default();
+        index.insert(summary("aa", 10), 1);
+
+        // 快照文件被重写后时间戳变化，摘要需要重新读取
+        let ids = vec![("aa".to_string(), 2)];
+        assert!(index.sync(&ids, |id| {
+            let mut renamed = summary(id, 10);
+            renamed.name = "renamed".to_string();
+            Ok(renamed)
+        }));
+        assert_eq!(index.newest_first()[0].name, "renamed");
+        assert!(!index.sync(&ids, |_| panic!("index is already in sync")));
+    }
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backend = LocalBackend::new(temp_dir.path().to_path_buf());
+        let key = RepoKey::generate();
+        let mut index = SnapshotIndex::default();
+        index.insert(summary("aa", 0), 1);
+        index.save(&backend, Some(&key)).unwrap();
+
+        let content = std::fs::read(temp_dir.path().join("snapshot").join("index")).unwrap();
+        assert!(!content.windows(8).any(|w| w == b"snapshot"));
+        let loaded = SnapshotIndex::load(&backend, Some(&key)).unwrap();
+        assert_eq!(loaded.newest_first(), index.newest_first());
+        assert!(SnapshotIndex::load(&backend, Some(&RepoKey::generate())).is_none());
+        assert!(SnapshotIndex::load(&backend, None).is_none());
+    }
+}
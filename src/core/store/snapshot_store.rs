@@ -1,41 +1,62 @@
+use crate::core::crypto::RepoKey;
 use crate::core::error::{GamError, GamResult};
-use crate::core::types::{Config, FileEntry, Snapshot, Timeline};
+use crate::core::store::backend::object_key;
+use crate::core::store::snapshot_index::INDEX_KEY;
+use crate::core::store::{PackStore, SnapshotIndex, StorageBackend, StorageLocation};
+use crate::core::types::{
+    CompressionCodec, Config, FileEntry, HashAlgorithm, Snapshot, SnapshotSummary, Timeline,
+};
 use crate::utils::FileUtils;
 use chrono::Local;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
+/// 松散快照所在的键前缀
+const SNAPSHOT_DIR: &str = "snapshot";
+/// 包文件所在的键前缀
+const PACK_DIR: &str = "pack/snapshot";
+
 /// 快照存储管理
 ///
 /// 管理快照元数据的存储和查询
+/// 快照既可以是存储后端中 `snapshot/{id_prefix}/{rest}` 键下的松散快照，也可以位于 `pack/snapshot` 下的包文件中
+/// 设置了仓库密钥时快照加密保存
+/// 列出快照时读取 `index` 中的快照摘要，只解析索引中缺少的快照
 pub struct SnapshotStore {
-    /// 快照目录
-    pub snapshot_dir: PathBuf,
-    /// 内容目录
-    content_dir: PathBuf,
+    /// 存储后端
+    backend: Box<dyn StorageBackend>,
+    /// 包文件
+    packs: PackStore,
+    /// 新快照记录的内容哈希算法
+    hash_algorithm: HashAlgorithm,
+    /// 仓库密钥（为空表示不加密）
+    key: Option<RepoKey>,
 }
 
 impl SnapshotStore {
-    /// 创建新的快照存储
-    pub fn new(gam_dir: &Path) -> Self {
-        let snapshot_dir = gam_dir.join("objects").join("snapshot");
-        let content_dir = gam_dir.join("objects").join("content");
-
-        SnapshotStore {
-            snapshot_dir,
-            content_dir,
-        }
+    /// 在存储位置的对象目录中打开快照存储
+    pub fn new(location: &StorageLocation) -> GamResult<Self> {
+        let packs = PackStore::open(location.backend(), PACK_DIR)?;
+
+        Ok(SnapshotStore {
+            backend: location.backend(),
+            packs,
+            hash_algorithm: HashAlgorithm::default(),
+            key: None,
+        })
     }
 
-    /// 获取快照目录路径
-    pub fn snapshot_dir(&self) -> &PathBuf {
-        &self.snapshot_dir
+    /// 设置仓库密钥
+    pub fn set_key(&mut self, key: Option<RepoKey>) {
+        self.key = key;
     }
 
-    /// 获取内容目录路径
-    pub fn content_dir(&self) -> &PathBuf {
-        &self.content_dir
+    /// 设置新快照记录的内容哈希算法
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.hash_algorithm = algorithm;
     }
 
     /// 创建快照
@@ -46,37 +67,28 @@ impl SnapshotStore {
         parent: Option<&str>,
         name: &str,
         description: Option<&str>,
-        game_path: &Path,
+        compression: CompressionCodec,
     ) -> GamResult<Snapshot> {
-        // 确保目录存在
-        fs::create_dir_all(&self.snapshot_dir)?;
-
         // 计算所有文件的总大小
         let total_size: u64 = files.iter().map(|f| f.size).sum();
 
-        // 创建快照 ID（基于元数据）
-        let timestamp = Local::now();
-        let parent_str = parent.as_deref().unwrap_or("");
-        let metadata = format!(
-            "{:?}|{}|{}|{:?}|{}",
-            files, timeline, parent_str, timestamp, name
-        );
-        let id = crate::utils::HashUtils::hash_string(&metadata);
-
         // 构建快照
-        let snapshot = Snapshot {
-            id: id.clone(),
+        let mut snapshot = Snapshot {
+            id: String::new(),
             parent: parent.map(|s| s.to_string()),
             timeline: timeline.to_string(),
-            timestamp,
+            timestamp: Local::now(),
             name: name.to_string(),
             description: description.map(|s| s.to_string()),
             files: files.to_vec(),
-            content_hash: String::new(), // TODO: 计算组合哈希
+            content_hash: Snapshot::compute_content_hash(files),
             size: total_size,
-            compression: String::from("none"),
+            compression: compression.to_string(),
+            hash_algorithm: self.hash_algorithm,
             version: String::from("2.0.0"),
         };
+        // 快照 ID 由规范序列化决定，与平台和时区无关
+        snapshot.id = snapshot.compute_id();
 
         // 保存快照
         self.save(&snapshot)?;
@@ -84,33 +96,62 @@ impl SnapshotStore {
         Ok(snapshot)
     }
 
-    /// 保存快照到文件
+    /// 保存快照
     pub fn save(&self, snapshot: &Snapshot) -> GamResult<()> {
-        let (prefix, suffix) = Self::hash_parts(&snapshot.id);
-        let storage_path = self.snapshot_dir.join(prefix);
-
-        fs::create_dir_all(&storage_path)?;
+        let key = Self::key_of(&snapshot.id);
+        let mut content = serde_json::to_vec_pretty(snapshot)?;
+        if let Some(key) = &self.key {
+            content = key.encrypt(&Self::aad(&snapshot.id), &content)?;
+        }
+        self.backend.put(&key, &content)?;
 
-        let file_path = storage_path.join(suffix);
-        let file = File::create(&file_path)?;
-        serde_json::to_writer_pretty(file, snapshot)?;
+        let stamp = self.stamp(&key);
+        self.update_index(|index| index.insert(snapshot.summary(), stamp))
+    }
 
+    /// 修改已有的快照索引
+    ///
+    /// 索引不存在或无法读取时不创建，下次列出快照时按快照文件重建
+    fn update_index<F>(&self, update: F) -> GamResult<()>
+    where
+        F: FnOnce(&mut SnapshotIndex),
+    {
+        if let Some(mut index) = SnapshotIndex::load(self.backend.as_ref(), self.key.as_ref()) {
+            update(&mut index);
+            index.save(self.backend.as_ref(), self.key.as_ref())?;
+        }
         Ok(())
     }
 
+    /// 删除快照索引（批量重写快照前调用，之后按需重建）
+    pub fn drop_index(&self) -> GamResult<()> {
+        self.backend.delete(INDEX_KEY)
+    }
+
     /// 获取快照
     pub fn get(&self, id: &str) -> GamResult<Snapshot> {
-        let (prefix, suffix) = Self::hash_parts(id);
-        let file_path = self.snapshot_dir.join(prefix).join(suffix);
+        let key = Self::key_of(id);
+        if self.backend.exists(&key) {
+            return self.decode(id, self.backend.get(&key)?);
+        }
 
-        if !file_path.exists() {
-            return Err(GamError::SnapshotNotFound(id.to_string()));
+        match self.packs.reader(id)? {
+            Some(reader) => self.decode(id, reader),
+            None => Err(GamError::SnapshotNotFound(id.to_string())),
         }
+    }
 
-        let file = File::open(&file_path)?;
-        let snapshot: Snapshot = serde_json::from_reader(file)?;
+    /// 解析快照数据（加密仓库中先解密）
+    fn decode(&self, id: &str, reader: impl Read) -> GamResult<Snapshot> {
+        match &self.key {
+            Some(key) => Ok(serde_json::from_reader(key.decryptor(&Self::aad(id), reader)?)?),
+            None => Ok(serde_json::from_reader(reader)?),
+        }
+    }
 
-        Ok(snapshot)
+    /// 快照加密时的附加数据，将密文与快照 ID 绑定
+    fn aad(id: &str) -> String {
+        format!("snapshot:{}", id)
     }
 
     /// 按 ID 前缀查找快照
@@ -132,96 +173,108 @@ impl SnapshotStore {
         }
     }
 
-    /// 按前缀查找所有匹配的快照
+    /// 按前缀查找所有匹配的快照（只解析 ID 匹配的快照）
     fn find_by_prefix(&self, prefix: &str) -> GamResult<Vec<Snapshot>> {
         let mut matches = Vec::new();
-
-        if self.snapshot_dir.exists() {
-            for entry in fs::read_dir(&self.snapshot_dir)? {
-                let entry = entry?;
-                if entry.file_type()?.is_dir() {
-                    let dir_name = entry.file_name().to_string_lossy().to_string();
-
-                    // If prefix length > 2, check directory name first
-                    if prefix.len() > 2 {
-                        let dir_prefix = &prefix[..2];
-                        if dir_name.starts_with(dir_prefix) {
-                            // Directory matches, check file names
-                            let file_prefix = &prefix[2..];
-                            for file in fs::read_dir(entry.path())? {
-                                let file = file?;
-                                if file.file_type()?.is_file() {
-                                    let file_name = file.file_name().to_string_lossy().to_string();
-                                    if file_name.starts_with(file_prefix) {
-                                        match SnapshotStore::load_snapshot_file(file.path()) {
-                                            Ok(snapshot) => matches.push(snapshot),
-                                            Err(_) => continue,
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        // Prefix length <= 2, just check directory name
-                        if dir_name.starts_with(prefix) {
-                            // Directory matches, load all files
-                            for file in fs::read_dir(entry.path())? {
-                                let file = file?;
-                                if file.file_type()?.is_file() {
-                                    match SnapshotStore::load_snapshot_file(file.path()) {
-                                        Ok(snapshot) => matches.push(snapshot),
-                                        Err(_) => continue,
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+        for id in self.ids()? {
+            if id.starts_with(prefix)
+                && let Ok(snapshot) = self.get(&id)
+            {
+                matches.push(snapshot);
             }
         }
-
         Ok(matches)
     }
 
-    /// 加载快照文件
-    pub fn load_snapshot_file(path: PathBuf) -> GamResult<Snapshot> {
-        let file = File::open(&path)?;
-        let snapshot: Snapshot = serde_json::from_reader(file)?;
-        Ok(snapshot)
+    /// 所有快照 ID（松散和已打包，已排序），不解析快照内容
+    pub fn ids(&self) -> GamResult<Vec<String>> {
+        let mut ids = PackStore::loose_objects(self.backend.as_ref(), SNAPSHOT_DIR)?;
+        ids.extend(self.packed_ids());
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// 所有快照 ID 及快照文件的时间戳（按 ID 排序），用于判断索引中的摘要是否过期
+    fn stamped_ids(&self) -> GamResult<Vec<(String, u64)>> {
+        // 包中的快照不会被原地修改，时间戳记为 0；同时存在松散快照时以松散快照为准
+        let mut ids: BTreeMap<String, u64> =
+            self.packed_ids().into_iter().map(|id| (id, 0)).collect();
+        for id in PackStore::loose_objects(self.backend.as_ref(), SNAPSHOT_DIR)? {
+            let stamp = self.stamp(&Self::key_of(&id));
+            ids.insert(id, stamp);
+        }
+        Ok(ids.into_iter().collect())
+    }
+
+    /// 松散快照的写入时间（纳秒），无法读取时为 0
+    fn stamp(&self, key: &str) -> u64 {
+        self.backend.stat(key).map_or(0, |s| s.modified)
+    }
+
+    /// 所有已打包的快照 ID
+    fn packed_ids(&self) -> Vec<String> {
+        self.packs.hashes().map(|h| h.to_string()).collect()
+    }
+
+    /// 将所有松散快照合并为包文件，返回打包的快照数和字节数
+    pub fn pack(&mut self) -> GamResult<(usize, u64)> {
+        self.packs.pack_loose(SNAPSHOT_DIR, |_| true)
+    }
+
+    /// 将所有包还原为松散快照文件，返回还原的快照数
+    pub fn unpack(&mut self) -> GamResult<usize> {
+        self.packs.unpack_into(SNAPSHOT_DIR)
+    }
+
+    /// 松散快照数量
+    pub fn loose_count(&self) -> GamResult<usize> {
+        Ok(PackStore::loose_objects(self.backend.as_ref(), SNAPSHOT_DIR)?.len())
     }
 
     /// 删除快照
     pub fn delete(&mut self, id: &str) -> GamResult<()> {
-        let (prefix, suffix) = Self::hash_parts(id);
-        let file_path = self.snapshot_dir.join(prefix).join(suffix);
+        self.delete_many(&[id.to_string()])?;
+        Ok(())
+    }
 
-        if file_path.exists() {
-            fs::remove_file(&file_path)?;
+    /// 批量删除快照，返回释放的字节数
+    ///
+    /// 包中的快照一次性移除，每个受影响的包只重写一次
+    pub fn delete_many(&mut self, ids: &[String]) -> GamResult<u64> {
+        let mut freed = 0u64;
+        let mut packed = HashSet::new();
+        for id in ids {
+            let key = Self::key_of(id);
+            if let Some(stat) = self.backend.stat(&key) {
+                freed += stat.size;
+                self.backend.delete(&key)?;
+            }
+            if self.packs.contains(id) {
+                packed.insert(id.clone());
+            }
         }
 
-        Ok(())
+        if !packed.is_empty() {
+            freed += self.packs.remove(&packed)?;
+        }
+
+        self.update_index(|index| {
+            for id in ids {
+                index.remove(id);
+            }
+        })?;
+        Ok(freed)
     }
 
-    /// 获取所有快照
+    /// 获取所有快照（解析完整的快照文件，只需要元数据时使用 [`Self::summaries`]）
     pub fn list_all(&self) -> GamResult<Vec<Snapshot>> {
-        let mut snapshots = Vec::new();
-
-        if self.snapshot_dir.exists() {
-            for entry in fs::read_dir(&self.snapshot_dir)? {
-                let entry = entry?;
-                if entry.file_type()?.is_dir() {
-                    for file in fs::read_dir(entry.path())? {
-                        let file = file?;
-                        if file.file_type()?.is_file() {
-                            match Self::load_snapshot_file(file.path()) {
-                                Ok(snapshot) => snapshots.push(snapshot),
-                                Err(_) => continue,
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        // 同时存在松散副本和打包副本的快照以松散快照为准
+        let mut snapshots: Vec<Snapshot> = self
+            .ids()?
+            .iter()
+            .filter_map(|id| self.get(id).ok())
+            .collect();
 
         // 按时间戳排序
         snapshots.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
@@ -229,21 +282,39 @@ impl SnapshotStore {
         Ok(snapshots)
     }
 
-    /// 获取指定时间线的所有快照
-    pub fn list_by_timeline(&self, timeline: &str) -> GamResult<Vec<Snapshot>> {
-        let all = self.list_all()?;
+    /// 所有快照的摘要，按时间从新到旧排序
+    ///
+    /// 索引缺失、与实际的快照集合不一致或快照文件被重写时，只解析这些快照并写回索引
+    pub fn summaries(&self) -> GamResult<Vec<SnapshotSummary>> {
+        let mut index =
+            SnapshotIndex::load(self.backend.as_ref(), self.key.as_ref()).unwrap_or_default();
+        let ids = self.stamped_ids()?;
+        if index.sync(&ids, |id| Ok(self.get(id)?.summary()))
+            && (!ids.is_empty() || self.backend.exists(INDEX_KEY))
+        {
+            // 写回失败（例如只读的仓库）不影响本次结果
+            let _ = index.save(self.backend.as_ref(), self.key.as_ref());
+        }
+        Ok(index.newest_first())
+    }
+
+    /// 指定时间线上所有快照的摘要，按时间从新到旧排序
+    pub fn summaries_on_timeline(&self, timeline: &str) -> GamResult<Vec<SnapshotSummary>> {
+        let all = self.summaries()?;
         Ok(all.into_iter().filter(|s| s.timeline == timeline).collect())
     }
 
     /// 获取时间线上的最新快照
     pub fn latest_on_timeline(&self, timeline: &str) -> GamResult<Option<Snapshot>> {
-        let timeline_snapshots = self.list_by_timeline(timeline)?;
-        Ok(timeline_snapshots.first().cloned())
+        match self.summaries_on_timeline(timeline)?.first() {
+            Some(summary) => Ok(Some(self.get(&summary.id)?)),
+            None => Ok(None),
+        }
     }
 
-    /// 分割哈希为前缀和后缀
-    fn hash_parts(hash: &str) -> (&str, &str) {
-        (&hash[..2], &hash[2..])
+    /// 快照的键
+    fn key_of(id: &str) -> String {
+        object_key(SNAPSHOT_DIR, id)
     }
 }
 
@@ -273,7 +344,11 @@ impl TimelineManager {
     /// 创建时间线
     pub fn create(&self, name: &str, from_snapshot: Option<&str>) -> GamResult<Timeline> {
         // 验证时间线名称
-        if name.is_empty() || name.contains('/') || name.contains('\\') {
+        if name.is_empty()
+            || name.contains('/')
+            || name.contains('\\')
+            || FileUtils::is_temp_file(Path::new(name))
+        {
             return Err(GamError::InvalidTimelineName(name.to_string()));
         }
 
@@ -306,7 +381,7 @@ impl TimelineManager {
         fs::create_dir_all(&self.refs_dir)?;
 
         let file_path = self.refs_dir.join(&timeline.name);
-        fs::write(file_path, &timeline.head_snapshot)?;
+        FileUtils::atomic_write(&file_path, timeline.head_snapshot.as_bytes())?;
 
         Ok(())
     }
@@ -341,7 +416,7 @@ impl TimelineManager {
         if self.refs_dir.exists() {
             for entry in fs::read_dir(&self.refs_dir)? {
                 let entry = entry?;
-                if entry.file_type().unwrap().is_file() {
+                if entry.file_type().unwrap().is_file() && !FileUtils::is_temp_file(&entry.path()) {
                     let name = entry.file_name().to_string_lossy().to_string();
                     if let Ok(Some(timeline)) = self.get(&name) {
                         timelines.push(timeline);
@@ -355,7 +430,10 @@ impl TimelineManager {
 
     /// 设置当前时间线（HEAD）
     pub fn set_current(&self, name: &str) -> GamResult<()> {
-        fs::write(&self.head_file, format!("ref: refs/timelines/{}\n", name))?;
+        FileUtils::atomic_write(
+            &self.head_file,
+            format!("ref: refs/timelines/{}\n", name).as_bytes(),
+        )?;
         Ok(())
     }
 
@@ -402,9 +480,9 @@ impl TimelineManager {
         if self.head_file.exists() {
             let head_content = fs::read_to_string(&self.head_file)?;
             if head_content.trim() == format!("ref: refs/timelines/{}", old_name) {
-                fs::write(
+                FileUtils::atomic_write(
                     &self.head_file,
-                    format!("ref: refs/timelines/{}\n", new_name),
+                    format!("ref: refs/timelines/{}\n", new_name).as_bytes(),
                 )?;
             }
         }
@@ -416,7 +494,7 @@ impl TimelineManager {
     /// 更新时间线 HEAD
     pub fn update_head(&self, name: &str, snapshot_id: &str) -> GamResult<()> {
         let file_path = self.refs_dir.join(name);
-        fs::write(file_path, snapshot_id)?;
+        FileUtils::atomic_write(&file_path, snapshot_id.as_bytes())?;
         Ok(())
     }
 
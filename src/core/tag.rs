@@ -3,6 +3,7 @@
 //! 提供快照标签的存储和管理功能
 
 use crate::core::error::GamResult;
+use crate::utils::FileUtils;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
@@ -36,7 +37,7 @@ impl TagStore {
     pub fn save(&self, gam_dir: &PathBuf) -> GamResult<()> {
         let tags_file = gam_dir.join("refs").join("tags.json");
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(&tags_file, content)?;
+        FileUtils::atomic_write(&tags_file, content.as_bytes())?;
         Ok(())
     }
 
@@ -94,6 +95,26 @@ impl TagStore {
         self.tags.iter().collect()
     }
 
+    /// 将标签指向的快照 ID 按映射替换（快照 ID 重写后使用）
+    pub fn remap_snapshots(&mut self, mapping: &HashMap<String, String>) {
+        for snapshot_id in self.tags.values_mut() {
+            if let Some(new_id) = mapping.get(snapshot_id) {
+                *snapshot_id = new_id.clone();
+            }
+        }
+
+        self.reverse.clear();
+        for (tag_name, snapshot_id) in &self.tags {
+            self.reverse
+                .entry(snapshot_id.clone())
+                .or_default()
+                .push(tag_name.clone());
+        }
+        for tags in self.reverse.values_mut() {
+            tags.sort();
+        }
+    }
+
     /// 检查标签是否存在
     pub fn exists(&self, tag_name: &str) -> bool {
         self.tags.contains_key(tag_name)
@@ -0,0 +1,297 @@
+//! 文本转换驱动模块
+//!
+//! 配置的 `[textconv]` 段把路径模式（与 `.gamignore` 语法相同）映射到外部命令，
+//! `gam diff --content` 比较前先用命令把压缩或编码过的存档转换为可读文本。
+//! 命令中的 `{}` 替换为文件路径，不含 `{}` 时文件内容从标准输入传入。
+//! Unix 上命令由 `sh -c` 执行；Windows 上不含 `{}` 的命令由 `cmd /C` 执行，
+//! 含 `{}` 的命令直接执行，路径作为单独的参数传入
+
+use crate::core::error::{GamError, GamResult};
+use crate::core::ignore::IgnoreEngine;
+use crate::core::types::IgnorePattern;
+#[cfg(any(windows, test))]
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// 按路径模式配置的文本转换命令
+pub struct TextConv {
+    /// （模式，命令），后配置的模式优先
+    drivers: Vec<(IgnoreEngine, String)>,
+}
+
+impl TextConv {
+    /// 从 `[textconv]` 配置段创建（值必须是字符串）
+    pub fn from_config(section: Option<&toml::Value>) -> GamResult<Self> {
+        let mut drivers = Vec::new();
+        if let Some(section) = section {
+            let table = section.as_table().ok_or_else(|| {
+                GamError::InvalidConfig("textconv must be a table of pattern = \"command\"".to_string())
+            })?;
+            for (pattern, command) in table {
+                let command = command.as_str().ok_or_else(|| {
+                    GamError::InvalidConfig(format!("textconv.\"{}\" must be a string", pattern))
+                })?;
+                drivers.push((
+                    IgnoreEngine::new(vec![IgnorePattern::new(pattern.clone())]),
+                    command.to_string(),
+                ));
+            }
+        }
+        Ok(TextConv { drivers })
+    }
+
+    /// 路径对应的转换命令
+    pub fn driver_for(&self, path: &Path) -> Option<&str> {
+        self.drivers
+            .iter()
+            .rev()
+            .find(|(engine, _)| engine.is_ignored(path, false))
+            .map(|(_, command)| command.as_str())
+    }
+
+    /// 对内容执行转换命令，返回标准输出
+    ///
+    /// 命令不含 `{}` 时内容直接写入命令的标准输入；否则先写入系统临时目录下的临时文件（保留原扩展名）。
+    /// 加密仓库的内容在此以明文写出，临时文件只有当前用户可读，转换结束后删除
+    pub fn convert_reader(command: &str, reader: &mut dyn Read, path: &Path) -> GamResult<Vec<u8>> {
+        if !command.contains("{}") {
+            return Self::run(command, Self::shell(command), Some(reader));
+        }
+
+        let suffix = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let mut temp = tempfile::Builder::new()
+            .prefix("textconv-")
+            .suffix(&suffix)
+            .tempfile()?;
+        io::copy(reader, temp.as_file_mut())?;
+        Self::convert_file(command, temp.path())
+    }
+
+    /// 对文件执行转换命令，返回标准输出
+    pub fn convert_file(command: &str, file: &Path) -> GamResult<Vec<u8>> {
+        if command.contains("{}") {
+            Self::run(command, Self::with_path(command, file), None)
+        } else {
+            Self::run(command, Self::shell(command), Some(&mut File::open(file)?))
+        }
+    }
+
+    /// 执行命令，`input` 不为空时写入命令的标准输入，返回标准输出
+    fn run(
+        command: &str,
+        mut process: Command,
+        input: Option<&mut dyn Read>,
+    ) -> GamResult<Vec<u8>> {
+        let stdin = if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        };
+        let mut child = process
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // 在其他线程中读取输出，避免命令写满输出管道后不再读取输入，与写入输入的一方互相等待
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let (stdout, stderr) = std::thread::scope(|scope| -> GamResult<_> {
+            let stdout = scope.spawn(move || Self::read_all(&mut stdout));
+            let stderr = scope.spawn(move || Self::read_all(&mut stderr));
+            if let (Some(reader), Some(mut stdin)) = (input, child.stdin.take()) {
+                match io::copy(reader, &mut stdin) {
+                    // 命令可以不读完全部输入就退出
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                    copied => {
+                        copied?;
+                    }
+                }
+            }
+            let stdout = stdout
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+            let stderr = stderr
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+            Ok((stdout, stderr))
+        })?;
+
+        let status = child.wait()?;
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            let mut message = format!("'{}' failed ({})", command, status);
+            if !stderr.trim().is_empty() {
+                message.push_str(&format!(": {}", stderr.trim()));
+            }
+            return Err(GamError::TextConv(message));
+        }
+        Ok(stdout)
+    }
+
+    fn read_all(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    #[cfg(unix)]
+    fn shell(line: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(line);
+        command
+    }
+
+    #[cfg(windows)]
+    fn shell(line: &str) -> Command {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(line);
+        command
+    }
+
+    /// 将命令中的 `{}` 替换为引用后的文件路径，交给 shell 执行
+    #[cfg(unix)]
+    fn with_path(command: &str, file: &Path) -> Command {
+        let quoted = format!("'{}'", file.to_string_lossy().replace('\'', "'\\''"));
+        Self::shell(&command.replace("{}", &quoted))
+    }
+
+    /// 直接执行命令中的程序（不经过 `cmd /C`），`{}` 替换为文件路径后作为参数传入
+    ///
+    /// `cmd` 没有可靠的引用方式（引号内的 `%` 仍会被展开），因此路径不能拼进命令行
+    #[cfg(windows)]
+    fn with_path(command: &str, file: &Path) -> Command {
+        let mut args = Self::split_command(command, file).into_iter();
+        let mut process = Command::new(args.next().unwrap_or_default());
+        process.args(args);
+        process
+    }
+
+    /// 按空白将命令分为参数（双引号内的空白不分隔，引号本身去掉），参数中的 `{}` 替换为文件路径
+    #[cfg(any(windows, test))]
+    fn split_command(command: &str, file: &Path) -> Vec<OsString> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut quoted = false;
+        for c in command.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    word.get_or_insert_with(String::new);
+                }
+                c if c.is_whitespace() && !quoted => words.extend(word.take()),
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+
+        words
+            .iter()
+            .map(|word| {
+                let mut arg = OsString::new();
+                for (i, part) in word.split("{}").enumerate() {
+                    if i > 0 {
+                        arg.push(file);
+                    }
+                    arg.push(part);
+                }
+                arg
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_driver_for() {
+        let config: toml::Value =
+            toml::from_str("\"*.sav\" = \"zcat\"\n\"slot1/*.sav\" = \"cat {}\"\n").unwrap();
+        let textconv = TextConv::from_config(Some(&config)).unwrap();
+        assert_eq!(textconv.driver_for(Path::new("slot2/a.sav")), Some("zcat"));
+        assert_eq!(textconv.driver_for(Path::new("slot1/a.sav")), Some("cat {}"));
+        assert_eq!(textconv.driver_for(Path::new("a.json")), None);
+
+        let invalid: toml::Value = toml::from_str("\"*.sav\" = 1\n").unwrap();
+        assert!(TextConv::from_config(Some(&invalid)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_convert_through_stdin() {
+        let path = Path::new("save.sav");
+
+        // 内容通过管道传入，不经过临时文件
+        let mut data: &[u8] = b"x";
+        let kind =
+            TextConv::convert_reader("[ -p /dev/stdin ] && echo pipe", &mut data, path).unwrap();
+        assert_eq!(kind, b"pipe\n");
+
+        // 大于管道缓冲区的内容不会使命令与写入方互相等待
+        let large: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let copied = TextConv::convert_reader("cat", &mut large.as_slice(), path).unwrap();
+        assert_eq!(copied, large);
+
+        // 命令不读完输入就退出不算失败
+        let first = TextConv::convert_reader("head -c 3", &mut large.as_slice(), path).unwrap();
+        assert_eq!(first, &large[..3]);
+
+        let failed = TextConv::convert_reader(
+            "cat >/dev/null; echo oops >&2; exit 3",
+            &mut large.as_slice(),
+            path,
+        );
+        match failed {
+            Err(GamError::TextConv(message)) => assert!(message.contains("oops")),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_split_command() {
+        let file = Path::new(r"C:\saves\100% done & more\slot.sav");
+        let args =
+            TextConv::split_command(r#""C:\Program Files\conv.exe" --in={}  -x "" {}"#, file);
+        let mut input = OsString::from("--in=");
+        input.push(file);
+        assert_eq!(
+            args,
+            vec![
+                OsString::from(r"C:\Program Files\conv.exe"),
+                input,
+                OsString::from("-x"),
+                OsString::new(),
+                file.as_os_str().to_os_string(),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_convert_through_temp_file() {
+        let path = Path::new("slot 1/save.sav");
+        let mut data: &[u8] = b"b\na\n";
+
+        let sorted = TextConv::convert_reader("sort", &mut data, path).unwrap();
+        assert_eq!(sorted, b"a\nb\n");
+
+        // 临时文件位于系统临时目录，保留扩展名，转换后删除
+        let mut data: &[u8] = b"x";
+        let named = TextConv::convert_reader("echo {}", &mut data, path).unwrap();
+        let temp = std::path::PathBuf::from(String::from_utf8(named).unwrap().trim());
+        assert!(temp.starts_with(std::env::temp_dir()));
+        assert_eq!(temp.extension().unwrap(), "sav");
+        assert!(!temp.exists());
+
+        let mut data: &[u8] = b"x";
+        assert!(TextConv::convert_reader("false", &mut data, path).is_err());
+    }
+}
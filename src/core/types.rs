@@ -1,11 +1,11 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// 快照元数据
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
-    /// 快照唯一ID (SHA256 of metadata)
+    /// 快照唯一ID（规范序列化的哈希，见 [`Snapshot::compute_id`]）
     pub id: String,
     /// 父快照ID
     pub parent: Option<String>,
@@ -19,12 +19,15 @@ pub struct Snapshot {
     pub description: Option<String>,
     /// 包含的文件列表
     pub files: Vec<FileEntry>,
-    /// 所有内容的组合哈希
+    /// 所有内容的组合哈希（按路径排序的 `(path, hash, size)` 的 Merkle 根）
     pub content_hash: String,
     /// 快照总大小（字节）
     pub size: u64,
-    /// 压缩算法（none, gzip）
+    /// 压缩算法（none, zstd, gzip）
     pub compression: String,
+    /// 文件内容哈希使用的算法
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
     /// GAM 版本
     pub version: String,
 }
@@ -42,22 +45,184 @@ impl Default for Snapshot {
             content_hash: String::new(),
             size: 0,
             compression: String::from("none"),
+            hash_algorithm: HashAlgorithm::default(),
             version: String::from("2.0.0"),
         }
     }
 }
 
+/// 快照摘要，快照索引中的条目
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotSummary {
+    /// 快照ID
+    pub id: String,
+    /// 父快照ID
+    pub parent: Option<String>,
+    /// 所属时间线
+    pub timeline: String,
+    /// 时间戳
+    pub timestamp: DateTime<Local>,
+    /// 快照名称
+    pub name: String,
+    /// 快照总大小（字节）
+    pub size: u64,
+    /// 文件数
+    pub file_count: usize,
+}
+
+/// 快照 ID 规范序列化的版本，写在序列化结果的首行
+pub const SNAPSHOT_ID_VERSION: u32 = 1;
+
+impl Snapshot {
+    /// 快照身份的规范序列化
+    ///
+    /// 覆盖时间线、父快照、UTC 时间戳（纳秒精度）、名称和按路径排序的 `(path, hash, size)`。
+    /// 字符串带长度前缀，结果与 Rust 的 Debug 格式、本地时区和文件顺序无关
+    pub fn canonical_identity(&self) -> String {
+        let mut files: Vec<(String, &FileEntry)> = self
+            .files
+            .iter()
+            .map(|f| (manifest_path(&f.path), f))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let timestamp = self
+            .timestamp
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Nanos, true);
+
+        let mut out = format!("gam-snapshot-id {}\n", SNAPSHOT_ID_VERSION);
+        out.push_str(&format!("timeline {}\n", length_prefixed(&self.timeline)));
+        out.push_str(&format!(
+            "parent {}\n",
+            length_prefixed(self.parent.as_deref().unwrap_or(""))
+        ));
+        out.push_str(&format!("timestamp {}\n", timestamp));
+        out.push_str(&format!("name {}\n", length_prefixed(&self.name)));
+        out.push_str(&format!("files {}\n", files.len()));
+        for (path, file) in files {
+            out.push_str(&format!(
+                "file {} {} {}\n",
+                length_prefixed(&path),
+                file.hash,
+                file.size
+            ));
+        }
+        out
+    }
+
+    /// 根据规范序列化计算快照 ID
+    pub fn compute_id(&self) -> String {
+        crate::utils::HashUtils::hash_string(&self.canonical_identity())
+    }
+
+    /// 快照摘要（不含文件列表）
+    pub fn summary(&self) -> SnapshotSummary {
+        SnapshotSummary {
+            id: self.id.clone(),
+            parent: self.parent.clone(),
+            timeline: self.timeline.clone(),
+            timestamp: self.timestamp,
+            name: self.name.clone(),
+            size: self.size,
+            file_count: self.files.len(),
+        }
+    }
+
+    /// 计算文件列表的内容根哈希
+    ///
+    /// 与文件顺序无关，路径统一使用 `/` 分隔，因此同样的存档内容在任何平台上得到相同的根
+    pub fn compute_content_hash(files: &[FileEntry]) -> String {
+        let mut leaves: Vec<(String, &FileEntry)> = files
+            .iter()
+            .map(|f| (manifest_path(&f.path), f))
+            .collect();
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let leaves: Vec<String> = leaves
+            .into_iter()
+            .map(|(path, f)| format!("{}\0{}\0{}", path, f.hash, f.size))
+            .collect();
+        crate::utils::HashUtils::merkle_root(&leaves)
+    }
+
+    /// 判断两个文件清单是否记录了相同的存档
+    ///
+    /// 内容根哈希只覆盖路径、哈希和大小，这里还比较条目类型、权限位和符号链接目标，
+    /// 因此只修改权限或增删空目录也视为不同；修改时间不参与比较
+    pub fn same_manifest(a: &[FileEntry], b: &[FileEntry]) -> bool {
+        a.len() == b.len()
+            && sorted_manifest(a)
+                .into_iter()
+                .zip(sorted_manifest(b))
+                .all(|((pa, fa), (pb, fb))| {
+                    pa == pb
+                        && fa.hash == fb.hash
+                        && fa.size == fb.size
+                        && fa.kind == fb.kind
+                        && fa.mode == fb.mode
+                        && fa.symlink_target == fb.symlink_target
+                })
+    }
+
+    /// 检查文件清单是否与记录的内容根哈希一致
+    ///
+    /// 旧版本创建的快照没有记录根哈希，返回 `None`
+    pub fn verify_content_hash(&self) -> Option<bool> {
+        if self.content_hash.is_empty() {
+            return None;
+        }
+        Some(Self::compute_content_hash(&self.files) == self.content_hash)
+    }
+}
+
+/// 带长度前缀的字符串（`<字节数>:<内容>`），避免分隔符歧义
+fn length_prefixed(s: &str) -> String {
+    format!("{}:{}", s.len(), s)
+}
+
+/// 按清单路径排序的文件条目
+fn sorted_manifest(files: &[FileEntry]) -> Vec<(String, &FileEntry)> {
+    let mut entries: Vec<(String, &FileEntry)> =
+        files.iter().map(|f| (manifest_path(&f.path), f)).collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+/// 清单中使用的路径形式（`/` 分隔）
+fn manifest_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 /// 文件条目
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
     /// 相对于存档目录的路径
     pub path: PathBuf,
-    /// 内容哈希 (SHA256)
+    /// 内容哈希（算法见所属快照的 `hash_algorithm`）
     pub hash: String,
     /// 文件大小
     pub size: u64,
     /// 压缩后大小（可选）
     pub compressed_size: Option<u64>,
+    /// 分块存储时按顺序排列的分块哈希（为空表示整文件存储）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+    /// 条目类型（旧快照中只有普通文件）
+    #[serde(default, skip_serializing_if = "EntryKind::is_file")]
+    pub kind: EntryKind,
+    /// 修改时间（Unix 纪元起的纳秒数，旧快照中为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    /// Unix 权限位（旧快照、符号链接和非 Unix 平台为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// 符号链接指向的路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<PathBuf>,
 }
 
 impl FileEntry {
@@ -67,8 +232,68 @@ impl FileEntry {
             hash,
             size,
             compressed_size: None,
+            chunks: Vec::new(),
+            kind: EntryKind::File,
+            mtime: None,
+            mode: None,
+            symlink_target: None,
+        }
+    }
+
+    /// 空目录条目（没有内容，哈希为空）
+    pub fn dir(path: PathBuf) -> Self {
+        FileEntry {
+            kind: EntryKind::Dir,
+            ..Self::new(path, String::new(), 0)
         }
     }
+
+    /// 符号链接条目，哈希由链接目标计算（见 `ContentStore::hash_symlink_with`），
+    /// 不在内容存储中保存对象
+    pub fn symlink(path: PathBuf, target: PathBuf, hash: String) -> Self {
+        let size = target.to_string_lossy().len() as u64;
+        FileEntry {
+            kind: EntryKind::Symlink,
+            symlink_target: Some(target),
+            ..Self::new(path, hash, size)
+        }
+    }
+
+    /// 是否为分块存储
+    pub fn is_chunked(&self) -> bool {
+        !self.chunks.is_empty()
+    }
+
+    /// 该条目在内容存储中引用的对象哈希（目录和符号链接没有对象）
+    pub fn object_hashes(&self) -> Vec<&str> {
+        if self.kind != EntryKind::File {
+            Vec::new()
+        } else if self.is_chunked() {
+            self.chunks.iter().map(|c| c.as_str()).collect()
+        } else {
+            vec![self.hash.as_str()]
+        }
+    }
+}
+
+/// 文件条目类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// 普通文件
+    #[default]
+    File,
+    /// 空目录
+    Dir,
+    /// 符号链接
+    Symlink,
+}
+
+impl EntryKind {
+    /// 是否为普通文件
+    pub fn is_file(&self) -> bool {
+        *self == EntryKind::File
+    }
 }
 
 /// 时间线指针
@@ -115,6 +340,78 @@ impl Default for StorageStrategy {
     }
 }
 
+/// 压缩算法
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum CompressionCodec {
+    /// 不压缩
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    /// zstd
+    #[serde(rename = "zstd")]
+    Zstd,
+    /// gzip
+    #[serde(rename = "gzip")]
+    Gzip,
+}
+
+impl CompressionCodec {
+    /// 算法名称（与配置文件和快照元数据中的写法一致）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionCodec::None => "none",
+            CompressionCodec::Zstd => "zstd",
+            CompressionCodec::Gzip => "gzip",
+        }
+    }
+}
+
+impl std::fmt::Display for CompressionCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// 内容寻址使用的哈希算法
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// SHA-256
+    #[default]
+    #[serde(rename = "sha256")]
+    Sha256,
+    /// BLAKE3（速度更快，适合体积很大的存档库）
+    #[serde(rename = "blake3")]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// 算法名称（与配置文件和快照元数据中的写法一致）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            other => Err(format!("unknown hash algorithm '{}'", other)),
+        }
+    }
+}
+
 /// 保留策略
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetentionPolicy {
@@ -290,12 +587,115 @@ mod tests {
         assert_eq!(snapshot.version, "2.0.0");
     }
 
+    #[test]
+    fn test_content_hash_is_order_independent() {
+        let a = FileEntry::new(PathBuf::from("a.sav"), "h1".to_string(), 1);
+        let b = FileEntry::new(PathBuf::from("dir/b.sav"), "h2".to_string(), 2);
+        let root = Snapshot::compute_content_hash(&[a.clone(), b.clone()]);
+        assert_eq!(root, Snapshot::compute_content_hash(&[b.clone(), a.clone()]));
+
+        let mut changed = b.clone();
+        changed.size = 3;
+        assert_ne!(root, Snapshot::compute_content_hash(&[a.clone(), changed]));
+
+        let mut snapshot = Snapshot {
+            files: vec![a, b],
+            ..Default::default()
+        };
+        assert_eq!(snapshot.verify_content_hash(), None);
+        snapshot.content_hash = root;
+        assert_eq!(snapshot.verify_content_hash(), Some(true));
+        snapshot.files.pop();
+        assert_eq!(snapshot.verify_content_hash(), Some(false));
+    }
+
+    #[test]
+    fn test_snapshot_id_is_canonical() {
+        let timestamp = DateTime::parse_from_rfc3339("2026-01-29T12:54:00.5+08:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let a = FileEntry::new(PathBuf::from("a.sav"), "h1".to_string(), 1);
+        let b = FileEntry::new(PathBuf::from("dir/b.sav"), "h2".to_string(), 2);
+        let snapshot = Snapshot {
+            parent: Some("p".to_string()),
+            timeline: "main".to_string(),
+            timestamp,
+            name: "boss".to_string(),
+            files: vec![a.clone(), b.clone()],
+            ..Default::default()
+        };
+
+        // 固定的序列化格式，修改格式必须提升 SNAPSHOT_ID_VERSION
+        assert_eq!(
+            snapshot.canonical_identity(),
+            "gam-snapshot-id 1\ntimeline 4:main\nparent 1:p\n\
+             timestamp 2026-01-29T04:54:00.500000000Z\nname 4:boss\nfiles 2\n\
+             file 5:a.sav h1 1\nfile 9:dir/b.sav h2 2\n"
+        );
+
+        let reordered = Snapshot {
+            files: vec![b, a],
+            ..snapshot.clone()
+        };
+        assert_eq!(snapshot.compute_id(), reordered.compute_id());
+
+        // JSON 往返后 ID 不变
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let parsed: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot.compute_id(), parsed.compute_id());
+
+        let renamed = Snapshot {
+            name: "boss2".to_string(),
+            ..snapshot.clone()
+        };
+        assert_ne!(snapshot.compute_id(), renamed.compute_id());
+    }
+
     #[test]
     fn test_file_entry_new() {
         let entry = FileEntry::new(PathBuf::from("test/save.dat"), "abc123".to_string(), 1024);
         assert_eq!(entry.path, PathBuf::from("test/save.dat"));
         assert_eq!(entry.hash, "abc123");
         assert_eq!(entry.size, 1024);
+        assert_eq!(entry.object_hashes(), vec!["abc123"]);
+    }
+
+    #[test]
+    fn test_file_entry_chunks_roundtrip() {
+        let mut entry = FileEntry::new(PathBuf::from("world.dat"), "whole".to_string(), 10);
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(!json.contains("chunks"));
+
+        entry.chunks = vec!["c1".to_string(), "c2".to_string()];
+        let json = serde_json::to_string(&entry).unwrap();
+        let parsed: FileEntry = serde_json::from_str(&json).unwrap();
+        assert!(parsed.is_chunked());
+        assert_eq!(parsed.object_hashes(), vec!["c1", "c2"]);
+    }
+
+    #[test]
+    fn test_file_entry_metadata_defaults() {
+        // 旧快照中的条目没有元数据字段
+        let parsed: FileEntry =
+            serde_json::from_str(r#"{"path":"a.sav","hash":"h","size":1,"compressed_size":null}"#)
+                .unwrap();
+        assert_eq!(parsed.kind, EntryKind::File);
+        assert_eq!(parsed.mtime, None);
+        assert_eq!(parsed.mode, None);
+
+        let link = FileEntry::symlink(
+            PathBuf::from("profile"),
+            PathBuf::from("../profiles/alice"),
+            "h".to_string(),
+        );
+        assert!(link.object_hashes().is_empty());
+        let json = serde_json::to_string(&link).unwrap();
+        assert!(json.contains(r#""kind":"symlink""#));
+        let parsed: FileEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.symlink_target, Some(PathBuf::from("../profiles/alice")));
+
+        let dir = FileEntry::dir(PathBuf::from("empty"));
+        assert!(dir.object_hashes().is_empty());
     }
 
     #[test]
@@ -351,6 +751,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compression_codec_serde() {
+        assert_eq!(CompressionCodec::default(), CompressionCodec::None);
+        let json = serde_json::to_string(&CompressionCodec::Zstd).unwrap();
+        assert_eq!(json, "\"zstd\"");
+        let codec: CompressionCodec = serde_json::from_str("\"gzip\"").unwrap();
+        assert_eq!(codec, CompressionCodec::Gzip);
+    }
+
     #[test]
     fn test_retention_policy_default() {
         let policy = RetentionPolicy::default();
@@ -15,12 +15,13 @@ mod utils;
 use clap::Parser;
 use cli::Cli;
 use core::commands::{
-    handle_activity, handle_config, handle_diff, handle_doctor, handle_gc, handle_history,
+    handle_activity, handle_config, handle_diff, handle_doctor, handle_fsck, handle_gc, handle_history,
     handle_ignore_add, handle_ignore_check, handle_ignore_init, handle_ignore_list,
-    handle_ignore_remove, handle_init, handle_restore, handle_snapshot_delete,
+    handle_ignore_remove, handle_index_rebuild, handle_init, handle_key_change, handle_migrate_hash, handle_pack, handle_restore, handle_snapshot_delete,
     handle_snapshot_info, handle_snapshot_list, handle_snapshot_save, handle_snapshot_tag,
-    handle_status, handle_timeline_create, handle_timeline_current, handle_timeline_delete,
-    handle_timeline_list, handle_timeline_rename, handle_timeline_switch,
+    handle_status, handle_storage_move, handle_storage_show, handle_timeline_create, handle_timeline_current, handle_timeline_delete,
+    handle_timeline_list, handle_timeline_rename, handle_timeline_switch, handle_unpack,
+    handle_upgrade,
 };
 use ui::{print_error, print_info};
 
@@ -45,9 +46,19 @@ fn main() -> Result<()> {
     // Parse command line arguments
     let cli = Cli::parse();
 
+    if let Some(secs) = cli.wait {
+        core::lock::set_lock_wait(secs);
+    }
+
     // Handle init command (doesn't require .gam directory)
     if let cli::Commands::Init(init_args) = cli.command {
-        handle_init(init_args.path, init_args.force)?;
+        handle_init(
+            init_args.path,
+            init_args.force,
+            init_args.encrypt,
+            init_args.key_file,
+            init_args.storage,
+        )?;
         return Ok(());
     }
 
@@ -79,11 +90,18 @@ fn main() -> Result<()> {
 
 fn handle_command(gam_dir: PathBuf, command: cli::Commands) -> core::GamResult<()> {
     match command {
-        cli::Commands::Init(args) => handle_init(args.path, args.force),
+        cli::Commands::Init(args) => {
+            handle_init(args.path, args.force, args.encrypt, args.key_file, args.storage)
+        }
 
         cli::Commands::Snapshot(args) => match args.command {
             cli::SnapshotCommands::Save(save_args) => {
-                handle_snapshot_save(&gam_dir, save_args.message, save_args.timeline)
+                handle_snapshot_save(
+                    &gam_dir,
+                    save_args.message,
+                    save_args.timeline,
+                    save_args.rehash,
+                )
             }
             cli::SnapshotCommands::List(list_args) => {
                 handle_snapshot_list(&gam_dir, list_args.all, list_args.timeline)
@@ -119,18 +137,57 @@ fn handle_command(gam_dir: PathBuf, command: cli::Commands) -> core::GamResult<(
         },
 
         cli::Commands::Restore(restore_args) => {
-            handle_restore(&gam_dir, &restore_args.id, restore_args.force)
+            handle_restore(
+                &gam_dir,
+                &restore_args.id,
+                restore_args.force,
+                restore_args.verify,
+            )
         }
 
         cli::Commands::History(history_args) => handle_history(&gam_dir, history_args.all),
 
-        cli::Commands::Status(_args) => handle_status(&gam_dir),
+        cli::Commands::Status(args) => handle_status(&gam_dir, args.verbose, args.rehash, args.find_renames),
 
         cli::Commands::Activity(args) => handle_activity(&gam_dir, args.limit),
 
-        cli::Commands::Diff(args) => handle_diff(&gam_dir, &args.id1, &args.id2),
+        cli::Commands::Diff(args) => handle_diff(
+            &gam_dir,
+            args.id1.as_deref(),
+            args.id2.as_deref(),
+            args.content,
+            args.binary.then_some(args.max_lines),
+            args.find_renames,
+        ),
+
+        cli::Commands::Gc(gc_args) => {
+            handle_gc(&gam_dir, gc_args.aggressive, gc_args.dry_run, gc_args.pack)
+        }
+
+        cli::Commands::Pack(_args) => handle_pack(&gam_dir),
+
+        cli::Commands::Unpack(_args) => handle_unpack(&gam_dir),
+
+        cli::Commands::Index(args) => match args.command {
+            cli::IndexCommands::Rebuild => handle_index_rebuild(&gam_dir),
+        },
+
+        cli::Commands::Upgrade(args) => handle_upgrade(&gam_dir, args.dry_run),
+
+        cli::Commands::MigrateHash(args) => handle_migrate_hash(&gam_dir, &args.algorithm),
+
+        cli::Commands::Key(args) => match args.command {
+            cli::KeyCommands::Change(change_args) => {
+                handle_key_change(&gam_dir, change_args.key_file)
+            }
+        },
 
-        cli::Commands::Gc(gc_args) => handle_gc(&gam_dir, gc_args.aggressive, gc_args.dry_run),
+        cli::Commands::Storage(args) => match args.command {
+            cli::StorageCommands::Show => handle_storage_show(&gam_dir),
+            cli::StorageCommands::Move(move_args) => {
+                handle_storage_move(&gam_dir, move_args.path)
+            }
+        },
 
         cli::Commands::Ignore(args) => match args.command {
             cli::IgnoreCommands::Add(add_args) => handle_ignore_add(&gam_dir, &add_args.pattern),
@@ -147,5 +204,7 @@ fn handle_command(gam_dir: PathBuf, command: cli::Commands) -> core::GamResult<(
         cli::Commands::Config(args) => handle_config(&gam_dir, args.key, args.value, args.list),
 
         cli::Commands::Doctor(args) => handle_doctor(&gam_dir, args.fix),
+
+        cli::Commands::Fsck(args) => handle_fsck(&gam_dir, args.quick),
     }
 }
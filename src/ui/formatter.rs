@@ -2,7 +2,10 @@
 //!
 //! 提供命令行输出格式化功能
 
-use crate::core::types::{Snapshot, Timeline};
+use crate::core::changes::ChangeSet;
+use crate::core::types::{
+    CompressionCodec, EntryKind, FileEntry, Snapshot, SnapshotSummary, Timeline,
+};
 use crate::utils::FileUtils;
 use chrono::{DateTime, Local};
 use std::path::PathBuf;
@@ -27,7 +30,7 @@ impl Formatter {
     }
 
     /// 格式化快照列表表格
-    pub fn format_snapshot_table(snapshots: &[Snapshot], timeline_name: &str) -> String {
+    pub fn format_snapshot_table(snapshots: &[SnapshotSummary], timeline_name: &str) -> String {
         if snapshots.is_empty() {
             return format!("{} 分支暂无快照", timeline_name);
         }
@@ -103,60 +106,228 @@ impl Formatter {
             Self::format_size(snapshot.size)
         ));
 
+        let stored: u64 = snapshot
+            .files
+            .iter()
+            .map(|f| f.compressed_size.unwrap_or(f.size))
+            .sum();
+        output.push_str(&format!(
+            "压缩:       {}{}\n",
+            snapshot.compression,
+            Self::format_ratio(snapshot.size, stored)
+        ));
+
         if let Some(parent) = &snapshot.parent {
             output.push_str(&format!("父快照:     {}\n", Self::short_hash(parent)));
         }
 
+        if !snapshot.content_hash.is_empty() {
+            let state = match snapshot.verify_content_hash() {
+                Some(false) => " (与文件清单不符!)",
+                _ => "",
+            };
+            output.push_str(&format!(
+                "内容哈希:   {}{}\n",
+                Self::short_hash(&snapshot.content_hash),
+                state
+            ));
+        }
+
         output.push('\n');
         output.push_str("包含的文件:\n");
 
         for (i, file) in snapshot.files.iter().enumerate() {
             let path = file.path.to_string_lossy();
-            output.push_str(&format!(
-                "  {}. {}  ({})\n",
-                i + 1,
-                path,
-                Self::format_size(file.size)
-            ));
+            let line = match file.kind {
+                EntryKind::Dir => format!("{}/  (空目录)", path),
+                EntryKind::Symlink => format!(
+                    "{} -> {}  (符号链接)",
+                    path,
+                    file.symlink_target
+                        .as_ref()
+                        .map(|t| t.to_string_lossy())
+                        .unwrap_or_default()
+                ),
+                EntryKind::File => {
+                    let chunks = if file.is_chunked() {
+                        format!(" [{} 块]", file.chunks.len())
+                    } else {
+                        String::new()
+                    };
+                    format!("{}  ({}){}", path, Self::format_size(file.size), chunks)
+                }
+            };
+            output.push_str(&format!("  {}. {}\n", i + 1, line));
         }
 
         output
     }
 
     /// 格式化状态信息
+    ///
+    /// `compression` 为所有对象的原始大小与落盘大小之和，`dedup_savings` 为去重节省的空间
     pub fn format_status(
         timeline: &str,
         snapshot_count: u32,
         game_size: u64,
-        store_size: u64,
+        dedup_savings: u64,
+        codec: CompressionCodec,
+        compression: (u64, u64),
+        unsaved: usize,
     ) -> String {
         let mut output = String::new();
+        let (content_size, stored_size) = compression;
 
         output.push_str("当前状态:\n");
         output.push_str(&format!("  当前时间线: {}\n", timeline));
         output.push_str(&format!("  快照数量:   {}\n", snapshot_count));
+        if unsaved == 0 {
+            output.push_str("  未保存修改: 无\n");
+        } else {
+            output.push_str(&format!("  未保存修改: {} 个文件\n", unsaved));
+        }
         output.push_str(&format!(
             "  存档大小:   {}\n",
             FileUtils::format_size(game_size)
         ));
         output.push_str(&format!(
             "  存储大小:   {}\n",
-            FileUtils::format_size(store_size)
+            FileUtils::format_size(stored_size)
         ));
 
-        if store_size > 0 && game_size > store_size {
-            let saved = game_size - store_size;
-            let percent = (saved as f64 / game_size as f64 * 100.0) as u32;
+        if dedup_savings > 0 {
             output.push_str(&format!(
-                "  节省空间:   {} ({}%)\n",
-                FileUtils::format_size(saved),
-                percent
+                "  去重节省:   {}\n",
+                FileUtils::format_size(dedup_savings)
             ));
         }
 
+        output.push_str(&format!(
+            "  压缩算法:   {}{}\n",
+            codec,
+            Self::format_ratio(content_size, stored_size)
+        ));
+
         output
     }
 
+    /// 格式化工作目录相对 `base` 快照的变更
+    ///
+    /// `verbose` 时列出每个文件的大小和哈希变化
+    pub fn format_changes(base: Option<&Snapshot>, changes: &ChangeSet, verbose: bool) -> String {
+        let mut output = String::new();
+
+        match base {
+            Some(snapshot) => output.push_str(&format!(
+                "相对快照 {} ({}):\n",
+                Self::short_hash(&snapshot.id),
+                snapshot.name
+            )),
+            None => output.push_str("尚无快照，所有文件均未保存:\n"),
+        }
+        if changes.is_empty() {
+            output.push_str("  没有未保存的修改\n");
+            return output;
+        }
+
+        let describe = |file: &FileEntry| -> String {
+            let path = file.path.to_string_lossy();
+            match file.kind {
+                EntryKind::Dir => format!("{}/", path),
+                EntryKind::Symlink => format!(
+                    "{} -> {}",
+                    path,
+                    file.symlink_target
+                        .as_ref()
+                        .map(|t| t.to_string_lossy())
+                        .unwrap_or_default()
+                ),
+                EntryKind::File if verbose => format!(
+                    "{}  ({}, {})",
+                    path,
+                    Self::format_size(file.size),
+                    Self::short_hash(&file.hash)
+                ),
+                EntryKind::File => path.to_string(),
+            }
+        };
+
+        if !changes.added.is_empty() {
+            output.push_str(&format!("  新增 ({}):\n", changes.added.len()));
+            for file in &changes.added {
+                output.push_str(&format!("    + {}\n", describe(file)));
+            }
+        }
+        if !changes.modified.is_empty() {
+            output.push_str(&format!("  修改 ({}):\n", changes.modified.len()));
+            for (old, new) in &changes.modified {
+                if verbose {
+                    output.push_str(&format!(
+                        "    ~ {}  ({} → {}, {} → {})\n",
+                        new.path.to_string_lossy(),
+                        Self::format_size(old.size),
+                        Self::format_size(new.size),
+                        Self::short_hash(&old.hash),
+                        Self::short_hash(&new.hash)
+                    ));
+                } else {
+                    output.push_str(&format!("    ~ {}\n", new.path.to_string_lossy()));
+                }
+            }
+        }
+        if !changes.deleted.is_empty() {
+            output.push_str(&format!("  删除 ({}):\n", changes.deleted.len()));
+            for file in &changes.deleted {
+                output.push_str(&format!("    - {}\n", describe(file)));
+            }
+        }
+        if !changes.renamed.is_empty() {
+            output.push_str(&format!("  重命名 ({}):\n", changes.renamed.len()));
+            for rename in &changes.renamed {
+                let similarity = if rename.similarity < 100 {
+                    format!(" [相似度 {}%]", rename.similarity)
+                } else {
+                    String::new()
+                };
+                output.push_str(&format!(
+                    "    > {} → {}{}\n",
+                    rename.old.path.to_string_lossy(),
+                    rename.new.path.to_string_lossy(),
+                    similarity
+                ));
+            }
+        }
+        if !changes.copied.is_empty() {
+            output.push_str(&format!("  复制 ({}):\n", changes.copied.len()));
+            for (source, file) in &changes.copied {
+                output.push_str(&format!(
+                    "    + {} (复制自 {})\n",
+                    describe(file),
+                    source.path.to_string_lossy()
+                ));
+            }
+        }
+        if verbose {
+            output.push_str(&format!("  未变化: {} 个文件\n", changes.unchanged));
+        }
+
+        output
+    }
+
+    /// 格式化压缩比（原始大小 → 落盘大小）
+    fn format_ratio(size: u64, stored: u64) -> String {
+        if size == 0 || stored >= size {
+            return String::new();
+        }
+
+        format!(
+            " ({} → {}, 压缩率 {:.1}%)",
+            Self::format_size(size),
+            Self::format_size(stored),
+            stored as f64 / size as f64 * 100.0
+        )
+    }
+
     /// 短哈希格式
     pub fn short_hash(full_hash: &str) -> String {
         if full_hash.len() > 8 {
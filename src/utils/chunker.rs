@@ -0,0 +1,156 @@
+use std::io::{self, Read};
+
+/// 默认最小分块大小
+pub const DEFAULT_MIN_CHUNK: usize = 256 * 1024;
+/// 默认平均分块大小（以 2 的幂表示，约 1 MiB）
+pub const DEFAULT_AVG_BITS: u32 = 20;
+/// 默认最大分块大小
+pub const DEFAULT_MAX_CHUNK: usize = 4 * 1024 * 1024;
+
+/// Gear 滚动哈希使用的随机表（splitmix64 生成，保证跨版本稳定）
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// 基于内容的分块器（Gear 滚动哈希）
+///
+/// 分块边界只取决于附近的字节，文件中间插入或修改少量数据时，
+/// 其余分块保持不变，从而可以在快照之间去重
+pub struct Chunker<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    eof: bool,
+    min_size: usize,
+    mask: u64,
+    max_size: usize,
+}
+
+impl<R: Read> Chunker<R> {
+    /// 使用默认参数创建分块器
+    pub fn new(reader: R) -> Self {
+        Self::with_sizes(reader, DEFAULT_MIN_CHUNK, DEFAULT_AVG_BITS, DEFAULT_MAX_CHUNK)
+    }
+
+    /// 使用指定参数创建分块器
+    pub fn with_sizes(reader: R, min_size: usize, avg_bits: u32, max_size: usize) -> Self {
+        // 使用高位作为边界判定，高位受窗口内所有字节影响
+        let mask = ((1u64 << avg_bits) - 1) << (64 - avg_bits);
+        Chunker {
+            reader,
+            buffer: Vec::with_capacity(max_size),
+            eof: false,
+            min_size,
+            mask,
+            max_size,
+        }
+    }
+
+    /// 填充缓冲区直到达到最大分块大小或读到文件末尾
+    fn fill(&mut self) -> io::Result<()> {
+        let mut block = [0u8; 64 * 1024];
+        while !self.eof && self.buffer.len() < self.max_size {
+            let want = (self.max_size - self.buffer.len()).min(block.len());
+            let read = self.reader.read(&mut block[..want])?;
+            if read == 0 {
+                self.eof = true;
+            } else {
+                self.buffer.extend_from_slice(&block[..read]);
+            }
+        }
+        Ok(())
+    }
+
+    /// 在缓冲区中寻找分块边界
+    fn cut_point(&self) -> usize {
+        let len = self.buffer.len().min(self.max_size);
+        if len <= self.min_size {
+            return len;
+        }
+
+        let mut hash = 0u64;
+        for (i, byte) in self.buffer[self.min_size..len].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask == 0 {
+                return self.min_size + i + 1;
+            }
+        }
+        len
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let cut = self.cut_point();
+        let rest = self.buffer.split_off(cut);
+        Some(Ok(std::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        Chunker::with_sizes(data, 1024, 12, 16 * 1024)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_chunks_reassemble() {
+        let data = pseudo_random(200_000, 1);
+        let chunks = chunks(&data);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= 16 * 1024));
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert!(chunks(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_boundaries_survive_insertion() {
+        let data = pseudo_random(200_000, 7);
+        let mut edited = data.clone();
+        edited.splice(50_000..50_000, b"inserted bytes".iter().copied());
+
+        let before = chunks(&data);
+        let after = chunks(&edited);
+        let shared = after.iter().filter(|c| before.contains(c)).count();
+        assert!(shared + 3 >= before.len());
+    }
+}
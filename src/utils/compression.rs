@@ -0,0 +1,97 @@
+use crate::core::types::CompressionCodec;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, BufReader, Read, Write};
+
+/// zstd 压缩级别
+const ZSTD_LEVEL: i32 = 3;
+
+/// 压缩工具
+pub struct CompressionUtils;
+
+impl CompressionUtils {
+    /// 压缩数据流
+    pub fn compress<R: Read, W: Write>(
+        mut reader: R,
+        mut writer: W,
+        codec: CompressionCodec,
+    ) -> io::Result<()> {
+        match codec {
+            CompressionCodec::None => {
+                io::copy(&mut reader, &mut writer)?;
+                writer.flush()
+            }
+            CompressionCodec::Zstd => zstd::stream::copy_encode(reader, writer, ZSTD_LEVEL),
+            CompressionCodec::Gzip => {
+                let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+                io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?.flush()
+            }
+        }
+    }
+
+    /// 为压缩数据创建解压读取器
+    pub fn decoder<'a, R: Read + 'a>(
+        reader: R,
+        codec: CompressionCodec,
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        let reader = BufReader::new(reader);
+        Ok(match codec {
+            CompressionCodec::None => Box::new(reader),
+            CompressionCodec::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            CompressionCodec::Gzip => Box::new(GzDecoder::new(reader)),
+        })
+    }
+
+    /// 根据文件头推断压缩算法（索引缺失时使用）
+    pub fn sniff(header: &[u8]) -> CompressionCodec {
+        if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            CompressionCodec::Zstd
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            CompressionCodec::Gzip
+        } else {
+            CompressionCodec::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(codec: CompressionCodec) {
+        let data = b"save data save data save data save data".repeat(64);
+        let mut compressed = Vec::new();
+        CompressionUtils::compress(&data[..], &mut compressed, codec).unwrap();
+
+        let mut decoded = Vec::new();
+        CompressionUtils::decoder(&compressed[..], codec)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_roundtrip_zstd() {
+        roundtrip(CompressionCodec::Zstd);
+    }
+
+    #[test]
+    fn test_roundtrip_gzip() {
+        roundtrip(CompressionCodec::Gzip);
+    }
+
+    #[test]
+    fn test_roundtrip_none() {
+        roundtrip(CompressionCodec::None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        let mut compressed = Vec::new();
+        CompressionUtils::compress(&b"abc"[..], &mut compressed, CompressionCodec::Zstd).unwrap();
+        assert_eq!(CompressionUtils::sniff(&compressed), CompressionCodec::Zstd);
+        assert_eq!(CompressionUtils::sniff(b"plain"), CompressionCodec::None);
+    }
+}
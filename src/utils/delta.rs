@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::io;
+
+/// 匹配块大小
+const BLOCK: usize = 32;
+/// 滚动哈希乘数
+const PRIME: u64 = 0x100_0000_01b3;
+
+/// 指令：从基础数据复制
+const OP_COPY: u8 = 0;
+/// 指令：插入新数据
+const OP_INSERT: u8 = 1;
+
+/// 二进制增量工具
+///
+/// 增量由一串指令组成：`COPY(offset, len)` 从基础数据复制，`INSERT(len, bytes)` 插入新字节，
+/// 整数使用 LEB128 变长编码
+pub struct DeltaUtils;
+
+impl DeltaUtils {
+    /// 计算从 `base` 生成 `target` 的增量
+    pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pending: Vec<u8> = Vec::new();
+
+        if base.len() < BLOCK || target.len() < BLOCK {
+            Self::emit_insert(&mut out, target);
+            return out;
+        }
+
+        // 基础数据中按块对齐位置建立索引
+        let mut blocks: HashMap<u64, usize> = HashMap::new();
+        for offset in (0..=base.len() - BLOCK).step_by(BLOCK) {
+            blocks
+                .entry(Self::hash_block(&base[offset..offset + BLOCK]))
+                .or_insert(offset);
+        }
+
+        let top = Self::top_power();
+        let mut pos = 0usize;
+        let mut hash = Self::hash_block(&target[..BLOCK]);
+
+        while pos + BLOCK <= target.len() {
+            let matched = blocks
+                .get(&hash)
+                .copied()
+                .filter(|&b| base[b..b + BLOCK] == target[pos..pos + BLOCK]);
+
+            if let Some(mut base_start) = matched {
+                // 向后扩展到待插入数据中
+                let mut start = pos;
+                while start > 0
+                    && base_start > 0
+                    && !pending.is_empty()
+                    && base[base_start - 1] == target[start - 1]
+                {
+                    start -= 1;
+                    base_start -= 1;
+                    pending.pop();
+                }
+
+                // 向前尽量扩展匹配
+                let mut len = pos + BLOCK - start;
+                while start + len < target.len()
+                    && base_start + len < base.len()
+                    && base[base_start + len] == target[start + len]
+                {
+                    len += 1;
+                }
+
+                Self::emit_insert(&mut out, &pending);
+                pending.clear();
+                Self::emit_copy(&mut out, base_start, len);
+
+                pos = start + len;
+                if pos + BLOCK <= target.len() {
+                    hash = Self::hash_block(&target[pos..pos + BLOCK]);
+                }
+                continue;
+            }
+
+            pending.push(target[pos]);
+            if pos + BLOCK < target.len() {
+                hash = hash
+                    .wrapping_sub((target[pos] as u64).wrapping_mul(top))
+                    .wrapping_mul(PRIME)
+                    .wrapping_add(target[pos + BLOCK] as u64);
+            }
+            pos += 1;
+        }
+
+        pending.extend_from_slice(&target[pos..]);
+        Self::emit_insert(&mut out, &pending);
+        out
+    }
+
+    /// 将增量应用到基础数据上
+    pub fn apply(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut pos = 0usize;
+
+        while pos < delta.len() {
+            let op = delta[pos];
+            pos += 1;
+            match op {
+                OP_COPY => {
+                    let offset = Self::read_varint(delta, &mut pos)? as usize;
+                    let len = Self::read_varint(delta, &mut pos)? as usize;
+                    let end = offset
+                        .checked_add(len)
+                        .filter(|&end| end <= base.len())
+                        .ok_or_else(|| Self::corrupt("copy out of range"))?;
+                    out.extend_from_slice(&base[offset..end]);
+                }
+                OP_INSERT => {
+                    let len = Self::read_varint(delta, &mut pos)? as usize;
+                    let end = pos
+                        .checked_add(len)
+                        .filter(|&end| end <= delta.len())
+                        .ok_or_else(|| Self::corrupt("insert out of range"))?;
+                    out.extend_from_slice(&delta[pos..end]);
+                    pos = end;
+                }
+                _ => return Err(Self::corrupt("unknown op")),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// 写入 LEB128 变长整数
+    pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    /// 读取 LEB128 变长整数
+    pub fn read_varint(data: &[u8], pos: &mut usize) -> io::Result<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *data
+                .get(*pos)
+                .ok_or_else(|| Self::corrupt("truncated varint"))?;
+            *pos += 1;
+            if shift >= 64 {
+                return Err(Self::corrupt("varint overflow"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn emit_copy(out: &mut Vec<u8>, offset: usize, len: usize) {
+        out.push(OP_COPY);
+        Self::write_varint(out, offset as u64);
+        Self::write_varint(out, len as u64);
+    }
+
+    fn emit_insert(out: &mut Vec<u8>, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        out.push(OP_INSERT);
+        Self::write_varint(out, data.len() as u64);
+        out.extend_from_slice(data);
+    }
+
+    fn hash_block(block: &[u8]) -> u64 {
+        block
+            .iter()
+            .fold(0u64, |h, b| h.wrapping_mul(PRIME).wrapping_add(*b as u64))
+    }
+
+    /// PRIME^(BLOCK-1)，滚动时用于移除窗口首字节
+    fn top_power() -> u64 {
+        (1..BLOCK).fold(1u64, |p, _| p.wrapping_mul(PRIME))
+    }
+
+    fn corrupt(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("corrupt delta: {}", msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8 ^ (i / 97) as u8).collect()
+    }
+
+    #[test]
+    fn test_small_change_produces_small_delta() {
+        let base = sample(100_000);
+        let mut target = base.clone();
+        target[40_000..40_010].copy_from_slice(b"0123456789");
+        target.splice(70_000..70_000, b"inserted".iter().copied());
+
+        let delta = DeltaUtils::encode(&base, &target);
+        assert!(delta.len() < 200);
+        assert_eq!(DeltaUtils::apply(&base, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn test_unrelated_data_roundtrip() {
+        let base = sample(1000);
+        let target: Vec<u8> = (0..777).map(|i| (i * 7) as u8).collect();
+        let delta = DeltaUtils::encode(&base, &target);
+        assert_eq!(DeltaUtils::apply(&base, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn test_tiny_inputs() {
+        let delta = DeltaUtils::encode(b"abc", b"abd");
+        assert_eq!(DeltaUtils::apply(b"abc", &delta).unwrap(), b"abd");
+        let delta = DeltaUtils::encode(b"abc", b"");
+        assert!(DeltaUtils::apply(b"abc", &delta).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_delta_rejected() {
+        assert!(DeltaUtils::apply(b"abc", &[OP_COPY, 2, 5]).is_err());
+        assert!(DeltaUtils::apply(b"abc", &[9]).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+/// 原子写入使用的临时文件名前缀
+const TEMP_PREFIX: &str = ".gam-tmp-";
+
 /// 文件工具函数
 pub struct FileUtils;
 
@@ -156,6 +159,135 @@ impl FileUtils {
         fs::write(path, content)
     }
 
+    /// 原子写入文件
+    ///
+    /// 先写入同目录下的临时文件并同步到磁盘，再重命名为目标文件。
+    /// 写入中途崩溃或磁盘写满时，目标文件要么保持旧内容，要么是完整的新内容
+    pub fn atomic_write(path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = Self::temp_path(path);
+        let written = fs::File::create(&tmp).and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        Self::persist(&tmp, path)
+    }
+
+    /// 将写好的临时文件同步到磁盘后重命名为目标文件
+    pub fn persist(tmp: &Path, path: &Path) -> io::Result<()> {
+        fs::OpenOptions::new().write(true).open(tmp)?.sync_all()?;
+        fs::rename(tmp, path)?;
+        Self::sync_parent(path)
+    }
+
+    /// 目标文件对应的临时文件路径（同目录，加 `.gam-tmp-` 前缀）
+    pub fn temp_path(path: &Path) -> PathBuf {
+        let mut name = std::ffi::OsString::from(TEMP_PREFIX);
+        name.push(path.file_name().unwrap_or_default());
+        path.with_file_name(name)
+    }
+
+    /// 判断是否为写入过程中遗留的临时文件
+    pub fn is_temp_file(path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(TEMP_PREFIX))
+    }
+
+    /// 同步父目录，确保重命名本身已落盘（仅 Unix 支持打开目录）
+    fn sync_parent(path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::File::open(parent)?.sync_all()?;
+        }
+        #[cfg(not(unix))]
+        let _ = path;
+        Ok(())
+    }
+
+    /// 获取 Unix 权限位（非 Unix 平台为空）
+    pub fn unix_mode(metadata: &fs::Metadata) -> Option<u32> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            None
+        }
+    }
+
+    /// 设置 Unix 权限位（非 Unix 平台忽略）
+    pub fn set_unix_mode(path: &Path, mode: u32) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, mode);
+            Ok(())
+        }
+    }
+
+    /// 设置修改时间（Unix 纪元起的纳秒数）
+    ///
+    /// 标准库无法修改符号链接本身的时间，符号链接保持创建时的时间
+    pub fn set_mtime(path: &Path, mtime: i64) -> io::Result<()> {
+        let offset = std::time::Duration::from_nanos(mtime.unsigned_abs());
+        let time = if mtime >= 0 {
+            std::time::UNIX_EPOCH + offset
+        } else {
+            std::time::UNIX_EPOCH - offset
+        };
+
+        let metadata = fs::symlink_metadata(path)?;
+        #[cfg(unix)]
+        {
+            // 文件所有者通过只读句柄即可设置时间，不受只读权限位影响
+            if metadata.is_file() || metadata.is_dir() {
+                fs::File::open(path)?.set_modified(time)?;
+            }
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        {
+            // 目录无法以普通方式打开，只设置文件的时间
+            if metadata.is_file() {
+                fs::File::options().write(true).open(path)?.set_modified(time)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// 创建指向 `target` 的符号链接 `link`
+    pub fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(target, link)
+        }
+        #[cfg(windows)]
+        {
+            // Windows 区分文件链接和目录链接，按目标当前的类型选择
+            let resolved = link.parent().unwrap_or(Path::new("")).join(target);
+            if resolved.is_dir() {
+                std::os::windows::fs::symlink_dir(target, link)
+            } else {
+                std::os::windows::fs::symlink_file(target, link)
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (target, link);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks are not supported"))
+        }
+    }
+
     /// 检查路径是否存在且为目录
     pub fn ensure_dir_exists(path: &Path) -> io::Result<()> {
         if !path.exists() {
@@ -175,6 +307,28 @@ mod tests {
     use super::*;
     use std::fs;
 
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("save.dat");
+        fs::write(&file, b"data").unwrap();
+
+        FileUtils::set_unix_mode(&file, 0o640).unwrap();
+        FileUtils::set_mtime(&file, 1_600_000_000_123_456_789).unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!(FileUtils::unix_mode(&metadata), Some(0o640));
+        let mtime = metadata.modified().unwrap().duration_since(std::time::UNIX_EPOCH).unwrap();
+        assert_eq!(mtime.as_secs(), 1_600_000_000);
+
+        let link = temp_dir.path().join("link");
+        FileUtils::create_symlink(Path::new("save.dat"), &link).unwrap();
+        FileUtils::set_mtime(&link, 1_500_000_000_000_000_000).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("save.dat"));
+        // 链接目标的时间不受影响
+        assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), metadata.modified().unwrap());
+    }
+
     #[test]
     fn test_file_utils_new() {
         let _utils = FileUtils;
@@ -214,6 +368,33 @@ mod tests {
         assert!(new_dir.exists());
     }
 
+    #[test]
+    fn test_atomic_write_replaces_content() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("index");
+        FileUtils::atomic_write(&path, b"old").unwrap();
+        FileUtils::atomic_write(&path, b"new content").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new content");
+        assert!(!FileUtils::temp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_atomic_write_missing_dir_fails_cleanly() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("missing").join("HEAD");
+        assert!(FileUtils::atomic_write(&path, b"x").is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_temp_path() {
+        let tmp = FileUtils::temp_path(Path::new("refs/timelines/main"));
+        assert_eq!(tmp, PathBuf::from("refs/timelines/.gam-tmp-main"));
+        assert!(FileUtils::is_temp_file(&tmp));
+        assert!(!FileUtils::is_temp_file(Path::new("refs/timelines/main")));
+        assert!(!FileUtils::is_temp_file(Path::new("refs/timelines/backup.tmp")));
+    }
+
     #[test]
     fn test_ensure_dir_exists_already_exists() {
         let temp_dir = tempfile::tempdir().unwrap();
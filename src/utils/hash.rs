@@ -1,31 +1,62 @@
+use crate::core::types::HashAlgorithm;
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
 
-/// SHA-256 哈希工具
+/// 哈希工具
+///
+/// 固定使用 SHA-256（快照 ID、包文件名等）；
+/// 内容寻址使用 [`ContentHasher`]，算法由仓库配置决定
 pub struct HashUtils;
 
-impl HashUtils {
-    /// 计算文件的 SHA-256 哈希
-    pub fn hash_file(path: &Path) -> Result<String, std::io::Error> {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-        let mut hasher = Sha256::new();
-        let mut buffer = [0u8; 8192];
+/// 增量计算内容哈希
+pub enum ContentHasher {
+    Sha256(Sha256),
+    HmacSha256(Box<Hmac<Sha256>>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl ContentHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => ContentHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
 
-        loop {
-            let bytes_read = reader.read(&mut buffer)?;
-            if bytes_read == 0 {
-                break;
+    /// 带密钥的哈希（加密仓库使用，SHA-256 对应 HMAC-SHA256，BLAKE3 使用其密钥模式）
+    pub fn keyed(algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Self {
+        let Some(key) = key else {
+            return Self::new(algorithm);
+        };
+        match algorithm {
+            HashAlgorithm::Sha256 => ContentHasher::HmacSha256(Box::new(
+                <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length"),
+            )),
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::new(blake3::Hasher::new_keyed(key))),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ContentHasher::Sha256(hasher) => hasher.update(data),
+            ContentHasher::HmacSha256(hasher) => hasher.update(data),
+            ContentHasher::Blake3(hasher) => {
+                hasher.update(data);
             }
-            hasher.update(&buffer[..bytes_read]);
         }
+    }
 
-        let result = hasher.finalize();
-        Ok(format!("{:x}", result))
+    /// 结束计算，返回十六进制摘要
+    pub fn finalize(self) -> String {
+        match self {
+            ContentHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            ContentHasher::HmacSha256(hasher) => format!("{:x}", hasher.finalize().into_bytes()),
+            ContentHasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
     }
+}
 
+impl HashUtils {
     /// 计算数据的 SHA-256 哈希
     pub fn hash_data(data: &[u8]) -> String {
         let mut hasher = Sha256::new();
@@ -39,15 +70,37 @@ impl HashUtils {
         Self::hash_data(s.as_bytes())
     }
 
-    /// 短哈希格式（取前 8 位）
-    pub fn short_hash(full_hash: &str) -> &str {
-        &full_hash[..8]
+    /// 计算 Merkle 树根哈希
+    ///
+    /// 叶子与内部节点使用不同的前缀，避免二者混淆；奇数个节点时末尾节点直接提升到上一层。
+    /// 空列表的根为空数据的哈希
+    pub fn merkle_root(leaves: &[String]) -> String {
+        if leaves.is_empty() {
+            return Self::hash_data(b"");
+        }
+
+        let mut level: Vec<String> = leaves
+            .iter()
+            .map(|leaf| Self::hash_data(format!("leaf:{}", leaf).as_bytes()))
+            .collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Self::hash_data(format!("node:{}{}", left, right).as_bytes()),
+                    [single] => single.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level.remove(0)
     }
 
-    /// 验证哈希
-    pub fn verify_hash(data: &[u8], expected: &str) -> bool {
-        let computed = Self::hash_data(data);
-        computed == expected
+    /// 判断字符串是否是内容地址格式（64 位十六进制）
+    ///
+    /// SHA-256 和 BLAKE3 的摘要长度相同，无法据此区分算法，算法以仓库配置为准
+    pub fn is_digest(s: &str) -> bool {
+        s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
     }
 }
 
@@ -55,6 +108,12 @@ impl HashUtils {
 mod tests {
     use super::*;
 
+    fn hash_data_with(data: &[u8], algorithm: HashAlgorithm) -> String {
+        let mut hasher = ContentHasher::new(algorithm);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
     #[test]
     fn test_hash_data_deterministic() {
         let data = b"test data";
@@ -73,41 +132,61 @@ mod tests {
     }
 
     #[test]
-    fn test_hash_data_length() {
-        let data = b"test";
-        let hash = HashUtils::hash_data(data);
-        // SHA256 produces 64 hex characters
-        assert_eq!(hash.len(), 64);
-    }
-
-    #[test]
-    fn test_hash_file_consistent() {
-        let temp_file = tempfile::NamedTempFile::new().unwrap();
-        std::fs::write(temp_file.path(), b"test content").unwrap();
-        let hash1 = HashUtils::hash_file(temp_file.path()).unwrap();
-        let hash2 = HashUtils::hash_file(temp_file.path()).unwrap();
-        assert_eq!(hash1, hash2);
+    fn test_hash_with_algorithm() {
+        let data = b"save data".repeat(1000);
+        assert_eq!(
+            hash_data_with(&data, HashAlgorithm::Sha256),
+            HashUtils::hash_data(&data)
+        );
+
+        let blake = hash_data_with(&data, HashAlgorithm::Blake3);
+        assert_eq!(blake, blake3::hash(&data).to_hex().to_string());
+        assert!(HashUtils::is_digest(&blake));
+        assert_ne!(blake, HashUtils::hash_data(&data));
+        assert!(!HashUtils::is_digest(&blake[..40]));
+        assert!(!HashUtils::is_digest(&"g".repeat(64)));
     }
 
     #[test]
-    fn test_verify_hash_valid() {
-        let data = b"test data";
-        let hash = HashUtils::hash_data(data);
-        assert!(HashUtils::verify_hash(data, &hash));
+    fn test_keyed_hash() {
+        let data = b"save data";
+        let key = [9u8; 32];
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let mut plain = ContentHasher::keyed(algorithm, None);
+            plain.update(data);
+            assert_eq!(plain.finalize(), hash_data_with(data, algorithm));
+
+            let mut keyed = ContentHasher::keyed(algorithm, Some(&key));
+            keyed.update(data);
+            let keyed = keyed.finalize();
+            assert!(HashUtils::is_digest(&keyed));
+            assert_ne!(keyed, hash_data_with(data, algorithm));
+
+            let mut other = ContentHasher::keyed(algorithm, Some(&[1u8; 32]));
+            other.update(data);
+            assert_ne!(other.finalize(), keyed);
+        }
     }
 
     #[test]
-    fn test_verify_hash_invalid() {
-        let data = b"test data";
-        let wrong_hash = "0000000000000000000000000000000000000000000000000000000000000000";
-        assert!(!HashUtils::verify_hash(data, wrong_hash));
+    fn test_merkle_root() {
+        let leaves: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let root = HashUtils::merkle_root(&leaves);
+        assert_eq!(root.len(), 64);
+        assert_eq!(root, HashUtils::merkle_root(&leaves));
+
+        let mut reordered = leaves.clone();
+        reordered.swap(0, 1);
+        assert_ne!(root, HashUtils::merkle_root(&reordered));
+        assert_ne!(root, HashUtils::merkle_root(&leaves[..2]));
+        assert_ne!(HashUtils::merkle_root(&leaves[..1]), leaves[0]);
     }
 
     #[test]
-    fn test_short_hash() {
-        let full_hash = "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890";
-        let short = HashUtils::short_hash(full_hash);
-        assert_eq!(short, "abcdef12");
-        assert_eq!(short.len(), 8);
+    fn test_hash_data_length() {
+        let data = b"test";
+        let hash = HashUtils::hash_data(data);
+        // SHA256 produces 64 hex characters
+        assert_eq!(hash.len(), 64);
     }
 }
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+
+/// 每行显示的字节数
+const ROW_BYTES: usize = 16;
+/// 变化行前后显示的上下文行数
+const CONTEXT_ROWS: usize = 1;
+
+/// 二进制逐字节比较结果
+///
+/// 按相同偏移比较两个字节流（长度不同时多出的部分视为变化），记录变化的字节区间，
+/// 并生成左右对照的十六进制 / ASCII 转储。区间和转储各最多保留 `max_rows` 个，
+/// 区间数和字节数始终完整统计
+#[derive(Debug, Default)]
+pub struct HexDiff {
+    /// 变化的字节区间 `[start, end)`（前 `max_rows` 个）
+    pub ranges: Vec<(u64, u64)>,
+    /// 变化区间的总数
+    pub range_count: u64,
+    /// 最后一个变化区间的结束位置，用于合并相邻的变化
+    last_end: Option<u64>,
+    /// 不同的字节数
+    pub changed_bytes: u64,
+    /// 转储行
+    rows: Vec<String>,
+    /// 转储是否因行数限制被截断
+    truncated: bool,
+}
+
+impl HexDiff {
+    /// 流式比较两个字节流
+    pub fn compare<A: Read, B: Read>(mut old: A, mut new: B, max_rows: usize) -> io::Result<Self> {
+        let mut diff = HexDiff::default();
+        let mut context: VecDeque<String> = VecDeque::new();
+        let mut trailing = 0usize;
+        let mut last_emitted: Option<u64> = None;
+        let mut offset = 0u64;
+        let mut a = [0u8; ROW_BYTES];
+        let mut b = [0u8; ROW_BYTES];
+
+        loop {
+            let a_len = Self::fill(&mut old, &mut a)?;
+            let b_len = Self::fill(&mut new, &mut b)?;
+            if a_len == 0 && b_len == 0 {
+                break;
+            }
+
+            let mut row_changed = false;
+            for i in 0..a_len.max(b_len) {
+                let differs = i >= a_len || i >= b_len || a[i] != b[i];
+                if differs {
+                    let pos = offset + i as u64;
+                    diff.push_byte(pos, max_rows);
+                    row_changed = true;
+                }
+            }
+
+            let line = Self::row(offset, &a[..a_len], &b[..b_len], row_changed);
+            let row_index = offset / ROW_BYTES as u64;
+            if row_changed {
+                // 与上一段转储不相邻时用省略行分隔
+                let first = row_index - context.len() as u64;
+                if last_emitted.is_some_and(|last| first > last + 1) {
+                    diff.push_row("  ...".to_string(), max_rows);
+                }
+                for line in context.drain(..) {
+                    diff.push_row(line, max_rows);
+                }
+                diff.push_row(line, max_rows);
+                last_emitted = Some(row_index);
+                trailing = CONTEXT_ROWS;
+            } else if trailing > 0 {
+                diff.push_row(line, max_rows);
+                last_emitted = Some(row_index);
+                trailing -= 1;
+            } else {
+                context.push_back(line);
+                if context.len() > CONTEXT_ROWS {
+                    context.pop_front();
+                }
+            }
+
+            offset += a_len.max(b_len) as u64;
+            if a_len < ROW_BYTES && b_len < ROW_BYTES {
+                break;
+            }
+        }
+        Ok(diff)
+    }
+
+    /// 输出变化区间列表和转储（`max_rows` 与比较时相同）
+    pub fn render(&self, max_rows: usize) -> String {
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "{} 处变化，共 {} 字节不同:",
+            self.range_count,
+            self.changed_bytes
+        );
+        for (start, end) in &self.ranges {
+            let _ = writeln!(
+                output,
+                "  0x{:08x}..0x{:08x} ({} 字节)",
+                start,
+                end,
+                end - start
+            );
+        }
+        let omitted = self.range_count - self.ranges.len() as u64;
+        if omitted > 0 {
+            let _ = writeln!(output, "  ... 还有 {} 处变化", omitted);
+        }
+
+        let _ = writeln!(output, "左侧为旧版本，右侧为新版本，* 标记有变化的行:");
+        for row in &self.rows {
+            let _ = writeln!(output, "{}", row);
+        }
+        if self.truncated {
+            let _ = writeln!(output, "  ... 输出已达到 {} 行上限", max_rows);
+        }
+        output
+    }
+
+    /// 记录位于 `pos` 的变化字节，与上一个区间相邻时合并
+    fn push_byte(&mut self, pos: u64, max_rows: usize) {
+        self.changed_bytes += 1;
+        if self.last_end == Some(pos) {
+            // 只有最后一个区间仍保留在列表中时才需要更新其结束位置
+            if self.range_count == self.ranges.len() as u64
+                && let Some(range) = self.ranges.last_mut()
+            {
+                range.1 = pos + 1;
+            }
+        } else {
+            self.range_count += 1;
+            if self.ranges.len() < max_rows {
+                self.ranges.push((pos, pos + 1));
+            }
+        }
+        self.last_end = Some(pos + 1);
+    }
+
+    fn push_row(&mut self, line: String, max_rows: usize) {
+        if self.rows.len() < max_rows {
+            self.rows.push(line);
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    /// 读取一行数据，到达末尾时返回实际读取的字节数
+    fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    /// 格式化一行左右对照的转储，变化的行以 `*` 标记
+    fn row(offset: u64, a: &[u8], b: &[u8], changed: bool) -> String {
+        let half = |data: &[u8]| {
+            let mut hex = String::new();
+            let mut ascii = String::new();
+            for i in 0..ROW_BYTES {
+                match data.get(i) {
+                    Some(byte) => {
+                        let _ = write!(hex, "{:02x} ", byte);
+                        ascii.push(if byte.is_ascii_graphic() || *byte == b' ' {
+                            *byte as char
+                        } else {
+                            '.'
+                        });
+                    }
+                    None => hex.push_str("   "),
+                }
+            }
+            format!("{}|{:<16}|", hex, ascii)
+        };
+        format!(
+            "{} {:08x}  {}  {}",
+            if changed { '*' } else { ' ' },
+            offset,
+            half(a),
+            half(b)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges_and_context() {
+        let old = vec![0u8; 64];
+        let mut new = old.clone();
+        new[20] = 1;
+        new[21] = 2;
+        new[40] = b'A';
+        new.push(9);
+
+        let diff = HexDiff::compare(&old[..], &new[..], 100).unwrap();
+        assert_eq!(diff.ranges, vec![(20, 22), (40, 41), (64, 65)]);
+        assert_eq!(diff.changed_bytes, 4);
+        // 第 0 行是上下文，第 1、2、4 行有变化，第 3 行是上下文
+        assert_eq!(diff.rows.len(), 5);
+        assert!(diff.rows[0].starts_with("  00000000"));
+        assert!(diff.rows[2].starts_with("* 00000020") && diff.rows[2].contains("|........A.......|"));
+        assert!(diff.rows[4].starts_with("* 00000040"));
+        assert!(HexDiff::compare(&old[..], &old[..], 100).unwrap().ranges.is_empty());
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let old = vec![0u8; 1024];
+        let new = vec![1u8; 1024];
+        let diff = HexDiff::compare(&old[..], &new[..], 4).unwrap();
+        assert_eq!(diff.ranges, vec![(0, 1024)]);
+        assert_eq!(diff.rows.len(), 4);
+        assert!(diff.render(4).contains("上限"));
+
+        // 区间列表同样只保留前 max_rows 个，其余只计数
+        let alternating: Vec<u8> = (0..1024).map(|i| (i % 2) as u8).collect();
+        let diff = HexDiff::compare(&old[..], &alternating[..], 4).unwrap();
+        assert_eq!(diff.ranges, vec![(1, 2), (3, 4), (5, 6), (7, 8)]);
+        assert_eq!(diff.range_count, 512);
+        assert_eq!(diff.changed_bytes, 512);
+        assert!(diff.render(4).contains("还有 508 处变化"));
+    }
+}
@@ -1,7 +1,19 @@
 //! 工具模块
 
+pub mod chunker;
+pub mod compression;
+pub mod delta;
 pub mod file_utils;
 pub mod hash;
+pub mod hex_diff;
+pub mod structured_diff;
+pub mod text_diff;
 
+pub use chunker::Chunker;
+pub use compression::CompressionUtils;
+pub use delta::DeltaUtils;
 pub use file_utils::FileUtils;
-pub use hash::HashUtils;
+pub use hash::{ContentHasher, HashUtils};
+pub use hex_diff::HexDiff;
+pub use structured_diff::{DocumentFormat, KeyChange, StructuredDiff};
+pub use text_diff::TextDiff;
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 可以按结构比较的文本格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Json,
+    Toml,
+    Ini,
+    Xml,
+}
+
+impl DocumentFormat {
+    /// 根据文件扩展名识别格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(DocumentFormat::Json),
+            "toml" => Some(DocumentFormat::Toml),
+            "ini" => Some(DocumentFormat::Ini),
+            "xml" => Some(DocumentFormat::Xml),
+            _ => None,
+        }
+    }
+}
+
+/// 单个键路径的变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange {
+    /// 新增的键和值
+    Added(String, String),
+    /// 删除的键和原来的值
+    Removed(String, String),
+    /// 值变化的键（旧值，新值）
+    Changed(String, String, String),
+}
+
+/// 结构化比较工具
+///
+/// 把文档展开为「键路径 → 值」的映射后逐键比较，例如 `player.gold: 120 → 450`。
+/// 对象的键用 `.` 连接，数组元素写作 `[i]`，XML 属性写作 `@name`
+pub struct StructuredDiff;
+
+impl StructuredDiff {
+    /// 比较两个文档，任一文档无法解析时返回 `None`
+    pub fn diff(format: DocumentFormat, old: &str, new: &str) -> Option<Vec<KeyChange>> {
+        let old = Self::flatten(format, old)?;
+        let new = Self::flatten(format, new)?;
+
+        let mut changes = Vec::new();
+        for (key, old_value) in &old {
+            match new.get(key) {
+                Some(new_value) if new_value != old_value => changes.push(KeyChange::Changed(
+                    key.clone(),
+                    old_value.clone(),
+                    new_value.clone(),
+                )),
+                Some(_) => {}
+                None => changes.push(KeyChange::Removed(key.clone(), old_value.clone())),
+            }
+        }
+        for (key, new_value) in &new {
+            if !old.contains_key(key) {
+                changes.push(KeyChange::Added(key.clone(), new_value.clone()));
+            }
+        }
+        changes.sort_by(|a, b| Self::key_of(a).cmp(Self::key_of(b)));
+        Some(changes)
+    }
+
+    /// 将文档展开为键路径到值的映射
+    pub fn flatten(format: DocumentFormat, text: &str) -> Option<BTreeMap<String, String>> {
+        let mut out = BTreeMap::new();
+        match format {
+            DocumentFormat::Json => {
+                let value: serde_json::Value = serde_json::from_str(text).ok()?;
+                Self::flatten_json("", &value, &mut out);
+            }
+            DocumentFormat::Toml => {
+                let value: toml::Value = toml::from_str(text).ok()?;
+                Self::flatten_toml("", &value, &mut out);
+            }
+            DocumentFormat::Ini => Self::flatten_ini(text, &mut out),
+            DocumentFormat::Xml => Self::flatten_xml(text, &mut out)?,
+        }
+        Some(out)
+    }
+
+    fn key_of(change: &KeyChange) -> &str {
+        match change {
+            KeyChange::Added(key, _) | KeyChange::Removed(key, _) | KeyChange::Changed(key, _, _) => key,
+        }
+    }
+
+    /// 拼接键路径
+    fn join(prefix: &str, key: &str) -> String {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    }
+
+    fn flatten_json(prefix: &str, value: &serde_json::Value, out: &mut BTreeMap<String, String>) {
+        match value {
+            serde_json::Value::Object(map) if !map.is_empty() => {
+                for (key, child) in map {
+                    Self::flatten_json(&Self::join(prefix, key), child, out);
+                }
+            }
+            serde_json::Value::Array(items) if !items.is_empty() => {
+                for (i, child) in items.iter().enumerate() {
+                    Self::flatten_json(&format!("{}[{}]", prefix, i), child, out);
+                }
+            }
+            _ => {
+                out.insert(prefix.to_string(), value.to_string());
+            }
+        }
+    }
+
+    fn flatten_toml(prefix: &str, value: &toml::Value, out: &mut BTreeMap<String, String>) {
+        match value {
+            toml::Value::Table(table) if !table.is_empty() => {
+                for (key, child) in table {
+                    Self::flatten_toml(&Self::join(prefix, key), child, out);
+                }
+            }
+            toml::Value::Array(items) if !items.is_empty() => {
+                for (i, child) in items.iter().enumerate() {
+                    Self::flatten_toml(&format!("{}[{}]", prefix, i), child, out);
+                }
+            }
+            _ => {
+                out.insert(prefix.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// INI：`[section]` 下的 `key = value` 展开为 `section.key`，`;` 和 `#` 开头的行为注释
+    fn flatten_ini(text: &str, out: &mut BTreeMap<String, String>) {
+        let mut section = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+            } else if let Some((key, value)) = line.split_once('=').or_else(|| line.split_once(':')) {
+                out.insert(Self::join(&section, key.trim()), value.trim().to_string());
+            }
+        }
+    }
+
+    /// XML：元素路径用 `.` 连接，同名的兄弟元素写作 `name[i]`，属性写作 `path@attr`，
+    /// 元素的文本内容作为该路径的值。只支持存档中常见的子集（不处理 DTD 和命名空间）
+    fn flatten_xml(text: &str, out: &mut BTreeMap<String, String>) -> Option<()> {
+        let root = XmlElement::parse(text)?;
+        root.flatten(&root.name, out);
+        Some(())
+    }
+}
+
+/// 解析后的 XML 元素
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    /// 解析文档，返回根元素
+    fn parse(text: &str) -> Option<Self> {
+        let mut stack: Vec<XmlElement> = Vec::new();
+        let mut root = None;
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let Some(lt) = rest.find('<') else {
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&Self::unescape(rest));
+                }
+                break;
+            };
+            if let Some(current) = stack.last_mut() {
+                current.text.push_str(&Self::unescape(&rest[..lt]));
+            }
+            rest = &rest[lt..];
+
+            if let Some(body) = rest.strip_prefix("<!--") {
+                rest = &body[body.find("-->")? + 3..];
+            } else if let Some(body) = rest.strip_prefix("<![CDATA[") {
+                let end = body.find("]]>")?;
+                stack.last_mut()?.text.push_str(&body[..end]);
+                rest = &body[end + 3..];
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                rest = &rest[rest.find('>')? + 1..];
+            } else if let Some(body) = rest.strip_prefix("</") {
+                let end = body.find('>')?;
+                let element = stack.pop()?;
+                if element.name != body[..end].trim() {
+                    return None;
+                }
+                rest = &body[end + 1..];
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            } else {
+                let end = Self::tag_end(rest)?;
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+                let (tag, self_closing) = match tag.strip_suffix('/') {
+                    Some(tag) => (tag, true),
+                    None => (tag, false),
+                };
+                let element = Self::parse_tag(tag)?;
+                if self_closing {
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => root = Some(element),
+                    }
+                } else {
+                    stack.push(element);
+                }
+            }
+        }
+
+        if stack.is_empty() { root } else { None }
+    }
+
+    /// 开始标签结束的位置（跳过引号中的 `>`）
+    fn tag_end(text: &str) -> Option<usize> {
+        let mut quote = None;
+        for (i, c) in text.char_indices() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), _) if c == q => quote = None,
+                (None, '>') => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// 解析开始标签中的元素名和属性
+    fn parse_tag(tag: &str) -> Option<Self> {
+        let tag = tag.trim();
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut element = XmlElement {
+            name: tag[..name_end].to_string(),
+            ..Default::default()
+        };
+        if element.name.is_empty() {
+            return None;
+        }
+
+        let mut rest = tag[name_end..].trim_start();
+        while !rest.is_empty() {
+            let eq = rest.find('=')?;
+            let key = rest[..eq].trim().to_string();
+            let value = rest[eq + 1..].trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let close = value[1..].find(quote)? + 1;
+            element
+                .attributes
+                .push((key, Self::unescape(&value[1..close])));
+            rest = value[close + 1..].trim_start();
+        }
+        Some(element)
+    }
+
+    /// 还原预定义实体
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    fn flatten(&self, path: &str, out: &mut BTreeMap<String, String>) {
+        for (key, value) in &self.attributes {
+            out.insert(format!("{}@{}", path, key), value.clone());
+        }
+        let text = self.text.trim();
+        if !text.is_empty() || (self.children.is_empty() && self.attributes.is_empty()) {
+            out.insert(path.to_string(), text.to_string());
+        }
+
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for child in &self.children {
+            *counts.entry(child.name.as_str()).or_default() += 1;
+        }
+        let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+        for child in &self.children {
+            let index = seen.entry(child.name.as_str()).or_default();
+            let child_path = if counts[child.name.as_str()] > 1 {
+                format!("{}.{}[{}]", path, child.name, index)
+            } else {
+                format!("{}.{}", path, child.name)
+            };
+            *index += 1;
+            child.flatten(&child_path, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(key: &str, old: &str, new: &str) -> KeyChange {
+        KeyChange::Changed(key.to_string(), old.to_string(), new.to_string())
+    }
+
+    #[test]
+    fn test_json_diff() {
+        let old = r#"{"player": {"gold": 120, "name": "Ann", "items": ["sword"]}}"#;
+        let new = r#"{"player": {"gold": 450, "name": "Ann", "items": ["sword", "shield"]}, "day": 3}"#;
+        let changes = StructuredDiff::diff(DocumentFormat::Json, old, new).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                KeyChange::Added("day".to_string(), "3".to_string()),
+                changed("player.gold", "120", "450"),
+                KeyChange::Added("player.items[1]".to_string(), "\"shield\"".to_string()),
+            ]
+        );
+        assert!(StructuredDiff::diff(DocumentFormat::Json, old, "{").is_none());
+    }
+
+    #[test]
+    fn test_toml_and_ini_diff() {
+        let changes =
+            StructuredDiff::diff(DocumentFormat::Toml, "[player]\ngold = 1\n", "[player]\ngold = 2\n")
+                .unwrap();
+        assert_eq!(changes, vec![changed("player.gold", "1", "2")]);
+
+        let old = "; comment\nversion=1\n[Player]\nGold = 10\nName=Ann\n";
+        let new = "version=1\n[Player]\nGold = 12\n";
+        let changes = StructuredDiff::diff(DocumentFormat::Ini, old, new).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                changed("Player.Gold", "10", "12"),
+                KeyChange::Removed("Player.Name".to_string(), "Ann".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xml_flatten() {
+        let xml = r#"<?xml version="1.0"?>
+<!-- save -->
+<save version="2">
+  <player level="5"><gold>120</gold><name>A &amp; B</name></player>
+  <item id="1"/>
+  <item id="2"/>
+  <note><![CDATA[x < y]]></note>
+</save>"#;
+        let flat = StructuredDiff::flatten(DocumentFormat::Xml, xml).unwrap();
+        assert_eq!(flat["save@version"], "2");
+        assert_eq!(flat["save.player@level"], "5");
+        assert_eq!(flat["save.player.gold"], "120");
+        assert_eq!(flat["save.player.name"], "A & B");
+        assert_eq!(flat["save.item[1]@id"], "2");
+        assert_eq!(flat["save.note"], "x < y");
+        assert!(StructuredDiff::flatten(DocumentFormat::Xml, "<a><b></a>").is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::Write;
+
+/// 逐行比较时允许的最大编辑数，超过后整段视为替换（限制内存占用）
+const MAX_EDITS: usize = 2000;
+
+/// 编辑操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// 编辑脚本中的一步，`old` 和 `new` 为操作发生时两侧的行号（从 0 开始）
+#[derive(Debug, Clone, Copy)]
+struct Edit {
+    kind: EditKind,
+    old: usize,
+    new: usize,
+}
+
+/// 文本逐行比较工具
+///
+/// 使用 Myers 差分算法计算最短编辑脚本，输出与 `diff -u` 相同格式的统一差异
+pub struct TextDiff;
+
+impl TextDiff {
+    /// 数据是否可以按文本比较（有效的 UTF-8 且不含 NUL 字节）
+    pub fn as_text(data: &[u8]) -> Option<&str> {
+        if data.contains(&0) {
+            return None;
+        }
+        std::str::from_utf8(data).ok()
+    }
+
+    /// 生成统一差异，内容相同时返回空字符串
+    pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str, context: usize) -> String {
+        let a: Vec<&str> = old.lines().collect();
+        let b: Vec<&str> = new.lines().collect();
+        let edits = Self::diff_lines(&a, &b);
+
+        let mut output = String::new();
+        for range in Self::hunks(&edits, context) {
+            if output.is_empty() {
+                let _ = writeln!(output, "--- {}", old_label);
+                let _ = writeln!(output, "+++ {}", new_label);
+            }
+            let hunk = &edits[range];
+            let old_len = hunk.iter().filter(|e| e.kind != EditKind::Insert).count();
+            let new_len = hunk.iter().filter(|e| e.kind != EditKind::Delete).count();
+            let start = |pos: usize, len: usize| if len == 0 { pos } else { pos + 1 };
+            let _ = writeln!(
+                output,
+                "@@ -{},{} +{},{} @@",
+                start(hunk[0].old, old_len),
+                old_len,
+                start(hunk[0].new, new_len),
+                new_len
+            );
+            for edit in hunk {
+                let _ = match edit.kind {
+                    EditKind::Equal => writeln!(output, " {}", a[edit.old]),
+                    EditKind::Delete => writeln!(output, "-{}", a[edit.old]),
+                    EditKind::Insert => writeln!(output, "+{}", b[edit.new]),
+                };
+            }
+        }
+        output
+    }
+
+    /// 计算从 `a` 到 `b` 的编辑脚本
+    fn diff_lines(a: &[&str], b: &[&str]) -> Vec<Edit> {
+        // 相同的开头和结尾不参与差分计算
+        let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+        let suffix = a[prefix..]
+            .iter()
+            .rev()
+            .zip(b[prefix..].iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+        let mut edits: Vec<Edit> = (0..prefix)
+            .map(|i| Edit {
+                kind: EditKind::Equal,
+                old: i,
+                new: i,
+            })
+            .collect();
+        let middle = Self::myers(mid_a, mid_b).unwrap_or_else(|| {
+            let deletes = (0..mid_a.len()).map(|i| Edit {
+                kind: EditKind::Delete,
+                old: i,
+                new: 0,
+            });
+            let inserts = (0..mid_b.len()).map(|i| Edit {
+                kind: EditKind::Insert,
+                old: mid_a.len(),
+                new: i,
+            });
+            deletes.chain(inserts).collect()
+        });
+        edits.extend(middle.into_iter().map(|e| Edit {
+            old: e.old + prefix,
+            new: e.new + prefix,
+            ..e
+        }));
+        edits.extend((0..suffix).map(|i| Edit {
+            kind: EditKind::Equal,
+            old: a.len() - suffix + i,
+            new: b.len() - suffix + i,
+        }));
+        edits
+    }
+
+    /// Myers 算法，编辑数超过 `MAX_EDITS` 时返回 `None`
+    fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+        let (n, m) = (a.len() as isize, b.len() as isize);
+        let max = (n + m) as usize;
+        let offset = max as isize + 1;
+        let mut v = vec![0isize; 2 * max + 3];
+        // trace[d] 保存第 d 步开始前对角线 -(d+1)..=(d+1) 上的最远位置
+        let mut trace: Vec<Vec<isize>> = Vec::new();
+
+        for d in 0..=max.min(MAX_EDITS) as isize {
+            trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                    v[(offset + k + 1) as usize]
+                } else {
+                    v[(offset + k - 1) as usize] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[(offset + k) as usize] = x;
+                if x >= n && y >= m {
+                    return Some(Self::backtrack(&trace, n, m));
+                }
+            }
+        }
+        None
+    }
+
+    /// 根据各步的最远位置倒推编辑脚本
+    fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        let (mut x, mut y) = (n, m);
+        for (d, v) in trace.iter().enumerate().rev() {
+            let d = d as isize;
+            let at = |k: isize| v[(k + d + 1) as usize];
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = at(prev_k);
+            let prev_y = prev_x - prev_k;
+
+            while x > prev_x && y > prev_y {
+                x -= 1;
+                y -= 1;
+                edits.push(Edit {
+                    kind: EditKind::Equal,
+                    old: x as usize,
+                    new: y as usize,
+                });
+            }
+            if d > 0 {
+                if x == prev_x {
+                    y -= 1;
+                    edits.push(Edit {
+                        kind: EditKind::Insert,
+                        old: x as usize,
+                        new: y as usize,
+                    });
+                } else {
+                    x -= 1;
+                    edits.push(Edit {
+                        kind: EditKind::Delete,
+                        old: x as usize,
+                        new: y as usize,
+                    });
+                }
+            }
+        }
+        edits.reverse();
+        edits
+    }
+
+    /// 将编辑脚本划分为带 `context` 行上下文的差异块（返回脚本中的下标范围）
+    fn hunks(edits: &[Edit], context: usize) -> Vec<std::ops::Range<usize>> {
+        let mut hunks = Vec::new();
+        let mut i = 0;
+        while i < edits.len() {
+            if edits[i].kind == EditKind::Equal {
+                i += 1;
+                continue;
+            }
+
+            // 两处修改之间的相同行不超过 2 * context 时合并为同一块
+            let start = i.saturating_sub(context);
+            let mut last_change = i;
+            let mut j = i;
+            while j < edits.len() {
+                if edits[j].kind != EditKind::Equal {
+                    last_change = j;
+                    j += 1;
+                    continue;
+                }
+                let run_start = j;
+                while j < edits.len() && edits[j].kind == EditKind::Equal {
+                    j += 1;
+                }
+                if j == edits.len() || j - run_start > 2 * context {
+                    break;
+                }
+            }
+            let end = (last_change + 1 + context).min(edits.len());
+            hunks.push(start..end);
+            i = end;
+        }
+        hunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let new = "a\nb\nC\nd\ne\nf\ng\nh\ni\nj\n";
+        let diff = TextDiff::unified(old, new, "a/save.txt", "b/save.txt", 1);
+        assert_eq!(
+            diff,
+            "--- a/save.txt\n+++ b/save.txt\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n@@ -9,1 +9,2 @@\n i\n+j\n"
+        );
+        assert_eq!(TextDiff::unified(old, old, "a", "b", 3), "");
+    }
+
+    #[test]
+    fn test_edit_script_is_minimal() {
+        let a: Vec<&str> = "a b c a b b a".split(' ').collect();
+        let b: Vec<&str> = "c b a b a c".split(' ').collect();
+        let edits = TextDiff::diff_lines(&a, &b);
+        let changes = edits.iter().filter(|e| e.kind != EditKind::Equal).count();
+        assert_eq!(changes, 5);
+
+        let mut rebuilt = Vec::new();
+        for edit in &edits {
+            match edit.kind {
+                EditKind::Equal => rebuilt.push(a[edit.old]),
+                EditKind::Insert => rebuilt.push(b[edit.new]),
+                EditKind::Delete => {}
+            }
+        }
+        assert_eq!(rebuilt, b);
+    }
+
+    #[test]
+    fn test_binary_detection() {
+        assert_eq!(TextDiff::as_text(b"gold = 10\n"), Some("gold = 10\n"));
+        assert!(TextDiff::as_text(b"\x00\x01").is_none());
+        assert!(TextDiff::as_text(&[0xff, 0xfe]).is_none());
+    }
+}